@@ -1,4 +1,4 @@
-use super::{Bool, Expr, Type, TypeInference};
+use super::{Bool, Expr, Literal, Type, TypeInference};
 
 /// Types of binary operation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -19,13 +19,59 @@ pub enum BinOpType {
     BitwiseAnd,
     BitwiseOr,
     Xor,
+    ShiftLeft,
+    ShiftRight,
     Max,
     Min,
-    // Pow,
+    Pow,
+    Atan2,
 }
 
 derive_display!(BinOpType);
 
+impl BinOpType {
+    /// Returns the identity of the operator on given type.
+    ///
+    /// It is used as initial value of mergers, None if the operator
+    /// has no identity.
+    pub fn identity(&self, ty: &Type) -> Option<Literal> {
+        let lit = match (self, ty) {
+            (BinOpType::Add, Type::U8(_)) => Literal::U8(0),
+            (BinOpType::Add, Type::U32(_)) => Literal::U32(0),
+            (BinOpType::Add, Type::I32(_)) => Literal::I32(0),
+            (BinOpType::Add, Type::U64(_)) => Literal::U64(0),
+            (BinOpType::Add, Type::I64(_)) => Literal::I64(0),
+            (BinOpType::Add, Type::F32(_)) => 0f32.into(),
+            (BinOpType::Add, Type::F64(_)) => 0f64.into(),
+            (BinOpType::Multiply, Type::U8(_)) => Literal::U8(1),
+            (BinOpType::Multiply, Type::U32(_)) => Literal::U32(1),
+            (BinOpType::Multiply, Type::I32(_)) => Literal::I32(1),
+            (BinOpType::Multiply, Type::U64(_)) => Literal::U64(1),
+            (BinOpType::Multiply, Type::I64(_)) => Literal::I64(1),
+            (BinOpType::Multiply, Type::F32(_)) => 1f32.into(),
+            (BinOpType::Multiply, Type::F64(_)) => 1f64.into(),
+            (BinOpType::Max, Type::U8(_)) => Literal::U8(u8::MIN),
+            (BinOpType::Max, Type::U32(_)) => Literal::U32(u32::MIN),
+            (BinOpType::Max, Type::I32(_)) => Literal::I32(i32::MIN),
+            (BinOpType::Max, Type::U64(_)) => Literal::U64(u64::MIN),
+            (BinOpType::Max, Type::I64(_)) => Literal::I64(i64::MIN),
+            (BinOpType::Max, Type::F32(_)) => f32::NEG_INFINITY.into(),
+            (BinOpType::Max, Type::F64(_)) => f64::NEG_INFINITY.into(),
+            (BinOpType::Min, Type::U8(_)) => Literal::U8(u8::MAX),
+            (BinOpType::Min, Type::U32(_)) => Literal::U32(u32::MAX),
+            (BinOpType::Min, Type::I32(_)) => Literal::I32(i32::MAX),
+            (BinOpType::Min, Type::U64(_)) => Literal::U64(u64::MAX),
+            (BinOpType::Min, Type::I64(_)) => Literal::I64(i64::MAX),
+            (BinOpType::Min, Type::F32(_)) => f32::INFINITY.into(),
+            (BinOpType::Min, Type::F64(_)) => f64::INFINITY.into(),
+            (BinOpType::LogicalAnd, Type::Bool(_)) => Literal::Bool(true),
+            (BinOpType::LogicalOr, Type::Bool(_)) => Literal::Bool(false),
+            _ => return None,
+        };
+        Some(lit)
+    }
+}
+
 /// A binary operation on two expressions.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BinOp {
@@ -90,6 +136,126 @@ impl BinOp {
             right: Box::new(right),
         }
     }
+
+    pub fn lt(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::LessThan,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn le(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::LessThanOrEqual,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn gt(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::GreaterThan,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn ge(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::GreaterThanOrEqual,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn and(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::LogicalAnd,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn or(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::LogicalOr,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn bitand(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::BitwiseAnd,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn bitor(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::BitwiseOr,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn xor(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::Xor,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn max(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::Max,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn min(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::Min,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn shl(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::ShiftLeft,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn shr(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::ShiftRight,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn pow(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::Pow,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn atan2(left: Expr, right: Expr) -> Self {
+        BinOp {
+            op_ty: BinOpType::Atan2,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
 }
 
 impl TypeInference for BinOp {
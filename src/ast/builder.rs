@@ -257,7 +257,10 @@ impl std::fmt::Display for NewGroupMerger {
     }
 }
 
-/// VecMerger update vector in place by given index and item.
+/// VecMerger update vector of fixed length in place by given index and item.
+///
+/// Slots never merged hold the identity of the operator, and merging
+/// an index out of the length is an error.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct VecMergerType {
     pub(crate) item_ty: Box<Type>,
@@ -272,11 +275,12 @@ impl std::fmt::Display for VecMergerType {
     }
 }
 
-/// A new VecMerger.
+/// A new VecMerger of given length.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct NewVecMerger {
     pub(crate) item_ty: Type,
     pub(crate) op_ty: BinOpType,
+    pub(crate) len: Box<Expr>,
 }
 
 impl TypeInference for NewVecMerger {
@@ -314,6 +318,10 @@ impl Builder for NewVecMerger {
 
 impl std::fmt::Display for NewVecMerger {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "NewVecMerger<{}, {}>", self.item_ty, self.op_ty)
+        write!(
+            f,
+            "NewVecMerger<{}, {}>({})",
+            self.item_ty, self.op_ty, self.len
+        )
     }
 }
@@ -17,12 +17,12 @@ impl std::fmt::Display for DictType {
 
 /// A new Dictionary.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Dict {
+pub struct NewDict {
     pub(crate) key_ty: Type,
     pub(crate) value_ty: Type,
 }
 
-impl TypeInference for Dict {
+impl TypeInference for NewDict {
     fn ty(&self) -> Type {
         Type::Dict(DictType {
             key_ty: Box::new(self.key_ty.clone()),
@@ -31,7 +31,7 @@ impl TypeInference for Dict {
     }
 }
 
-impl std::fmt::Display for Dict {
+impl std::fmt::Display for NewDict {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "NewDict<{}, {}>", self.key_ty, self.value_ty)
     }
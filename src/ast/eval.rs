@@ -8,7 +8,7 @@ pub struct Eval(pub(crate) Box<Expr>);
 
 impl TypeInference for Eval {
     fn ty(&self) -> Type {
-        self.0.ty().eval()
+        self.0.ty().try_eval().unwrap_or(Type::Unknown)
     }
 }
 
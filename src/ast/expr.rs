@@ -27,6 +27,8 @@ pub enum Expr {
     Lookup(Lookup),
     /// Evaluate different branch based on condition.
     IfThenElse(IfThenElse),
+    /// Bind a value to a symbol in scope of the body.
+    Let(Let),
     /// Update a builder in parallel by itearating over data.
     For(For),
     /// Update a builder value, returning a new builder.
@@ -36,7 +38,7 @@ pub enum Expr {
     /// Construct a new vector.
     Vector(Vector),
     /// Construct a new dictionary.
-    Dict(Dict),
+    Dict(NewDict),
     /// Construct a new tuple.
     Tuple(Tuple),
     /// Construct a new appender.
@@ -133,10 +135,16 @@ impl Expr {
                 r |= f.transform_expr(e.as_mut())?;
                 r
             }
+            Expr::Let(Let { value, body, .. }) => {
+                let mut r = f.transform_expr(value.as_mut())?;
+                r |= f.transform_expr(body.as_mut())?;
+                r
+            }
             Expr::For(For {
                 iters,
                 builder,
                 func,
+                env,
             }) => {
                 let mut r = false;
                 for it in iters {
@@ -149,6 +157,9 @@ impl Expr {
                     }
                 }
                 r |= f.transform_expr(builder.as_mut())?;
+                if let Some(env) = env.as_mut() {
+                    r |= f.transform_expr(env.as_mut())?;
+                }
                 r |= f.transform_expr(func.as_mut())?;
                 r
             }
@@ -173,6 +184,7 @@ impl Expr {
                 r
             }
             Expr::Eval(Eval(value)) => f.transform_expr(value.as_mut())?,
+            Expr::NewVecMerger(NewVecMerger { len, .. }) => f.transform_expr(len.as_mut())?,
             // below expressions do not have children
             Expr::Symbol(_)
             | Expr::Literal(_)
@@ -180,8 +192,7 @@ impl Expr {
             | Expr::NewAppender(_)
             | Expr::NewMerger(_)
             | Expr::NewDictMerger(_)
-            | Expr::NewGroupMerger(_)
-            | Expr::NewVecMerger(_) => false,
+            | Expr::NewGroupMerger(_) => false,
         };
         Ok(r)
     }
@@ -219,10 +230,15 @@ impl Expr {
                 f.visit_expr(t.as_ref())?;
                 f.visit_expr(e.as_ref())?;
             }
+            Expr::Let(Let { value, body, .. }) => {
+                f.visit_expr(value.as_ref())?;
+                f.visit_expr(body.as_ref())?;
+            }
             Expr::For(For {
                 iters,
                 builder,
                 func,
+                env,
             }) => {
                 for it in iters {
                     f.visit_expr(it.data.as_ref())?;
@@ -234,6 +250,9 @@ impl Expr {
                     }
                 }
                 f.visit_expr(builder.as_ref())?;
+                if let Some(env) = env.as_ref() {
+                    f.visit_expr(env.as_ref())?;
+                }
                 f.visit_expr(func.as_ref())?;
             }
             Expr::Merge(Merge { builder, value }) => {
@@ -256,6 +275,9 @@ impl Expr {
             Expr::Eval(Eval(value)) => {
                 f.visit_expr(value.as_ref())?;
             }
+            Expr::NewVecMerger(NewVecMerger { len, .. }) => {
+                f.visit_expr(len.as_ref())?;
+            }
             // below expressions do not have children
             Expr::Symbol(_)
             | Expr::Literal(_)
@@ -263,8 +285,7 @@ impl Expr {
             | Expr::NewAppender(_)
             | Expr::NewMerger(_)
             | Expr::NewDictMerger(_)
-            | Expr::NewGroupMerger(_)
-            | Expr::NewVecMerger(_) => (),
+            | Expr::NewGroupMerger(_) => (),
         };
         Ok(())
     }
@@ -283,6 +304,7 @@ impl std::fmt::Display for Expr {
             Expr::Length(len) => len.fmt(f),
             Expr::Lookup(lkp) => lkp.fmt(f),
             Expr::IfThenElse(ite) => ite.fmt(f),
+            Expr::Let(lt) => lt.fmt(f),
             Expr::For(fr) => fr.fmt(f),
             Expr::Merge(mg) => mg.fmt(f),
             Expr::Lambda(lmd) => lmd.fmt(f),
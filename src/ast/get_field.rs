@@ -1,4 +1,4 @@
-use super::{Expr, Type, TypeInference};
+use super::{Expr, TupleType, Type, TypeInference};
 
 /// Get value from tuple by given index.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -9,7 +9,13 @@ pub struct GetField {
 
 impl TypeInference for GetField {
     fn ty(&self) -> Type {
-        self.tuple.as_tuple().unwrap().0[self.index as usize].ty()
+        match self.tuple.ty() {
+            Type::Tuple(TupleType(tys)) => tys
+                .get(self.index as usize)
+                .cloned()
+                .unwrap_or(Type::Unknown),
+            _ => Type::Unknown,
+        }
     }
 }
 
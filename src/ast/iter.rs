@@ -10,7 +10,7 @@ pub struct Iter {
 
 impl TypeInference for Iter {
     fn ty(&self) -> Type {
-        todo!()
+        self.data.ty()
     }
 }
 
@@ -0,0 +1,22 @@
+use super::{Expr, Type, TypeInference};
+use crate::sym::Symbol;
+
+/// Bind the value to a symbol, which is in scope of the body.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Let {
+    pub(crate) sym: Symbol,
+    pub(crate) value: Box<Expr>,
+    pub(crate) body: Box<Expr>,
+}
+
+impl TypeInference for Let {
+    fn ty(&self) -> Type {
+        self.body.ty()
+    }
+}
+
+impl std::fmt::Display for Let {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Let({} = {}, {})", self.sym, self.value, self.body)
+    }
+}
@@ -97,31 +97,37 @@ impl Literal {
             BinOpType::Modulo => try_rem(self, other),
             BinOpType::Equal => try_eq(self, other),
             BinOpType::NotEqual => try_ne(self, other),
+            // comparisons on NaN are false, same as the generated code
             BinOpType::GreaterThan => {
                 let r = try_cmp(self, other)?;
-                Ok(Literal::Bool(r == Ordering::Greater))
+                Ok(Literal::Bool(r == Some(Ordering::Greater)))
             }
             BinOpType::GreaterThanOrEqual => {
                 let r = try_cmp(self, other)?;
                 Ok(Literal::Bool(
-                    r == Ordering::Greater || r == Ordering::Equal,
+                    r == Some(Ordering::Greater) || r == Some(Ordering::Equal),
                 ))
             }
             BinOpType::LessThan => {
                 let r = try_cmp(self, other)?;
-                Ok(Literal::Bool(r == Ordering::Less))
+                Ok(Literal::Bool(r == Some(Ordering::Less)))
             }
             BinOpType::LessThanOrEqual => {
                 let r = try_cmp(self, other)?;
-                Ok(Literal::Bool(r == Ordering::Less || r == Ordering::Equal))
+                Ok(Literal::Bool(
+                    r == Some(Ordering::Less) || r == Some(Ordering::Equal),
+                ))
             }
             BinOpType::LogicalAnd => try_logical_and(self, other),
             BinOpType::LogicalOr => try_logical_or(self, other),
             BinOpType::BitwiseAnd => try_bit_and(self, other),
             BinOpType::BitwiseOr => try_bit_or(self, other),
             BinOpType::Xor => try_bit_xor(self, other),
+            BinOpType::ShiftLeft => try_shl(self, other),
+            BinOpType::ShiftRight => try_shr(self, other),
             BinOpType::Max => try_max(self, other),
             BinOpType::Min => try_min(self, other),
+            BinOpType::Pow | BinOpType::Atan2 => try_binary_math(self, other, op_ty),
         }
     }
 
@@ -134,8 +140,9 @@ impl Literal {
                 Literal::Bool(!r)
             }
             UnaryOpType::Neg => match self {
-                Literal::I32(v) => Literal::I32(-*v),
-                Literal::I64(v) => Literal::I64(-*v),
+                // wraps on minimum like the generated code
+                Literal::I32(v) => Literal::I32(v.wrapping_neg()),
+                Literal::I64(v) => Literal::I64(v.wrapping_neg()),
                 Literal::F32(v) => {
                     let f = f32::from_bits(*v);
                     (-f).into()
@@ -151,7 +158,72 @@ impl Literal {
                     ))
                 }
             },
-            _ => return Err(compile_err!("unimplemented {} operation", op_ty)),
+            UnaryOpType::Abs => match self {
+                Literal::U8(_) | Literal::U32(_) | Literal::U64(_) => self.clone(),
+                // wraps on minimum like the generated code
+                Literal::I32(v) => Literal::I32(v.wrapping_abs()),
+                Literal::I64(v) => Literal::I64(v.wrapping_abs()),
+                Literal::F32(v) => f32::from_bits(*v).abs().into(),
+                Literal::F64(v) => f64::from_bits(*v).abs().into(),
+                _ => {
+                    return Err(compile_err!(
+                        "incompabile type[{}] in Abs operation",
+                        self.ty()
+                    ))
+                }
+            },
+            _ => try_unary_math(self, op_ty)?,
+        };
+        Ok(r)
+    }
+
+    /// Cast the literal to given scalar type.
+    ///
+    /// Integers are truncated or extended, floats are converted
+    /// towards zero, saturating at bounds of the integer type with NaN
+    /// converted to zero, and casting to bool compares with zero.
+    pub fn cast(&self, ty: &Type) -> Result<Self> {
+        enum Num {
+            Int(i128),
+            Float(f64),
+        }
+        macro_rules! cast_to {
+            ($n:expr, $t:ty) => {
+                match $n {
+                    Num::Int(v) => v as $t,
+                    Num::Float(v) => v as $t,
+                }
+            };
+        }
+        let n = match self {
+            Literal::Bool(v) => Num::Int(*v as i128),
+            Literal::U8(v) => Num::Int(*v as i128),
+            Literal::I32(v) => Num::Int(*v as i128),
+            Literal::I64(v) => Num::Int(*v as i128),
+            Literal::U32(v) => Num::Int(*v as i128),
+            Literal::U64(v) => Num::Int(*v as i128),
+            Literal::F32(v) => Num::Float(f32::from_bits(*v) as f64),
+            Literal::F64(v) => Num::Float(f64::from_bits(*v)),
+            Literal::Str(_) => {
+                return Err(compile_err!(
+                    "incompatible type[{}] in cast operation",
+                    self.ty()
+                ))
+            }
+        };
+        let r = match ty {
+            Type::Bool(_) => Literal::Bool(match n {
+                Num::Int(v) => v != 0,
+                Num::Float(v) => v != 0.0,
+            }),
+            Type::U8(_) => Literal::U8(cast_to!(n, u8)),
+            Type::U32(_) => Literal::U32(cast_to!(n, u32)),
+            Type::I32(_) => Literal::I32(cast_to!(n, i32)),
+            Type::U64(_) => Literal::U64(cast_to!(n, u64)),
+            Type::I64(_) => Literal::I64(cast_to!(n, i64)),
+            Type::F32(_) => cast_to!(n, f32).into(),
+            Type::F64(_) => cast_to!(n, f64).into(),
+            _ => return Err(compile_err!("incompatible type[{}] in cast operation", ty)),
         };
         Ok(r)
     }
@@ -176,27 +248,22 @@ impl_from_for_lit_expr!(String, Literal::Str);
 try_eq_for_lit!(try_eq, ==);
 try_eq_for_lit!(try_ne, !=);
 
-fn try_cmp(this: &Literal, that: &Literal) -> Result<Ordering> {
+/// Compare two literals, returns None if either is NaN.
+fn try_cmp(this: &Literal, that: &Literal) -> Result<Option<Ordering>> {
     let r = match (this, that) {
-        (Literal::Bool(v0), Literal::Bool(v1)) => v0.cmp(v1),
-        (Literal::U8(v0), Literal::U8(v1)) => v0.cmp(v1),
-        (Literal::U32(v0), Literal::U32(v1)) => v0.cmp(v1),
-        (Literal::I32(v0), Literal::I32(v1)) => v0.cmp(v1),
-        (Literal::U64(v0), Literal::U64(v1)) => v0.cmp(v1),
-        (Literal::I64(v0), Literal::I64(v1)) => v0.cmp(v1),
+        (Literal::Bool(v0), Literal::Bool(v1)) => v0.partial_cmp(v1),
+        (Literal::U8(v0), Literal::U8(v1)) => v0.partial_cmp(v1),
+        (Literal::U32(v0), Literal::U32(v1)) => v0.partial_cmp(v1),
+        (Literal::I32(v0), Literal::I32(v1)) => v0.partial_cmp(v1),
+        (Literal::U64(v0), Literal::U64(v1)) => v0.partial_cmp(v1),
+        (Literal::I64(v0), Literal::I64(v1)) => v0.partial_cmp(v1),
         (Literal::F32(v0), Literal::F32(v1)) => {
-            let v0 = f32::from_bits(*v0);
-            let v1 = f32::from_bits(*v1);
-            v0.partial_cmp(&v1)
-                .ok_or_else(|| compile_err!("float values[{} and {}] fail cmp operation", v0, v1))?
+            f32::from_bits(*v0).partial_cmp(&f32::from_bits(*v1))
         }
         (Literal::F64(v0), Literal::F64(v1)) => {
-            let v0 = f64::from_bits(*v0);
-            let v1 = f64::from_bits(*v1);
-            v0.partial_cmp(&v1)
-                .ok_or_else(|| compile_err!("float values[{} and {}] fail cmp operation", v0, v1))?
+            f64::from_bits(*v0).partial_cmp(&f64::from_bits(*v1))
         }
-        (Literal::Str(v0), Literal::Str(v1)) => v0.cmp(v1),
+        (Literal::Str(v0), Literal::Str(v1)) => v0.partial_cmp(v1),
         (s, o) => {
             return Err(compile_err!(
                 "incompatible types [{} and {}] in cmp operation",
@@ -214,16 +281,111 @@ try_maxmin_for_lit!(try_min, <);
 try_logical_for_lit!(try_logical_and, &&);
 try_logical_for_lit!(try_logical_or, ||);
 
-try_arith_for_num_lit!(try_add, +);
-try_arith_for_num_lit!(try_sub, -);
-try_arith_for_num_lit!(try_mul, *);
-try_arith_for_num_lit!(try_div, /);
-try_arith_for_num_lit!(try_rem, %);
+try_arith_for_num_lit!(try_add, wrapping_add, +);
+try_arith_for_num_lit!(try_sub, wrapping_sub, -);
+try_arith_for_num_lit!(try_mul, wrapping_mul, *);
+try_div_for_num_lit!(try_div, wrapping_div, /);
+try_div_for_num_lit!(try_rem, wrapping_rem, %);
 
 try_bitop_for_num_lit!(try_bit_and, &);
 try_bitop_for_num_lit!(try_bit_or, |);
 try_bitop_for_num_lit!(try_bit_xor, ^);
 
+try_shift_for_num_lit!(try_shl, checked_shl);
+try_shift_for_num_lit!(try_shr, checked_shr);
+
+/// Apply math function on float literal.
+fn try_unary_math(lit: &Literal, op_ty: &UnaryOpType) -> Result<Literal> {
+    macro_rules! math {
+        ($v:expr, $fty:ty) => {
+            match op_ty {
+                UnaryOpType::Exp => $v.exp(),
+                UnaryOpType::Log => $v.ln(),
+                UnaryOpType::Sqrt => $v.sqrt(),
+                UnaryOpType::Sin => $v.sin(),
+                UnaryOpType::Cos => $v.cos(),
+                UnaryOpType::Tan => $v.tan(),
+                UnaryOpType::ASin => $v.asin(),
+                UnaryOpType::ACos => $v.acos(),
+                UnaryOpType::ATan => $v.atan(),
+                UnaryOpType::Sinh => $v.sinh(),
+                UnaryOpType::Cosh => $v.cosh(),
+                UnaryOpType::Tanh => $v.tanh(),
+                UnaryOpType::Erf => erf($v as f64) as $fty,
+                UnaryOpType::Floor => $v.floor(),
+                UnaryOpType::Ceil => $v.ceil(),
+                UnaryOpType::Round => $v.round(),
+                UnaryOpType::Not | UnaryOpType::Neg | UnaryOpType::Abs => unreachable!(),
+            }
+        };
+    }
+    let r = match lit {
+        Literal::F32(v) => math!(f32::from_bits(*v), f32).into(),
+        Literal::F64(v) => math!(f64::from_bits(*v), f64).into(),
+        _ => {
+            return Err(compile_err!(
+                "incompatible type[{}] in {} operation, only floats are supported",
+                lit.ty(),
+                op_ty
+            ))
+        }
+    };
+    Ok(r)
+}
+
+/// Apply binary math function on float literals.
+fn try_binary_math(this: &Literal, that: &Literal, op_ty: &BinOpType) -> Result<Literal> {
+    macro_rules! math {
+        ($v0:expr, $v1:expr) => {
+            match op_ty {
+                BinOpType::Pow => $v0.powf($v1),
+                BinOpType::Atan2 => $v0.atan2($v1),
+                _ => unreachable!(),
+            }
+        };
+    }
+    let r = match (this, that) {
+        (Literal::F32(v0), Literal::F32(v1)) => {
+            math!(f32::from_bits(*v0), f32::from_bits(*v1)).into()
+        }
+        (Literal::F64(v0), Literal::F64(v1)) => {
+            math!(f64::from_bits(*v0), f64::from_bits(*v1)).into()
+        }
+        (s, o) => {
+            return Err(compile_err!(
+                "incompatible types [{} and {}] in {} operation, only floats are supported",
+                s.ty(),
+                o.ty(),
+                op_ty
+            ))
+        }
+    };
+    Ok(r)
+}
+
+/// Error function, which is missing in std.
+///
+/// Computed by the series `2/sqrt(pi) * exp(-x^2) * sum(2^n * x^(2n+1) / (2n+1)!!)`,
+/// whose terms are all positive, so there is no cancellation.
+fn erf(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    if x.abs() >= 6.0 {
+        return x.signum();
+    }
+    let x2 = x * x;
+    let mut term = x;
+    let mut sum = x;
+    let mut n = 0.0;
+    while term.abs() > sum.abs() * f64::EPSILON {
+        n += 1.0;
+        term *= 2.0 * x2 / (2.0 * n + 1.0);
+        sum += term;
+    }
+    2.0 / std::f64::consts::PI.sqrt() * (-x2).exp() * sum
+}
+
 /// special conversion between f32 and Literal
 impl From<f32> for Literal {
     fn from(src: f32) -> Self {
@@ -1,4 +1,4 @@
-use super::{Expr, Type, TypeInference};
+use super::{DictType, Expr, Type, TypeInference};
 
 /// Lookup value in dictionary.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -9,7 +9,10 @@ pub struct Lookup {
 
 impl TypeInference for Lookup {
     fn ty(&self) -> Type {
-        todo!()
+        match self.dict.ty() {
+            Type::Dict(DictType { value_ty, .. }) => *value_ty,
+            _ => Type::Unknown,
+        }
     }
 }
 
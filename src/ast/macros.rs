@@ -19,14 +19,224 @@ macro_rules! impl_num_var {
             }
 
             /// Equality check on two vars and returns a bool var
-            pub fn eq(self, other: Self) -> Var<bool> {
+            pub fn eq(self, other: Self) -> Var<Bool> {
                 Var::new(Expr::BinOp(BinOp::eq(self.expr, other.expr)))
             }
 
             /// Non-equality check on two vars, and returns a bool var
-            pub fn ne(self, other: Self) -> Var<bool> {
+            pub fn ne(self, other: Self) -> Var<Bool> {
                 Var::new(Expr::BinOp(BinOp::ne(self.expr, other.expr)))
             }
+
+            /// Less than check on two vars, and returns a bool var
+            pub fn lt(self, other: Self) -> Var<Bool> {
+                Var::new(Expr::BinOp(BinOp::lt(self.expr, other.expr)))
+            }
+
+            /// Less than or equal check on two vars, and returns a bool var
+            pub fn le(self, other: Self) -> Var<Bool> {
+                Var::new(Expr::BinOp(BinOp::le(self.expr, other.expr)))
+            }
+
+            /// Greater than check on two vars, and returns a bool var
+            pub fn gt(self, other: Self) -> Var<Bool> {
+                Var::new(Expr::BinOp(BinOp::gt(self.expr, other.expr)))
+            }
+
+            /// Greater than or equal check on two vars, and returns a bool var
+            pub fn ge(self, other: Self) -> Var<Bool> {
+                Var::new(Expr::BinOp(BinOp::ge(self.expr, other.expr)))
+            }
+
+            /// Returns the smaller of two vars
+            pub fn min(self, other: Self) -> Self {
+                Var::new(Expr::BinOp(BinOp::min(self.expr, other.expr)))
+            }
+
+            /// Returns the larger of two vars
+            pub fn max(self, other: Self) -> Self {
+                Var::new(Expr::BinOp(BinOp::max(self.expr, other.expr)))
+            }
+        }
+    };
+}
+
+macro_rules! impl_math_for_var_float {
+    ($ty:ty, $exprf:ident) => {
+        impl Var<$ty> {
+            fn unary_math(self, op_ty: UnaryOpType) -> Self {
+                match self.expr {
+                    Expr::Literal(v) => Var::new(Expr::Literal(v.apply_unary_op(&op_ty).unwrap())),
+                    other => Var::$exprf(Expr::UnaryOp(UnaryOp {
+                        op_ty,
+                        value: Box::new(other),
+                    })),
+                }
+            }
+
+            fn binary_math(self, other: Self, op_ty: BinOpType) -> Self {
+                match (self.expr, other.expr) {
+                    (Expr::Literal(v0), Expr::Literal(v1)) => {
+                        Var::new(Expr::Literal(v0.apply_bin_op(&v1, &op_ty).unwrap()))
+                    }
+                    (v0, v1) => Var::$exprf(Expr::BinOp(BinOp {
+                        op_ty,
+                        left: Box::new(v0),
+                        right: Box::new(v1),
+                    })),
+                }
+            }
+
+            /// Returns the var raised to the power of other
+            pub fn pow(self, other: Self) -> Self {
+                self.binary_math(other, BinOpType::Pow)
+            }
+
+            /// Returns four quadrant arctangent of the var and other in radians
+            pub fn atan2(self, other: Self) -> Self {
+                self.binary_math(other, BinOpType::Atan2)
+            }
+
+            /// Returns absolute value of the var
+            pub fn abs(self) -> Self {
+                self.unary_math(UnaryOpType::Abs)
+            }
+
+            /// Returns the largest integer less than or equal to the var
+            pub fn floor(self) -> Self {
+                self.unary_math(UnaryOpType::Floor)
+            }
+
+            /// Returns the smallest integer greater than or equal to the var
+            pub fn ceil(self) -> Self {
+                self.unary_math(UnaryOpType::Ceil)
+            }
+
+            /// Returns the nearest integer to the var, half way cases away from zero
+            pub fn round(self) -> Self {
+                self.unary_math(UnaryOpType::Round)
+            }
+
+            /// Returns e raised to the power of the var
+            pub fn exp(self) -> Self {
+                self.unary_math(UnaryOpType::Exp)
+            }
+
+            /// Returns natural logarithm of the var
+            pub fn log(self) -> Self {
+                self.unary_math(UnaryOpType::Log)
+            }
+
+            /// Returns square root of the var
+            pub fn sqrt(self) -> Self {
+                self.unary_math(UnaryOpType::Sqrt)
+            }
+
+            /// Returns sine of the var in radians
+            pub fn sin(self) -> Self {
+                self.unary_math(UnaryOpType::Sin)
+            }
+
+            /// Returns cosine of the var in radians
+            pub fn cos(self) -> Self {
+                self.unary_math(UnaryOpType::Cos)
+            }
+
+            /// Returns tangent of the var in radians
+            pub fn tan(self) -> Self {
+                self.unary_math(UnaryOpType::Tan)
+            }
+
+            /// Returns arcsine of the var in radians
+            pub fn asin(self) -> Self {
+                self.unary_math(UnaryOpType::ASin)
+            }
+
+            /// Returns arccosine of the var in radians
+            pub fn acos(self) -> Self {
+                self.unary_math(UnaryOpType::ACos)
+            }
+
+            /// Returns arctangent of the var in radians
+            pub fn atan(self) -> Self {
+                self.unary_math(UnaryOpType::ATan)
+            }
+
+            /// Returns hyperbolic sine of the var
+            pub fn sinh(self) -> Self {
+                self.unary_math(UnaryOpType::Sinh)
+            }
+
+            /// Returns hyperbolic cosine of the var
+            pub fn cosh(self) -> Self {
+                self.unary_math(UnaryOpType::Cosh)
+            }
+
+            /// Returns hyperbolic tangent of the var
+            pub fn tanh(self) -> Self {
+                self.unary_math(UnaryOpType::Tanh)
+            }
+
+            /// Returns error function of the var
+            pub fn erf(self) -> Self {
+                self.unary_math(UnaryOpType::Erf)
+            }
+        }
+    };
+}
+
+macro_rules! impl_static_type_for_scalar {
+    ($ty:ident, $path:path) => {
+        impl StaticType for $ty {
+            fn static_ty() -> Type {
+                $path($ty)
+            }
+        }
+    };
+}
+
+macro_rules! impl_static_type_for_tuple {
+    ($($t:ident : $i:tt),+) => {
+        impl<$($t: StaticType),+> StaticType for ($($t,)+) {
+            fn static_ty() -> Type {
+                Type::Tuple(TupleType(vec![$($t::static_ty()),+]))
+            }
+        }
+
+        impl<$($t: IntoVar),+> IntoVar for ($($t,)+) {
+            type Ty = ($($t::Ty,)+);
+
+            fn into_var(self) -> Var<Self::Ty> {
+                Var::new(Expr::Tuple(Tuple(vec![$(self.$i.into_var().expr),+])))
+            }
+        }
+
+        impl_tuple_fields!(($($t),+); $($t : $i),+);
+    };
+}
+
+macro_rules! impl_tuple_fields {
+    ($all:tt; $($t:ident : $i:tt),+) => {
+        $(impl_tuple_field!($all, $t, $i);)+
+    };
+}
+
+macro_rules! impl_tuple_field {
+    (($($all:ident),+), $t:ident, $i:tt) => {
+        impl<$($all),+> TupleField<$i> for ($($all,)+) {
+            type Output = $t;
+        }
+    };
+}
+
+macro_rules! impl_into_var_for_lit {
+    ($rty:ty, $ty:ty, $litf:ident) => {
+        impl IntoVar for $rty {
+            type Ty = $ty;
+
+            fn into_var(self) -> Var<$ty> {
+                Var::$litf(self)
+            }
         }
     };
 }
@@ -71,8 +281,9 @@ macro_rules! try_eq_for_lit {
                 (Literal::I32(v0), Literal::I32(v1)) => v0 $op v1,
                 (Literal::U64(v0), Literal::U64(v1)) => v0 $op v1,
                 (Literal::I64(v0), Literal::I64(v1)) => v0 $op v1,
-                (Literal::F32(v0), Literal::F32(v1)) => v0 $op v1,
-                (Literal::F64(v0), Literal::F64(v1)) => v0 $op v1,
+                // compared as floats rather than bits, NaN is never equal
+                (Literal::F32(v0), Literal::F32(v1)) => f32::from_bits(*v0) $op f32::from_bits(*v1),
+                (Literal::F64(v0), Literal::F64(v1)) => f64::from_bits(*v0) $op f64::from_bits(*v1),
                 (Literal::Str(v0), Literal::Str(v1)) => v0 $op v1,
                 (s, o) => return Err(compile_err!("incompatible types [{} and {}] in eq/ne operation", s.ty(), o.ty())),
             };
@@ -121,14 +332,51 @@ macro_rules! try_logical_for_lit {
 }
 
 macro_rules! try_arith_for_num_lit {
-    ($f:ident, $op:tt) => {
+    ($f:ident, $intf:ident, $op:tt) => {
+        fn $f(this: &Literal, that: &Literal) -> Result<Literal> {
+            let r = match (this, that) {
+                // integers wrap on overflow like the generated code
+                (Literal::U8(left), Literal::U8(right)) => Literal::U8(left.$intf(*right)),
+                (Literal::U32(left), Literal::U32(right)) => Literal::U32(left.$intf(*right)),
+                (Literal::I32(left), Literal::I32(right)) => Literal::I32(left.$intf(*right)),
+                (Literal::U64(left), Literal::U64(right)) => Literal::U64(left.$intf(*right)),
+                (Literal::I64(left), Literal::I64(right)) => Literal::I64(left.$intf(*right)),
+                (Literal::F32(left), Literal::F32(right)) => {
+                    let left = f32::from_bits(*left);
+                    let right = f32::from_bits(*right);
+                    let r = left $op right;
+                    Literal::F32(f32::to_bits(r))
+                }
+                (Literal::F64(left), Literal::F64(right)) => {
+                    let left = f64::from_bits(*left);
+                    let right = f64::from_bits(*right);
+                    let r = left $op right;
+                    Literal::F64(f64::to_bits(r))
+                }
+                (s, o) => return Err(compile_err!("incompatible types [{}, {}] for {} operation", s.ty(), o.ty(), stringify!($f))),
+            };
+            Ok(r)
+        }
+    }
+}
+
+macro_rules! try_div_for_num_lit {
+    ($f:ident, $intf:ident, $op:tt) => {
         fn $f(this: &Literal, that: &Literal) -> Result<Literal> {
             let r = match (this, that) {
-                (Literal::U8(left), Literal::U8(right)) => Literal::U8(left $op right),
-                (Literal::U32(left), Literal::U32(right)) => Literal::U32(left $op right),
-                (Literal::I32(left), Literal::I32(right)) => Literal::I32(left $op right),
-                (Literal::U64(left), Literal::U64(right)) => Literal::U64(left $op right),
-                (Literal::I64(left), Literal::I64(right)) => Literal::I64(left $op right),
+                (Literal::U8(_), Literal::U8(0))
+                | (Literal::U32(_), Literal::U32(0))
+                | (Literal::I32(_), Literal::I32(0))
+                | (Literal::U64(_), Literal::U64(0))
+                | (Literal::I64(_), Literal::I64(0)) => {
+                    return Err(runtime_err!("division by zero in {} operation", stringify!($f)))
+                }
+                // minimum divided by -1 wraps like the generated code
+                (Literal::U8(left), Literal::U8(right)) => Literal::U8(left.$intf(*right)),
+                (Literal::U32(left), Literal::U32(right)) => Literal::U32(left.$intf(*right)),
+                (Literal::I32(left), Literal::I32(right)) => Literal::I32(left.$intf(*right)),
+                (Literal::U64(left), Literal::U64(right)) => Literal::U64(left.$intf(*right)),
+                (Literal::I64(left), Literal::I64(right)) => Literal::I64(left.$intf(*right)),
                 (Literal::F32(left), Literal::F32(right)) => {
                     let left = f32::from_bits(*left);
                     let right = f32::from_bits(*right);
@@ -141,7 +389,7 @@ macro_rules! try_arith_for_num_lit {
                     let r = left $op right;
                     Literal::F64(f64::to_bits(r))
                 }
-                (s, o) => return Err(compile_err!("incompatible types [{}, {}] for {} operation", s.ty(), o.ty(), stringify!($ty))),
+                (s, o) => return Err(compile_err!("incompatible types [{}, {}] for {} operation", s.ty(), o.ty(), stringify!($f))),
             };
             Ok(r)
         }
@@ -164,58 +412,106 @@ macro_rules! try_bitop_for_num_lit {
     }
 }
 
-macro_rules! impl_arith_for_var_num {
-    ($opty:ty, $oplty:ty, $oprty:ty, $vty:ty, $builtinty:ty, $opf:ident, $op:tt, $asf:ident, $litf:ident, $exprf:ident, $binopf:path) => {
-        impl $opty for $vty {
+macro_rules! try_shift_for_num_lit {
+    ($f:ident, $shf:ident) => {
+        fn $f(this: &Literal, that: &Literal) -> Result<Literal> {
+            let r = match (this, that) {
+                (Literal::U8(v0), Literal::U8(v1)) => v0.$shf(*v1 as u32).map(Literal::U8),
+                (Literal::U32(v0), Literal::U32(v1)) => v0.$shf(*v1).map(Literal::U32),
+                (Literal::I32(v0), Literal::I32(v1)) if *v1 >= 0 => {
+                    v0.$shf(*v1 as u32).map(Literal::I32)
+                }
+                (Literal::U64(v0), Literal::U64(v1)) if *v1 <= u32::MAX as u64 => {
+                    v0.$shf(*v1 as u32).map(Literal::U64)
+                }
+                (Literal::I64(v0), Literal::I64(v1)) if *v1 >= 0 && *v1 <= u32::MAX as i64 => {
+                    v0.$shf(*v1 as u32).map(Literal::I64)
+                }
+                (Literal::I32(_), Literal::I32(_))
+                | (Literal::U64(_), Literal::U64(_))
+                | (Literal::I64(_), Literal::I64(_)) => None,
+                (s, o) => {
+                    return Err(compile_err!(
+                        "incompatible types [{} and {}] in {} operation",
+                        s.ty(),
+                        o.ty(),
+                        stringify!($f)
+                    ))
+                }
+            };
+            r.ok_or_else(|| {
+                runtime_err!(
+                    "shift amount {} out of range in {} operation",
+                    that,
+                    stringify!($f)
+                )
+            })
+        }
+    };
+}
+
+macro_rules! impl_bin_op_for_var {
+    ($opty:ident, $opf:ident, $binopf:path, $ty:ty, $rty:ty, $litf:ident, $exprf:ident) => {
+        impl $opty for Var<$ty> {
             type Output = Self;
 
+            // literals are folded the same as the interpreter, but an
+            // operation that fails, e.g. division by zero, is left
+            // unfolded to fail at run time
             fn $opf(self, other: Self) -> Self {
-                match (self.expr, other.expr) {
-                    (Expr::Literal(v0), Expr::Literal(v1)) => {
-                        let v = v0.$asf().unwrap() $op v1.$asf().unwrap();
-                        Var::$litf(v)
-                    }
-                    (v0, v1) => {
-                        Var::$exprf(Expr::BinOp($binopf(v0, v1)))
+                let bo = $binopf(self.expr, other.expr);
+                if let (Expr::Literal(v0), Expr::Literal(v1)) =
+                    (bo.left.as_ref(), bo.right.as_ref())
+                {
+                    if let Ok(v) = v0.apply_bin_op(v1, &bo.op_ty) {
+                        return Var::new(Expr::Literal(v));
                     }
                 }
+                Var::$exprf(Expr::BinOp(bo))
             }
         }
 
-        impl $oplty for $builtinty {
-            type Output = $vty;
+        impl $opty<Var<$ty>> for $rty {
+            type Output = Var<$ty>;
 
-            fn $opf(self, other: $vty) -> $vty {
-                match other.expr {
-                    Expr::Literal(v1) => {
-                        let v = self $op v1.$asf().unwrap();
-                        Var::$litf(v)
-                    }
-                    v1 => {
-                        let v0 = Var::$litf(self);
-                        Var::$exprf(Expr::BinOp($binopf(v0.expr, v1)))
-                    }
-                }
+            fn $opf(self, other: Var<$ty>) -> Var<$ty> {
+                <Var<$ty> as $opty>::$opf(Var::$litf(self), other)
             }
         }
 
-        impl $oprty for $vty {
+        impl $opty<$rty> for Var<$ty> {
             type Output = Self;
 
-            fn $opf(self, other: $builtinty) -> Self {
-                match self.expr {
-                    Expr::Literal(v0) => {
-                        let v = v0.$asf().unwrap() $op other;
-                        Var::$litf(v)
-                    }
-                    v0 => {
-                        let v1 = other.into();
-                        Var::$exprf(Expr::BinOp($binopf(v0, Expr::Literal(v1))))
-                    }
-                }
+            fn $opf(self, other: $rty) -> Self {
+                <Var<$ty> as $opty>::$opf(self, Var::$litf(other))
             }
         }
-    }
+    };
+}
+
+macro_rules! impl_arith_for_var_num {
+    ($ty:ty, $rty:ty, $litf:ident, $exprf:ident) => {
+        impl_bin_op_for_var!(Add, add, BinOp::add, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(Sub, sub, BinOp::sub, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(Mul, mul, BinOp::mul, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(Div, div, BinOp::div, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(Rem, rem, BinOp::rem, $ty, $rty, $litf, $exprf);
+    };
+}
+
+macro_rules! impl_bitop_for_var_int {
+    ($ty:ty, $rty:ty, $litf:ident, $exprf:ident) => {
+        impl_bin_op_for_var!(BitAnd, bitand, BinOp::bitand, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(BitOr, bitor, BinOp::bitor, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(BitXor, bitxor, BinOp::xor, $ty, $rty, $litf, $exprf);
+    };
+}
+
+macro_rules! impl_shift_for_var_int {
+    ($ty:ty, $rty:ty, $litf:ident, $exprf:ident) => {
+        impl_bin_op_for_var!(Shl, shl, BinOp::shl, $ty, $rty, $litf, $exprf);
+        impl_bin_op_for_var!(Shr, shr, BinOp::shr, $ty, $rty, $litf, $exprf);
+    };
 }
 
 macro_rules! derive_display {
@@ -0,0 +1,84 @@
+use super::*;
+use std::marker::PhantomData;
+
+/// Type known at compile time of the staging program.
+///
+/// It is implemented by scalar types, `Vec`, `Dict` and tuples of them,
+/// so element types of vars are checked by Rust compiler.
+pub trait StaticType {
+    fn static_ty() -> Type;
+}
+
+impl_static_type_for_scalar!(Bool, Type::Bool);
+impl_static_type_for_scalar!(U8, Type::U8);
+impl_static_type_for_scalar!(U32, Type::U32);
+impl_static_type_for_scalar!(I32, Type::I32);
+impl_static_type_for_scalar!(U64, Type::U64);
+impl_static_type_for_scalar!(I64, Type::I64);
+impl_static_type_for_scalar!(F32, Type::F32);
+impl_static_type_for_scalar!(F64, Type::F64);
+impl_static_type_for_scalar!(Str, Type::Str);
+
+impl<T: StaticType> StaticType for Vec<T> {
+    fn static_ty() -> Type {
+        Type::Vector(VectorType {
+            item_ty: Box::new(T::static_ty()),
+        })
+    }
+}
+
+/// Dict mapping keys of type K to values of type V.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dict<K, V>(PhantomData<(K, V)>);
+
+impl<K: StaticType, V: StaticType> StaticType for Dict<K, V> {
+    fn static_ty() -> Type {
+        Type::Dict(DictType {
+            key_ty: Box::new(K::static_ty()),
+            value_ty: Box::new(V::static_ty()),
+        })
+    }
+}
+
+/// Field at index I of a tuple type.
+pub trait TupleField<const I: usize> {
+    type Output;
+}
+
+impl_static_type_for_tuple!(A: 0);
+impl_static_type_for_tuple!(A: 0, B: 1);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_static_type_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+/// Appender of items of type T.
+///
+/// Builders have no static type, because operator of merger
+/// is only known at run time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Appender<T>(PhantomData<T>);
+
+/// Merger of items of type T.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Merger<T>(PhantomData<T>);
+
+/// DictMerger of keys of type K and values of type V.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DictMerger<K, V>(PhantomData<(K, V)>);
+
+/// GroupMerger of keys of type K and values of type V.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupMerger<K, V>(PhantomData<(K, V)>);
+
+/// VecMerger of items of type T.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VecMerger<T>(PhantomData<T>);
+
+impl<T> BuilderType for Appender<T> {}
+impl<T> BuilderType for Merger<T> {}
+impl<K, V> BuilderType for DictMerger<K, V> {}
+impl<K, V> BuilderType for GroupMerger<K, V> {}
+impl<T> BuilderType for VecMerger<T> {}
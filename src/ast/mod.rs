@@ -25,8 +25,10 @@ mod ifte;
 mod iter;
 mod lambda;
 mod length;
+mod let_in;
 mod lit;
 mod lookup;
+mod marker;
 mod merge;
 mod pfor;
 mod scalar;
@@ -43,7 +45,7 @@ pub use builder::{
     NewGroupMerger, NewMerger, NewVecMerger, VecMergerType,
 };
 pub use cast::Cast;
-pub use dict::{Dict, DictType};
+pub use dict::{DictType, NewDict};
 pub use eval::Eval;
 pub use expr::Expr;
 pub use expr_ext::{ExprTransformer, ExprVisitor};
@@ -52,13 +54,17 @@ pub use ifte::IfThenElse;
 pub use iter::Iter;
 pub use lambda::{Lambda, LambdaType};
 pub use length::Length;
+pub use let_in::Let;
 pub use lit::Literal;
 pub use lookup::Lookup;
+pub use marker::{
+    Appender, Dict, DictMerger, GroupMerger, Merger, StaticType, TupleField, VecMerger,
+};
 pub use merge::Merge;
 pub use pfor::For;
 pub use scalar::ScalarType;
 pub use tuple::{Tuple, TupleType};
 pub use ty::{Bool, BuilderType, Str, Type, TypeInference, F32, F64, I32, I64, U32, U64, U8};
 pub use unary_op::{UnaryOp, UnaryOpType};
-pub use var::Var;
+pub use var::{IntoVar, Var};
 pub use vector::{Vector, VectorType};
@@ -8,6 +8,9 @@ pub struct For {
     pub(crate) iters: Vec<Iter>,
     pub(crate) builder: Box<Expr>,
     pub(crate) func: Box<Expr>,
+    /// Environment tuple passed to the function after the item, only
+    /// set by closure conversion.
+    pub(crate) env: Option<Box<Expr>>,
 }
 
 impl TypeInference for For {
@@ -50,6 +53,10 @@ impl std::fmt::Display for For {
             }
             t.fmt(f)?;
         }
-        write!(f, "], {}, {})", self.builder, self.func)
+        write!(f, "], {}, {}", self.builder, self.func)?;
+        if let Some(env) = &self.env {
+            write!(f, ", {}", env)?;
+        }
+        f.write_char(')')
     }
 }
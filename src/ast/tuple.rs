@@ -3,6 +3,8 @@ use super::{Expr, Type, TypeInference};
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TupleType(pub(crate) Vec<Type>);
 
+impl_from_for_type!(TupleType, Type::Tuple);
+
 impl std::fmt::Display for TupleType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use std::fmt::Write;
@@ -43,7 +43,7 @@ pub enum Type {
     GroupMerger(GroupMergerType),
     /// A builder that constructs a vector by updating elements.
     VecMerger(VecMergerType),
-    /// An unknown type, used only before type inference.
+    /// An unknown type, used before type check or if inference fails.
     Unknown,
 }
 
@@ -64,6 +64,7 @@ impl Type {
         }
     }
 
+    /// Returns true if the type is a builder or a tuple of builders.
     #[inline]
     pub fn is_builder(&self) -> bool {
         match self {
@@ -72,6 +73,40 @@ impl Type {
             | Type::DictMerger(_)
             | Type::GroupMerger(_)
             | Type::VecMerger(_) => true,
+            Type::Tuple(TupleType(tys)) => !tys.is_empty() && tys.iter().all(Type::is_builder),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the type is an integer or floating-point scalar.
+    #[inline]
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Returns true if the type is an integer scalar, boolean excluded.
+    #[inline]
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Type::U8(_) | Type::U32(_) | Type::I32(_) | Type::U64(_) | Type::I64(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the type is a signed numeric scalar.
+    #[inline]
+    pub fn is_signed(&self) -> bool {
+        match self {
+            Type::I32(_) | Type::I64(_) | Type::F32(_) | Type::F64(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the type is a floating-point scalar.
+    #[inline]
+    pub fn is_float(&self) -> bool {
+        match self {
+            Type::F32(_) | Type::F64(_) => true,
             _ => false,
         }
     }
@@ -303,24 +338,42 @@ impl Type {
     /// Returns the result type for eval
     #[inline]
     pub fn eval(self) -> Type {
-        match self {
+        match self.try_eval() {
+            Some(ty) => ty,
+            None => panic!("{:?} cannot be evaluated", self),
+        }
+    }
+
+    /// Returns the result type for eval, or None if self is not a builder.
+    pub fn try_eval(&self) -> Option<Type> {
+        let ty = match self {
             Type::Appender(AppenderType { item_ty })
-            | Type::VecMerger(VecMergerType { item_ty, .. }) => {
-                Type::Vector(VectorType { item_ty })
-            }
-            Type::Merger(MergerType { item_ty, .. }) => *item_ty,
+            | Type::VecMerger(VecMergerType { item_ty, .. }) => Type::Vector(VectorType {
+                item_ty: item_ty.clone(),
+            }),
+            Type::Merger(MergerType { item_ty, .. }) => item_ty.as_ref().clone(),
             Type::DictMerger(DictMergerType {
                 key_ty, value_ty, ..
-            }) => Type::Dict(DictType { key_ty, value_ty }),
+            }) => Type::Dict(DictType {
+                key_ty: key_ty.clone(),
+                value_ty: value_ty.clone(),
+            }),
             Type::GroupMerger(GroupMergerType { key_ty, value_ty }) => {
-                let list_ty = Type::Vector(VectorType { item_ty: value_ty });
+                let list_ty = Type::Vector(VectorType {
+                    item_ty: value_ty.clone(),
+                });
                 Type::Dict(DictType {
-                    key_ty,
+                    key_ty: key_ty.clone(),
                     value_ty: Box::new(list_ty),
                 })
             }
-            _ => panic!("{:?} cannot be evaluated", self),
-        }
+            // tuple of builders evaluates to tuple of their results
+            Type::Tuple(TupleType(tys)) if self.is_builder() => Type::Tuple(TupleType(
+                tys.iter().map(Type::try_eval).collect::<Option<Vec<_>>>()?,
+            )),
+            _ => return None,
+        };
+        Some(ty)
     }
 
     /// Returns the item type for merge
@@ -328,18 +381,34 @@ impl Type {
     /// This method only returns valid type that can be merged
     /// if self is builder type, otherwise it will panic.
     pub fn merge(self) -> Type {
-        match self {
-            Type::Appender(AppenderType { item_ty })
-            | Type::VecMerger(VecMergerType { item_ty, .. })
-            | Type::Merger(MergerType { item_ty, .. }) => *item_ty,
+        match self.try_merge() {
+            Some(ty) => ty,
+            None => panic!("{:?} cannot be merged", self),
+        }
+    }
+
+    /// Returns the item type for merge, or None if self is not a builder.
+    pub fn try_merge(&self) -> Option<Type> {
+        let ty = match self {
+            Type::Appender(AppenderType { item_ty }) | Type::Merger(MergerType { item_ty, .. }) => {
+                item_ty.as_ref().clone()
+            }
+            // vecmerger merges item at given index
+            Type::VecMerger(VecMergerType { item_ty, .. }) => {
+                Type::Tuple(TupleType(vec![Type::U64(U64), item_ty.as_ref().clone()]))
+            }
             Type::DictMerger(DictMergerType {
                 key_ty, value_ty, ..
             })
             | Type::GroupMerger(GroupMergerType { key_ty, value_ty }) => {
-                Type::Tuple(TupleType(vec![*key_ty, *value_ty]))
+                Type::Tuple(TupleType(vec![
+                    key_ty.as_ref().clone(),
+                    value_ty.as_ref().clone(),
+                ]))
             }
-            _ => panic!("{:?} cannot be merged", self),
-        }
+            _ => return None,
+        };
+        Some(ty)
     }
 }
 
@@ -413,6 +482,7 @@ pub struct Str {}
 #[allow(non_upper_case_globals)]
 pub const Str: Str = Str {};
 
+impl_from_for_type!(Bool, Type::Bool);
 impl_from_for_type!(U8, Type::U8);
 impl_from_for_type!(U32, Type::U32);
 impl_from_for_type!(I32, Type::I32);
@@ -424,9 +494,3 @@ impl_from_for_type!(Str, Type::Str);
 
 /// Marker trait for builder
 pub trait BuilderType {}
-
-impl BuilderType for AppenderType {}
-impl BuilderType for MergerType {}
-impl BuilderType for DictMergerType {}
-impl BuilderType for GroupMergerType {}
-impl BuilderType for VecMergerType {}
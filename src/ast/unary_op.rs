@@ -19,10 +19,25 @@ pub enum UnaryOpType {
     Cosh,
     Tanh,
     Erf,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
 }
 
 derive_display!(UnaryOpType);
 
+impl UnaryOpType {
+    /// Returns true if the operator is a math function, which is only
+    /// defined on floats.
+    pub fn is_math(&self) -> bool {
+        match self {
+            UnaryOpType::Not | UnaryOpType::Neg | UnaryOpType::Abs => false,
+            _ => true,
+        }
+    }
+}
+
 /// Unary operation on single expression.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct UnaryOp {
@@ -56,8 +71,8 @@ impl TypeInference for UnaryOp {
     fn ty(&self) -> Type {
         match self.op_ty {
             UnaryOpType::Not => Type::Bool(Bool),
-            UnaryOpType::Neg => self.value.ty(),
-            _ => todo!(),
+            // other operators keep the type of operand
+            _ => self.value.ty(),
         }
     }
 }
@@ -1,16 +1,18 @@
 use super::*;
 use crate::sym::Symbol;
 use std::marker::PhantomData;
-use std::ops::{Add, Div, Mul, Neg, Not, Rem, Sub};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub};
 
 /// Var represents a variable of linear type in the staging program.
 ///
-/// Var is generic over its actual value.
+/// Var is generic over its actual value, element types of vectors,
+/// dicts, tuples and builders are part of the generic type, e.g.
+/// Var<Vec<I32>>, Var<Dict<Str, I64>>, Var<(I32, Bool)>.
 ///
 /// Scalar operations defined on Var<ScalarType>.
 ///
-/// Builder operations defined on Var<AppenderType>, Var<MergerType>, Var<DictMergerType>,
-/// Var<GroupMergerType>, Var<VecMergerType>.
+/// Builder operations defined on Var<Appender<T>>, Var<Merger<T>>, Var<DictMerger<K, V>>,
+/// Var<GroupMerger<K, V>>, Var<VecMerger<T>>.
 ///
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Var<T> {
@@ -31,33 +33,13 @@ impl<T> Var<T> {
         }
     }
 
-    pub fn zip<U>(self, other: Var<U>) -> Var<TupleType> {
-        match (self.expr.is_tuple(), other.expr.is_tuple()) {
-            (true, true) => {
-                let mut tp0 = self.expr.to_tuple();
-                let tp1 = other.expr.to_tuple();
-                tp0.0.extend(tp1.0);
-                Var::new(Expr::Tuple(tp0))
-            }
-            (true, false) => {
-                let mut tp0 = self.expr.to_tuple();
-                tp0.0.push(other.expr);
-                Var::new(Expr::Tuple(tp0))
-            }
-            (false, true) => {
-                let tp1 = other.expr.to_tuple();
-                let mut exprs = Vec::with_capacity(tp1.0.len() + 1);
-                exprs.push(self.expr);
-                exprs.extend(tp1.0);
-                Var::new(Expr::Tuple(Tuple(exprs)))
-            }
-            (false, false) => {
-                let mut exprs = Vec::with_capacity(2);
-                exprs.push(self.expr);
-                exprs.push(other.expr);
-                Var::new(Expr::Tuple(Tuple(exprs)))
-            }
-        }
+    /// Zip two vars into a pair.
+    ///
+    /// Tuples are no longer flattened, zipping a tuple nests it,
+    /// e.g. `a.zip(b).zip(c)` is `((a, b), c)` instead of `(a, b, c)`.
+    /// Use `Var::new_tuple` to build a flat tuple.
+    pub fn zip<U>(self, other: Var<U>) -> Var<(T, U)> {
+        Var::new(Expr::Tuple(Tuple(vec![self.expr, other.expr])))
     }
 
     /// Clone a symbol.
@@ -73,19 +55,81 @@ impl<T> Var<T> {
         }
     }
 
-    /// Create a new symbol.
-    pub fn new_symbol<S>(name: S, ty: T) -> Self
+    /// Create a new symbol, the type is only used for inference.
+    pub fn new_symbol<S>(name: S, _ty: T) -> Self
     where
         S: Into<String>,
-        T: Into<Type>,
+        T: StaticType,
     {
         Var {
-            expr: Expr::Symbol(Symbol::named(name, ty)),
+            expr: Expr::Symbol(Symbol::named(name, T::static_ty())),
             _marker: PhantomData,
         }
     }
+
+    /// Cast the scalar var to another scalar type.
+    ///
+    /// Literal is converted at once.
+    pub fn cast<U>(self) -> Var<U>
+    where
+        T: ScalarType,
+        U: ScalarType + StaticType,
+    {
+        let ty = U::static_ty();
+        match self.expr {
+            Expr::Literal(lit) => Var::new(Expr::Literal(lit.cast(&ty).unwrap())),
+            value => Var::new(Expr::Cast(Cast {
+                ty,
+                value: Box::new(value),
+            })),
+        }
+    }
+
+    /// Bind the value to a new symbol, and build the body with it.
+    ///
+    /// The value is evaluated only once, no matter how many times
+    /// the symbol is used in the body.
+    pub fn let_in<U, F>(value: Var<T>, f: F) -> Var<U>
+    where
+        F: FnOnce(Var<T>) -> Var<U>,
+    {
+        let sym = Symbol::fresh_named("v", value.ty());
+        let body = f(Var::clone_symbol(sym.clone()));
+        Var::new(Expr::Let(Let {
+            sym,
+            value: Box::new(value.expr),
+            body: Box::new(body.expr),
+        }))
+    }
+}
+
+/// Conversion into a var, whose type is known at compile time.
+///
+/// It is implemented by vars, Rust literals and tuples of them.
+pub trait IntoVar {
+    type Ty;
+
+    fn into_var(self) -> Var<Self::Ty>;
+}
+
+impl<T> IntoVar for Var<T> {
+    type Ty = T;
+
+    fn into_var(self) -> Var<T> {
+        self
+    }
 }
 
+impl_into_var_for_lit!(bool, Bool, lit_bool);
+impl_into_var_for_lit!(u8, U8, lit_u8);
+impl_into_var_for_lit!(u32, U32, lit_u32);
+impl_into_var_for_lit!(i32, I32, lit_i32);
+impl_into_var_for_lit!(u64, U64, lit_u64);
+impl_into_var_for_lit!(i64, I64, lit_i64);
+impl_into_var_for_lit!(f32, F32, lit_f32);
+impl_into_var_for_lit!(f64, F64, lit_f64);
+impl_into_var_for_lit!(String, Str, lit_string);
+
 impl<T> From<Var<T>> for Expr {
     fn from(src: Var<T>) -> Self {
         src.expr
@@ -115,6 +159,36 @@ impl Var<Bool> {
         );
         Var::new(expr)
     }
+
+    /// Choose between two vars of same type by the condition.
+    ///
+    /// Only the chosen branch is evaluated.
+    pub fn if_then_else<T>(self, t: Var<T>, e: Var<T>) -> Var<T> {
+        assert_eq!(
+            t.ty(),
+            e.ty(),
+            "Imcompatible types of branches in if_then_else operation"
+        );
+        match self.expr {
+            Expr::Literal(Literal::Bool(true)) => t,
+            Expr::Literal(Literal::Bool(false)) => e,
+            i => Var::new(Expr::IfThenElse(IfThenElse {
+                i: Box::new(i),
+                t: Box::new(t.expr),
+                e: Box::new(e.expr),
+            })),
+        }
+    }
+
+    /// Logical and on two vars, and returns a bool var
+    pub fn and(self, other: Self) -> Self {
+        Var::new(Expr::BinOp(BinOp::and(self.expr, other.expr)))
+    }
+
+    /// Logical or on two vars, and returns a bool var
+    pub fn or(self, other: Self) -> Self {
+        Var::new(Expr::BinOp(BinOp::or(self.expr, other.expr)))
+    }
 }
 
 impl Not for Var<Bool> {
@@ -152,12 +226,12 @@ impl Var<Str> {
     }
 
     /// Equality check on two vars, and returns a bool var
-    pub fn eq(self, other: Self) -> Var<bool> {
+    pub fn eq(self, other: Self) -> Var<Bool> {
         Var::new(Expr::BinOp(BinOp::eq(self.expr, other.expr)))
     }
 
     /// Non-equality check on two vars, and returns a bool var
-    pub fn ne(self, other: Self) -> Var<bool> {
+    pub fn ne(self, other: Self) -> Var<Bool> {
         Var::new(Expr::BinOp(BinOp::ne(self.expr, other.expr)))
     }
 }
@@ -170,54 +244,49 @@ impl_num_var!(I64, i64, lit_i64, expr_i64, is_i64);
 impl_num_var!(F32, f32, lit_f32, expr_f32, is_f32);
 impl_num_var!(F64, f64, lit_f64, expr_f64, is_f64);
 
-impl_arith_for_var_num!(Add, Add<Var<U8>>, Add<u8>, Var<U8>, u8, add, +, as_u8, lit_u8, expr_u8, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<U8>>, Sub<u8>, Var<U8>, u8, sub, -, as_u8, lit_u8, expr_u8, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<U8>>, Mul<u8>, Var<U8>, u8, mul, *, as_u8, lit_u8, expr_u8, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<U8>>, Div<u8>, Var<U8>, u8, div, /, as_u8, lit_u8, expr_u8, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<U8>>, Rem<u8>, Var<U8>, u8, rem, %, as_u8, lit_u8, expr_u8, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<U32>>, Add<u32>, Var<U32>, u32, add, +, as_u32, lit_u32, expr_u32, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<U32>>, Sub<u32>, Var<U32>, u32, sub, -, as_u32, lit_u32, expr_u32, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<U32>>, Mul<u32>, Var<U32>, u32, mul, *, as_u32, lit_u32, expr_u32, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<U32>>, Div<u32>, Var<U32>, u32, div, /, as_u32, lit_u32, expr_u32, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<U32>>, Rem<u32>, Var<U32>, u32, rem, /, as_u32, lit_u32, expr_u32, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<I32>>, Add<i32>, Var<I32>, i32, add, +, as_i32, lit_i32, expr_i32, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<I32>>, Sub<i32>, Var<I32>, i32, sub, -, as_i32, lit_i32, expr_i32, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<I32>>, Mul<i32>, Var<I32>, i32, mul, *, as_i32, lit_i32, expr_i32, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<I32>>, Div<i32>, Var<I32>, i32, div, /, as_i32, lit_i32, expr_i32, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<I32>>, Rem<i32>, Var<I32>, i32, rem, /, as_i32, lit_i32, expr_i32, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<U64>>, Add<u64>, Var<U64>, u64, add, +, as_u64, lit_u64, expr_u64, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<U64>>, Sub<u64>, Var<U64>, u64, sub, -, as_u64, lit_u64, expr_u64, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<U64>>, Mul<u64>, Var<U64>, u64, mul, *, as_u64, lit_u64, expr_u64, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<U64>>, Div<u64>, Var<U64>, u64, div, /, as_u64, lit_u64, expr_u64, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<U64>>, Rem<u64>, Var<U64>, u64, rem, /, as_u64, lit_u64, expr_u64, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<I64>>, Add<i64>, Var<I64>, i64, add, +, as_i64, lit_i64, expr_i64, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<I64>>, Sub<i64>, Var<I64>, i64, sub, -, as_i64, lit_i64, expr_i64, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<I64>>, Mul<i64>, Var<I64>, i64, mul, *, as_i64, lit_i64, expr_i64, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<I64>>, Div<i64>, Var<I64>, i64, div, /, as_i64, lit_i64, expr_i64, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<I64>>, Rem<i64>, Var<I64>, i64, rem, /, as_i64, lit_i64, expr_i64, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<F32>>, Add<f32>, Var<F32>, f32, add, +, as_f32, lit_f32, expr_f32, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<F32>>, Sub<f32>, Var<F32>, f32, sub, -, as_f32, lit_f32, expr_f32, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<F32>>, Mul<f32>, Var<F32>, f32, mul, *, as_f32, lit_f32, expr_f32, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<F32>>, Div<f32>, Var<F32>, f32, div, /, as_f32, lit_f32, expr_f32, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<F32>>, Rem<f32>, Var<F32>, f32, rem, /, as_f32, lit_f32, expr_f32, BinOp::rem);
-
-impl_arith_for_var_num!(Add, Add<Var<F64>>, Add<f64>, Var<F64>, f64, add, +, as_f64, lit_f64, expr_f64, BinOp::add);
-impl_arith_for_var_num!(Sub, Sub<Var<F64>>, Sub<f64>, Var<F64>, f64, sub, -, as_f64, lit_f64, expr_f64, BinOp::sub);
-impl_arith_for_var_num!(Mul, Mul<Var<F64>>, Mul<f64>, Var<F64>, f64, mul, *, as_f64, lit_f64, expr_f64, BinOp::mul);
-impl_arith_for_var_num!(Div, Div<Var<F64>>, Div<f64>, Var<F64>, f64, div, /, as_f64, lit_f64, expr_f64, BinOp::div);
-impl_arith_for_var_num!(Rem, Rem<Var<F64>>, Rem<f64>, Var<F64>, f64, rem, /, as_f64, lit_f64, expr_f64, BinOp::rem);
+impl_math_for_var_float!(F32, expr_f32);
+impl_math_for_var_float!(F64, expr_f64);
+
+impl_arith_for_var_num!(U8, u8, lit_u8, expr_u8);
+impl_arith_for_var_num!(U32, u32, lit_u32, expr_u32);
+impl_arith_for_var_num!(I32, i32, lit_i32, expr_i32);
+impl_arith_for_var_num!(U64, u64, lit_u64, expr_u64);
+impl_arith_for_var_num!(I64, i64, lit_i64, expr_i64);
+impl_arith_for_var_num!(F32, f32, lit_f32, expr_f32);
+impl_arith_for_var_num!(F64, f64, lit_f64, expr_f64);
+
+impl_bitop_for_var_int!(U8, u8, lit_u8, expr_u8);
+impl_bitop_for_var_int!(U32, u32, lit_u32, expr_u32);
+impl_bitop_for_var_int!(I32, i32, lit_i32, expr_i32);
+impl_bitop_for_var_int!(U64, u64, lit_u64, expr_u64);
+impl_bitop_for_var_int!(I64, i64, lit_i64, expr_i64);
+
+impl_shift_for_var_int!(U8, u8, lit_u8, expr_u8);
+impl_shift_for_var_int!(U32, u32, lit_u32, expr_u32);
+impl_shift_for_var_int!(I32, i32, lit_i32, expr_i32);
+impl_shift_for_var_int!(U64, u64, lit_u64, expr_u64);
+impl_shift_for_var_int!(I64, i64, lit_i64, expr_i64);
+
+impl Var<I32> {
+    /// Returns absolute value of the var, wraps on minimum value
+    pub fn abs(self) -> Self {
+        match self.expr {
+            Expr::Literal(Literal::I32(v)) => Var::lit_i32(v.wrapping_abs()),
+            other => Var::expr_i32(Expr::UnaryOp(UnaryOp {
+                op_ty: UnaryOpType::Abs,
+                value: Box::new(other),
+            })),
+        }
+    }
+}
 
 impl Neg for Var<I32> {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
         match self.expr {
-            Expr::Literal(Literal::I32(v)) => Var::lit_i32(-v),
+            // wraps on minimum value, same as the interpreter
+            Expr::Literal(Literal::I32(v)) => Var::lit_i32(v.wrapping_neg()),
             Expr::UnaryOp(UnaryOp {
                 op_ty: UnaryOpType::Neg,
                 value,
@@ -228,12 +297,26 @@ impl Neg for Var<I32> {
     }
 }
 
+impl Var<I64> {
+    /// Returns absolute value of the var, wraps on minimum value
+    pub fn abs(self) -> Self {
+        match self.expr {
+            Expr::Literal(Literal::I64(v)) => Var::lit_i64(v.wrapping_abs()),
+            other => Var::expr_i64(Expr::UnaryOp(UnaryOp {
+                op_ty: UnaryOpType::Abs,
+                value: Box::new(other),
+            })),
+        }
+    }
+}
+
 impl Neg for Var<I64> {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
         match self.expr {
-            Expr::Literal(Literal::I64(v)) => Var::lit_i64(-v),
+            // wraps on minimum value, same as the interpreter
+            Expr::Literal(Literal::I64(v)) => Var::lit_i64(v.wrapping_neg()),
             Expr::UnaryOp(UnaryOp {
                 op_ty: UnaryOpType::Neg,
                 value,
@@ -286,23 +369,28 @@ impl<B: BuilderType> Var<B> {
     /// pfor represents the parallel for expression of builder operation.
     /// The provided lambda will be called in parallel with non-overlapping
     /// items in iteration.
-    pub fn pfor<T, F>(self, it: Var<VectorType>, f: F) -> Self
+    pub fn pfor<T, F>(self, it: Var<Vec<T>>, f: F) -> Self
     where
-        F: FnOnce(Self, Var<u64>, Var<T>) -> Self,
+        F: FnOnce(Self, Var<U64>, Var<T>) -> Self,
         F: 'static,
     {
+        let item_ty = match it.ty() {
+            Type::Vector(VectorType { item_ty }) => *item_ty,
+            other => panic!("Imcompatible type[{}] to iterate in pfor operation", other),
+        };
         let iter = Iter {
             data: Box::new(it.expr),
             start: None,
             end: None,
         };
 
-        let sym_b = Symbol::named("b", self.ty());
-        let sym_i = Symbol::named("i", U64);
-        let sym_e = Symbol::named("e", self.ty().merge());
+        // parameters are fresh, so nested loops never shadow each other
+        let sym_b = Symbol::fresh_named("b", self.ty());
+        let sym_i = Symbol::fresh_named("i", U64);
+        let sym_e = Symbol::fresh_named("e", item_ty);
 
         let b = Var::<B>::clone_symbol(sym_b.clone());
-        let i = Var::<u64>::clone_symbol(sym_i.clone());
+        let i = Var::<U64>::clone_symbol(sym_i.clone());
         let e = Var::<T>::clone_symbol(sym_e.clone());
 
         let body = f(b, i, e);
@@ -316,35 +404,35 @@ impl<B: BuilderType> Var<B> {
             iters: vec![iter],
             builder: Box::new(self.expr),
             func: Box::new(Expr::Lambda(func)),
+            env: None,
         };
         Var::new(Expr::For(pfor))
     }
 }
 
 /// Implements methods on appender var.
-impl Var<AppenderType> {
+impl<T: StaticType> Var<Appender<T>> {
     /// Create a new var of appender with given item type.
-    pub fn appender<T: Into<Type>>(item_ty: T) -> Self {
+    pub fn appender(_item_ty: T) -> Self {
         Var::new(Expr::NewAppender(NewAppender {
-            item_ty: item_ty.into(),
+            item_ty: T::static_ty(),
         }))
     }
 
     /// Evaluate the appender and returns a var of vector.
-    pub fn eval(self) -> Var<VectorType> {
-        let result = Eval(Box::new(self.expr));
-        assert!(result.ty().is_vector());
-        Var::new(Expr::Eval(result))
+    pub fn eval(self) -> Var<Vec<T>> {
+        Var::new(Expr::Eval(Eval(Box::new(self.expr))))
     }
 
     /// Merge the appender with given item and return the updated appender.
     ///
     /// The internal implementation of merging might be executed in place
     /// instead of constructing a brand new appender.
-    pub fn merge<T>(self, item: T) -> Self
+    pub fn merge<I>(self, item: I) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = T>,
     {
+        let item = item.into_var();
         let m = match self.expr {
             Expr::NewAppender(a) => a.merge(item),
             Expr::Merge(m) => m.merge(item),
@@ -355,35 +443,26 @@ impl Var<AppenderType> {
     }
 }
 
-impl Var<MergerType> {
+impl<T: StaticType> Var<Merger<T>> {
     /// Create a new var of merger with given item type and operator.
-    pub fn new_merger<T: Into<Type>>(item_ty: T, op_ty: BinOpType) -> Self {
+    pub fn new_merger(_item_ty: T, op_ty: BinOpType) -> Self {
         Var::new(Expr::NewMerger(NewMerger {
-            item_ty: item_ty.into(),
+            item_ty: T::static_ty(),
             op_ty,
         }))
     }
 
-    /// Evaluate the merger with dynamic type, e.g. Tuple, Vector, etc.
-    ///
-    /// The input type should be consistent with the eval type.
-    pub fn eval<T: Into<Type>>(self, item_ty: T) -> Var<T> {
-        let item_ty = item_ty.into();
-        let result = Eval(Box::new(self.expr));
-        assert!(
-            result.ty() == item_ty,
-            "Imcompatible types[{:?} and {:?}] on eval operation",
-            result.ty(),
-            item_ty
-        );
-        Var::new(Expr::Eval(result))
+    /// Evaluate the merger and returns a var of merged value.
+    pub fn eval(self) -> Var<T> {
+        Var::new(Expr::Eval(Eval(Box::new(self.expr))))
     }
 
     /// Merge the merger with given item and return the updated merger.
-    pub fn merge<T>(self, item: T) -> Self
+    pub fn merge<I>(self, item: I) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = T>,
     {
+        let item = item.into_var();
         let m = match self.expr {
             Expr::NewMerger(m) => m.merge(item),
             Expr::Merge(m) => m.merge(item),
@@ -394,32 +473,27 @@ impl Var<MergerType> {
     }
 }
 
-impl Var<DictMergerType> {
+impl<K: StaticType, V: StaticType> Var<DictMerger<K, V>> {
     /// Create a new var of dictmerger with given key, value type and operator.
-    pub fn dictmerger<K: Into<Type>, V: Into<Type>>(
-        key_ty: K,
-        value_ty: V,
-        op_ty: BinOpType,
-    ) -> Self {
+    pub fn dictmerger(_key_ty: K, _value_ty: V, op_ty: BinOpType) -> Self {
         Var::new(Expr::NewDictMerger(NewDictMerger {
-            key_ty: key_ty.into(),
-            value_ty: value_ty.into(),
+            key_ty: K::static_ty(),
+            value_ty: V::static_ty(),
             op_ty,
         }))
     }
 
     /// Evaluate the dictmerger and returns a var of dict.
-    pub fn eval(self) -> Var<DictType> {
-        let result = Eval(Box::new(self.expr));
-        assert!(result.ty().is_dict());
-        Var::new(Expr::Eval(result))
+    pub fn eval(self) -> Var<Dict<K, V>> {
+        Var::new(Expr::Eval(Eval(Box::new(self.expr))))
     }
 
-    /// Merge the dictmerger with given item and return the updated dictmerger.
-    pub fn merge<T>(self, item: T) -> Self
+    /// Merge the dictmerger with given key-value pair and return the updated dictmerger.
+    pub fn merge<I>(self, item: I) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = (K, V)>,
     {
+        let item = item.into_var();
         let m = match self.expr {
             Expr::NewDictMerger(dm) => dm.merge(item),
             Expr::Merge(m) => m.merge(item),
@@ -430,27 +504,26 @@ impl Var<DictMergerType> {
     }
 }
 
-impl Var<GroupMergerType> {
+impl<K: StaticType, V: StaticType> Var<GroupMerger<K, V>> {
     /// Create a new var of groupmerger with given key, value type.
-    pub fn groupmerger<K: Into<Type>, V: Into<Type>>(key_ty: K, value_ty: V) -> Self {
+    pub fn groupmerger(_key_ty: K, _value_ty: V) -> Self {
         Var::new(Expr::NewGroupMerger(NewGroupMerger {
-            key_ty: key_ty.into(),
-            value_ty: value_ty.into(),
+            key_ty: K::static_ty(),
+            value_ty: V::static_ty(),
         }))
     }
 
     /// Evaluate the groupmerger and returns a var of dict.
-    pub fn eval(self) -> Var<DictType> {
-        let result = Eval(Box::new(self.expr));
-        assert!(result.ty().is_dict());
-        Var::new(Expr::Eval(result))
+    pub fn eval(self) -> Var<Dict<K, Vec<V>>> {
+        Var::new(Expr::Eval(Eval(Box::new(self.expr))))
     }
 
-    /// Merge the groupmerger with given item and return the updated groupmerger.
-    pub fn merge<T>(self, item: T) -> Self
+    /// Merge the groupmerger with given key-value pair and return the updated groupmerger.
+    pub fn merge<I>(self, item: I) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = (K, V)>,
     {
+        let item = item.into_var();
         let m = match self.expr {
             Expr::NewGroupMerger(gm) => gm.merge(item),
             Expr::Merge(m) => m.merge(item),
@@ -461,26 +534,30 @@ impl Var<GroupMergerType> {
     }
 }
 
-impl Var<VecMergerType> {
-    /// Create a new var of vecmerger with given item type and operator.
-    pub fn vecmerger<T: Into<Type>>(item_ty: T, op_ty: BinOpType) -> Self {
+impl<T: StaticType> Var<VecMerger<T>> {
+    /// Create a new var of vecmerger with given item type, operator and length.
+    pub fn vecmerger<I>(_item_ty: T, op_ty: BinOpType, len: I) -> Self
+    where
+        I: IntoVar<Ty = U64>,
+    {
         Var::new(Expr::NewVecMerger(NewVecMerger {
-            item_ty: item_ty.into(),
+            item_ty: T::static_ty(),
             op_ty,
+            len: Box::new(len.into_var().expr),
         }))
     }
 
     /// Evaluate the vecmerger and returns a var of vector.
-    pub fn eval(self) -> Var<VectorType> {
-        let result = Eval(Box::new(self.expr));
-        assert!(result.ty().is_vector());
-        Var::new(Expr::Eval(result))
+    pub fn eval(self) -> Var<Vec<T>> {
+        Var::new(Expr::Eval(Eval(Box::new(self.expr))))
     }
 
-    pub fn merge<T>(self, item: T) -> Self
+    /// Merge the vecmerger with given index and item, and return the updated vecmerger.
+    pub fn merge<I>(self, item: I) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = (U64, T)>,
     {
+        let item = item.into_var();
         let m = match self.expr {
             Expr::NewVecMerger(vm) => vm.merge(item),
             Expr::Merge(m) => m.merge(item),
@@ -492,47 +569,61 @@ impl Var<VecMergerType> {
 }
 
 /// Implements methods on vector var.
-impl Var<VectorType> {
+impl<T> Var<Vec<T>> {
     /// Create a new var of vector with given items
-    pub fn new_vector<T>(items: Vec<T>) -> Self
+    pub fn new_vector<I>(items: Vec<I>) -> Self
     where
-        T: Into<Expr>,
+        I: IntoVar<Ty = T>,
     {
         assert!(
             !items.is_empty(),
             "Empty list of items not allowed in creating new vector, use appender instead"
         );
-        let items: Vec<Expr> = items.into_iter().map(Into::into).collect();
+        let items: Vec<Expr> = items.into_iter().map(|item| item.into_var().expr).collect();
         let item_ty: Type = items[0].ty();
         Var::new(Expr::Vector(Vector { item_ty, items }))
     }
+
+    /// Returns length of the vector.
+    pub fn len(self) -> Var<U64> {
+        Var::new(Expr::Length(Length(Box::new(self.expr))))
+    }
 }
 
-impl Var<TupleType> {
-    pub fn new_tuple(items: Vec<Expr>) -> Self {
-        assert!(
-            !items.is_empty(),
-            "Empty list of items not allowed in creating new tuple"
-        );
-        Var::new(Expr::Tuple(Tuple(items)))
+/// Implements methods on dict var.
+impl<K, V> Var<Dict<K, V>> {
+    /// Lookup the value of given key in the dict.
+    pub fn lookup<I>(self, key: I) -> Var<V>
+    where
+        I: IntoVar<Ty = K>,
+    {
+        Var::new(Expr::Lookup(Lookup {
+            dict: Box::new(self.expr),
+            index: Box::new(key.into_var().expr),
+        }))
     }
+}
 
-    pub fn get<T>(&self, index: u32, ty: T) -> Var<T>
+/// Implements methods on tuple var.
+impl<T> Var<T> {
+    /// Create a new var of tuple with given items, e.g. `(a, b, 1)`.
+    pub fn new_tuple<I>(items: I) -> Self
     where
-        T: Into<Type>,
+        I: IntoVar<Ty = T>,
+        T: TupleField<0>,
     {
-        let ty: Type = ty.into();
-        let get_field = GetField {
+        items.into_var()
+    }
+
+    /// Returns field at index I of the tuple.
+    pub fn get<const I: usize>(&self) -> Var<<T as TupleField<I>>::Output>
+    where
+        T: TupleField<I>,
+    {
+        Var::new(Expr::GetField(GetField {
             tuple: Box::new(self.expr.clone()),
-            index,
-        };
-        assert_eq!(
-            ty,
-            get_field.ty(),
-            "Imcompatible type[{}] in get_field opertaion",
-            ty
-        );
-        Var::new(Expr::GetField(get_field))
+            index: I as u32,
+        }))
     }
 }
 
@@ -540,6 +631,9 @@ impl Var<TupleType> {
 mod tests {
 
     use super::*;
+    use crate::runtime::{interpret_program, Value};
+    use crate::stage::Program;
+    use crate::sym::{typecheck_program, uniquify_program};
 
     #[test]
     fn test_var_lit_add() {
@@ -551,6 +645,31 @@ mod tests {
         assert_eq!(Var::lit_i32(8), v4 + 4);
     }
 
+    #[test]
+    fn test_var_lit_fold_overflow_and_division() {
+        assert_eq!(Var::lit_i32(i32::MIN), Var::lit_i32(i32::MAX) + 1);
+        assert_eq!(Var::lit_i32(i32::MIN), -Var::lit_i32(i32::MIN));
+        assert_eq!(Var::lit_i64(i64::MIN), -Var::lit_i64(i64::MIN));
+        assert_eq!(Var::lit_i32(1), Var::lit_i32(7) % 2);
+        assert_eq!(Var::lit_f64(1.5), Var::lit_f64(7.5) % 2.0);
+        let v = Var::lit_i32(1) / 0;
+        assert_eq!(
+            Expr::BinOp(BinOp::div(
+                Expr::Literal(Literal::I32(1)),
+                Expr::Literal(Literal::I32(0))
+            )),
+            v.expr
+        );
+        let v = Var::lit_u64(1) % 0;
+        assert_eq!(
+            Expr::BinOp(BinOp::rem(
+                Expr::Literal(Literal::U64(1)),
+                Expr::Literal(Literal::U64(0))
+            )),
+            v.expr
+        );
+    }
+
     #[test]
     fn test_var_appender() {
         let a1 = Var::appender(I32);
@@ -566,26 +685,223 @@ mod tests {
         let m2 = m1.merge(1);
         let m3 = m2.merge(2);
         let m4 = m3.merge(3);
-        println!("{}", m4.eval(I32).expr);
+        println!("{}", m4.eval().expr);
     }
 
     #[test]
     fn test_var_vector() {
         let v1 = Var::new_vector(vec![1, 2, 3]);
         let m1 = Var::new_merger(I32, BinOpType::Add);
-        let m2 = m1.pfor(v1, |b, _i, e: Var<i32>| b.merge(e));
+        let m2 = m1.pfor(v1, |b, _i, e: Var<I32>| b.merge(e));
         println!("{}", m2.expr);
     }
 
     #[test]
     fn test_var_tuple() {
-        let v1 = Var::new_tuple(vec![1.into(), true.into()]);
-        let v2 = v1.get(0, I32);
+        let v1 = Var::new_tuple((1, true));
+        let v2: Var<I32> = v1.get::<0>();
         println!("{}", v2.expr);
 
         let v3 = Var::lit_i32(1);
         let v4 = Var::lit_bool(true);
         let v5 = v3.zip(v4);
-        println!("{}", v5.expr);
+        assert_eq!(v1, v5);
+        let v6: Var<(I32, (I32, Bool))> = Var::new_tuple((2, v5.clone()));
+        assert_eq!(Type::Bool(Bool), v6.get::<1>().get::<1>().ty());
+        let v7: Var<((I32, Bool), I32)> = v5.zip(Var::lit_i32(2));
+        assert_eq!(Type::Bool(Bool), v7.get::<0>().get::<1>().ty());
+    }
+
+    #[test]
+    fn test_var_static_types() {
+        let pairs = Var::new_vector(vec![(1, 10i64), (2, 20)]);
+        assert_eq!(Vec::<(I32, I64)>::static_ty(), pairs.ty());
+        let groups: Var<Dict<I32, Vec<I64>>> = Var::groupmerger(I32, I64)
+            .pfor(pairs, |b, _i, e| b.merge(e))
+            .eval();
+        assert_eq!(Dict::<I32, Vec<I64>>::static_ty(), groups.ty());
+        let group: Var<Vec<I64>> = groups.lookup(1);
+        assert_eq!(Type::U64(U64), group.len().ty());
+    }
+
+    #[test]
+    fn test_var_let_in() {
+        let sym = Symbol::named("x", I32);
+        let x = Var::<I32>::clone_symbol(sym.clone());
+        let v1 = Var::let_in(x.clone() * 3, move |v| v.clone() * v + x);
+        println!("{}", v1.expr);
+        let mut program = Program::new(vec![sym], v1);
+        uniquify_program(&mut program).unwrap();
+        typecheck_program(&program).unwrap();
+        let res = interpret_program(&program, &[Value::I32(2)]).unwrap();
+        assert_eq!(Value::I32(38), res);
+    }
+
+    #[test]
+    fn test_var_compare_and_bitwise() {
+        assert_eq!(Var::lit_i32(2), Var::lit_i32(6) & 3);
+        assert_eq!(Var::lit_u64(7), 3 | Var::lit_u64(5));
+        assert_eq!(Var::lit_i64(6), Var::lit_i64(3) ^ Var::lit_i64(5));
+
+        let params = vec![Symbol::named("x", I32), Symbol::named("y", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let y = Var::<I32>::clone_symbol(params[1].clone());
+        let in_range = x.clone().ge(Var::lit_i32(0)).and(x.clone().lt(y.clone()));
+        let edge = x.clone().eq(y.clone()).or(!x.clone().ne(Var::lit_i32(0)));
+        let body = Var::new_tuple((
+            in_range,
+            edge,
+            x.clone().le(y.clone()),
+            x.clone().gt(y.clone()),
+            x.clone().min(y.clone()),
+            x.clone().max(y.clone()),
+            (x.clone() & y.clone()) | (x ^ y) & 12,
+        ));
+        let program = Program::new(params, body);
+        typecheck_program(&program).unwrap();
+        let res = interpret_program(&program, &[Value::I32(5), Value::I32(9)]).unwrap();
+        let expected = Value::Tuple(vec![
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::I32(5),
+            Value::I32(9),
+            Value::I32(13),
+        ]);
+        assert_eq!(expected, res);
+    }
+
+    #[test]
+    fn test_var_math() {
+        // literals are folded
+        assert_eq!(Var::lit_f64(2.0), Var::lit_f64(4.0).sqrt());
+        assert_eq!(Var::lit_f32(0.0), Var::lit_f32(0.0).sin().tanh());
+        let erf = |x: f64| match Var::lit_f64(x).erf().expr {
+            Expr::Literal(Literal::F64(v)) => f64::from_bits(v),
+            other => panic!("not folded: {}", other),
+        };
+        assert!((erf(0.5) - 0.520_499_877_813_046_5).abs() < 1e-15);
+        assert!((erf(-2.0) + 0.995_322_265_018_952_7).abs() < 1e-15);
+        assert_eq!(1.0, erf(10.0));
+
+        let params = vec![Symbol::named("x", F64)];
+        let x = Var::<F64>::clone_symbol(params[0].clone());
+        let body = Var::new_tuple((
+            x.clone().exp().log(),
+            x.clone().sin() * x.clone().sin() + x.clone().cos() * x.clone().cos(),
+            x.clone().tan().atan(),
+            x.clone().cosh(),
+            x.erf(),
+        ));
+        let program = Program::new(params, body);
+        typecheck_program(&program).unwrap();
+        let res = interpret_program(&program, &[Value::F64(0.5)]).unwrap();
+        let expected = [0.5, 1.0, 0.5, 0.5f64.cosh(), 0.520_499_877_813_046_5];
+        match res {
+            Value::Tuple(items) => {
+                for (item, e) in items.iter().zip(&expected) {
+                    match item {
+                        Value::F64(v) => assert!((v - e).abs() < 1e-12, "{} != {}", v, e),
+                        other => panic!("unexpected value {}", other),
+                    }
+                }
+            }
+            other => panic!("unexpected value {}", other),
+        }
+    }
+
+    #[test]
+    fn test_var_pow_rounding_and_shift() {
+        // literals are folded
+        assert_eq!(Var::lit_f64(8.0), Var::lit_f64(2.0).pow(Var::lit_f64(3.0)));
+        assert_eq!(Var::lit_f32(-3.0), Var::lit_f32(-2.5).round());
+        assert_eq!(Var::lit_i32(5), Var::lit_i32(-5).abs());
+        assert_eq!(Var::lit_u32(12), Var::lit_u32(3) << 2);
+        assert_eq!(Var::lit_i64(-2), Var::lit_i64(-8) >> Var::lit_i64(2));
+        // out of range amounts are left to fail at run time
+        let shl = Var::lit_u32(1) << 40u32;
+        assert_eq!(Expr::BinOp(BinOp::shl(1u32.into(), 40u32.into())), shl.expr);
+        let shr = Var::lit_i32(-8) >> -1;
+        assert_eq!(Expr::BinOp(BinOp::shr((-8).into(), (-1).into())), shr.expr);
+        let program = Program::new(vec![], shl);
+        assert!(interpret_program(&program, &[]).is_err());
+
+        let params = vec![Symbol::named("x", F64), Symbol::named("n", I64)];
+        let x = Var::<F64>::clone_symbol(params[0].clone());
+        let n = Var::<I64>::clone_symbol(params[1].clone());
+        let body = Var::new_tuple((
+            x.clone().pow(Var::lit_f64(2.0)),
+            x.clone().atan2(Var::lit_f64(1.0)),
+            x.clone().abs(),
+            x.clone().floor(),
+            x.clone().ceil(),
+            x.round(),
+            n.clone().abs(),
+            (n.clone() << 3) >> n,
+        ));
+        let program = Program::new(params, body);
+        typecheck_program(&program).unwrap();
+        let res = interpret_program(&program, &[Value::F64(-1.5), Value::I64(2)]).unwrap();
+        let expected = Value::Tuple(vec![
+            Value::F64(2.25),
+            Value::F64((-1.5f64).atan2(1.0)),
+            Value::F64(1.5),
+            Value::F64(-2.0),
+            Value::F64(-1.0),
+            Value::F64(-2.0),
+            Value::I64(2),
+            Value::I64(4),
+        ]);
+        assert_eq!(expected, res);
+
+        // integers are rejected by math functions
+        let i = Var::<I32>::new_symbol("i", I32);
+        let floor = Expr::UnaryOp(UnaryOp {
+            op_ty: UnaryOpType::Floor,
+            value: Box::new(i.clone().expr),
+        });
+        let program = Program::new(vec![Symbol::named("i", I32)], Var::<I32>::new(floor));
+        assert!(typecheck_program(&program).is_err());
+        let pow = Expr::BinOp(BinOp::pow(i.clone().expr, i.expr));
+        let program = Program::new(vec![Symbol::named("i", I32)], Var::<I32>::new(pow));
+        assert!(typecheck_program(&program).is_err());
+    }
+
+    #[test]
+    fn test_var_ifte_cast_len_lookup() {
+        // literals are folded
+        assert_eq!(Var::lit_i64(3), Var::lit_f64(3.7).cast::<I64>());
+        assert_eq!(
+            Var::lit_i32(1),
+            Var::lit_bool(true).if_then_else(Var::lit_i32(1), Var::lit_i32(2))
+        );
+
+        let params = vec![Symbol::named("v", Vec::<I32>::static_ty())];
+        let v = Var::<Vec<I32>>::clone_symbol(params[0].clone());
+        // ratio of odd items
+        let odds = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| {
+                let odd = (e % 2).ne(Var::lit_i32(0));
+                b.merge(odd.if_then_else(Var::lit_i32(1), Var::lit_i32(0)))
+            })
+            .eval();
+        let ratio = odds.cast::<F64>() / v.len().cast::<F64>();
+        let pairs = Var::new_vector(vec![(1, 10i64), (2, 20), (1, 5)]);
+        let sum = Var::dictmerger(I32, I64, BinOpType::Add)
+            .pfor(pairs, |b, _i, e| b.merge(e))
+            .eval()
+            .lookup(1);
+        let program = Program::new(params, Var::new_tuple((ratio, sum)));
+        typecheck_program(&program).unwrap();
+        let res = interpret_program(&program, &[Value::from(vec![1, 2, 3, 5])]).unwrap();
+        assert_eq!(Value::Tuple(vec![Value::F64(0.75), Value::I64(15)]), res);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_var_ifte_incompatible_branches() {
+        Var::<Bool>::new_symbol("c", Bool)
+            .if_then_else(Var::<I32>::new(Var::lit_i64(1).expr), Var::lit_i32(2));
     }
 }
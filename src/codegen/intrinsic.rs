@@ -1,6 +1,8 @@
-use crate::ast::ScalarType;
+use crate::ast::{BinOpType, ScalarType, UnaryOpType};
 use inkwell::context::Context;
 use inkwell::module::Module;
+use inkwell::types::FunctionType;
+use inkwell::values::FunctionValue;
 use std::collections::HashMap;
 
 pub enum Intrinsic {
@@ -22,6 +24,65 @@ impl Intrinsics {
     pub fn llvm_numeric<T: AsRef<str>, S: ScalarType>(name: T, ty: S) -> String {
         format!("llvm.{}.{}", name.as_ref(), ty.scalar_repr())
     }
+
+    /// Returns name of the function implementing unary math operator on float type.
+    ///
+    /// LLVM intrinsics are preferred, operators without intrinsic fall back
+    /// to functions of C math library.
+    pub fn unary_math<S: ScalarType>(op_ty: UnaryOpType, ty: S) -> Option<String> {
+        let suffix = match ty.scalar_repr() {
+            "f32" => "f",
+            "f64" => "",
+            _ => return None,
+        };
+        let name = match op_ty {
+            UnaryOpType::Exp => return Some(Self::llvm_numeric("exp", ty)),
+            UnaryOpType::Log => return Some(Self::llvm_numeric("log", ty)),
+            UnaryOpType::Sqrt => return Some(Self::llvm_numeric("sqrt", ty)),
+            UnaryOpType::Sin => return Some(Self::llvm_numeric("sin", ty)),
+            UnaryOpType::Cos => return Some(Self::llvm_numeric("cos", ty)),
+            UnaryOpType::Abs => return Some(Self::llvm_numeric("fabs", ty)),
+            UnaryOpType::Floor => return Some(Self::llvm_numeric("floor", ty)),
+            UnaryOpType::Ceil => return Some(Self::llvm_numeric("ceil", ty)),
+            UnaryOpType::Round => return Some(Self::llvm_numeric("round", ty)),
+            UnaryOpType::Tan => "tan",
+            UnaryOpType::ASin => "asin",
+            UnaryOpType::ACos => "acos",
+            UnaryOpType::ATan => "atan",
+            UnaryOpType::Sinh => "sinh",
+            UnaryOpType::Cosh => "cosh",
+            UnaryOpType::Tanh => "tanh",
+            UnaryOpType::Erf => "erf",
+            UnaryOpType::Not | UnaryOpType::Neg => return None,
+        };
+        Some(format!("{}{}", name, suffix))
+    }
+
+    /// Returns name of the function implementing binary math operator on float type.
+    pub fn binary_math<S: ScalarType>(op_ty: BinOpType, ty: S) -> Option<String> {
+        let suffix = match ty.scalar_repr() {
+            "f32" => "f",
+            "f64" => "",
+            _ => return None,
+        };
+        match op_ty {
+            BinOpType::Pow => Some(Self::llvm_numeric("pow", ty)),
+            // no intrinsic of atan2 in LLVM
+            BinOpType::Atan2 => Some(format!("atan2{}", suffix)),
+            _ => None,
+        }
+    }
+
+    /// Returns the function of given name in module, declares it if not exists.
+    pub fn get_or_declare<'ctx>(
+        module: &Module<'ctx>,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> FunctionValue<'ctx> {
+        module
+            .get_function(name)
+            .unwrap_or_else(|| module.add_function(name, fn_type, None))
+    }
 }
 
 #[cfg(test)]
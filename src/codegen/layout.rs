@@ -0,0 +1,69 @@
+//! Memory layout of values in generated code.
+//!
+//! The layout follows C representation, so values can be exchanged
+//! with Rust code directly:
+//! vector is a struct of data pointer and length, appender is a struct
+//! of data pointer, length and capacity, and tuple is a struct of its
+//! fields with natural alignment.
+use crate::ast::*;
+use crate::Result;
+
+/// Returns the size and alignment in bytes of given type.
+pub fn size_align(ty: &Type) -> Result<(usize, usize)> {
+    let r = match ty {
+        Type::Bool(_) | Type::U8(_) => (1, 1),
+        Type::U32(_) | Type::I32(_) | Type::F32(_) => (4, 4),
+        Type::U64(_) | Type::I64(_) | Type::F64(_) => (8, 8),
+        Type::Vector(_) => (16, 8),
+        Type::Appender(_) => (24, 8),
+        Type::Merger(MergerType { item_ty, .. }) => size_align(item_ty)?,
+        Type::Tuple(TupleType(tys)) => {
+            let (_, size, align) = struct_layout(tys)?;
+            (size, align)
+        }
+        other => return Err(compile_err!("Unsupported type[{}] in memory layout", other)),
+    };
+    Ok(r)
+}
+
+/// Returns the field offsets, size and alignment of a struct
+/// with given field types.
+pub fn struct_layout(tys: &[Type]) -> Result<(Vec<usize>, usize, usize)> {
+    let mut offsets = Vec::with_capacity(tys.len());
+    let (mut offset, mut align) = (0, 1);
+    for ty in tys {
+        let (s, a) = size_align(ty)?;
+        offset = round_up(offset, a);
+        offsets.push(offset);
+        offset += s;
+        align = align.max(a);
+    }
+    Ok((offsets, round_up(offset, align), align))
+}
+
+#[inline]
+fn round_up(n: usize, align: usize) -> usize {
+    (n + align - 1) / align * align
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_struct_layout() -> Result<()> {
+        let tys: Vec<Type> = vec![Bool.into(), I32.into(), U8.into(), F64.into()];
+        let (offsets, size, align) = struct_layout(&tys)?;
+        assert_eq!(vec![0, 4, 8, 16], offsets);
+        assert_eq!((24, 8), (size, align));
+        let tys: Vec<Type> = vec![
+            U8.into(),
+            Type::Tuple(TupleType(vec![U32.into(), U8.into()])),
+        ];
+        let (offsets, size, align) = struct_layout(&tys)?;
+        assert_eq!(vec![0, 4], offsets);
+        assert_eq!((12, 4), (size, align));
+        Ok(())
+    }
+}
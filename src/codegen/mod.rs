@@ -1 +1,1146 @@
+//! Codegen module translates SIR program into LLVM IR.
 pub mod intrinsic;
+pub mod layout;
+
+use crate::ast::*;
+use crate::sir::{FunctionId, SirFunction, SirProgram, Stmt, StmtExpr, StmtIter, Terminator};
+use crate::sym::Symbol;
+use crate::Result;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::types::{BasicType, BasicTypeEnum, IntType};
+use inkwell::values::{
+    BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue, StructValue,
+};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate};
+use intrinsic::Intrinsics;
+use layout::size_align;
+use std::collections::HashMap;
+
+/// Name of the runtime function which allocates a new buffer and copies
+/// the used bytes of old buffer into it.
+///
+/// Its signature is `i8* (i8* old, i64 used, i64 size)`, the caller of
+/// generated code must provide the implementation.
+pub const RT_GROW: &str = "lms_rt_grow";
+
+/// Name of the runtime function which reports an error of generated code.
+///
+/// Its signature is `void (i32 code)`, where code is one of `RT_ERR_*`.
+/// Generated code continues after reporting, so the caller must discard
+/// the result once an error is reported.
+pub const RT_ERROR: &str = "lms_rt_error";
+
+/// Error code of integer division or remainder by zero.
+pub const RT_ERR_DIVIDE_BY_ZERO: u32 = 1;
+
+/// Error code of iterator range out of its vector, or iterators of a
+/// loop differing in length.
+pub const RT_ERR_INVALID_ITERATOR: u32 = 2;
+
+/// Error code of shift amount not in range of the bit width.
+pub const RT_ERR_SHIFT_OUT_OF_RANGE: u32 = 3;
+
+/// Initial capacity of appender.
+const APPENDER_INIT_CAP: u64 = 16;
+
+/// CodeGen translates SIR program into LLVM module.
+///
+/// Each symbol is allocated on stack of its function, so a symbol
+/// assigned in multiple blocks does not require phi node.
+/// The mem2reg pass of LLVM will promote them into registers.
+pub struct CodeGen<'ctx> {
+    ctx: &'ctx Context,
+    module: Module<'ctx>,
+    builder: Builder<'ctx>,
+    // functions indexed by id of SIR function
+    funcs: Vec<FunctionValue<'ctx>>,
+    // captured symbols of functions, indexed by id of SIR function
+    captures: Vec<Vec<Symbol>>,
+    // stack slots of symbols in current function
+    locals: HashMap<Symbol, PointerValue<'ctx>>,
+}
+
+impl<'ctx> CodeGen<'ctx> {
+    pub fn new(ctx: &'ctx Context, name: &str) -> Self {
+        CodeGen {
+            ctx,
+            module: ctx.create_module(name),
+            builder: ctx.create_builder(),
+            funcs: vec![],
+            captures: vec![],
+            locals: HashMap::new(),
+        }
+    }
+
+    pub fn module(&self) -> &Module<'ctx> {
+        &self.module
+    }
+
+    pub fn into_module(self) -> Module<'ctx> {
+        self.module
+    }
+
+    /// Generate all functions of the program and returns the entry function.
+    ///
+    /// Only one program can be generated by a CodeGen.
+    pub fn gen_program(&mut self, prog: &SirProgram) -> Result<FunctionValue<'ctx>> {
+        // declare all functions ahead so any of them can be called
+        for func in &prog.funcs {
+            let fn_value = self.declare_function(func)?;
+            self.funcs.push(fn_value);
+            self.captures.push(func.captures.clone());
+        }
+        for func in &prog.funcs {
+            self.gen_function(func)?;
+        }
+        self.module.verify()?;
+        Ok(self.funcs[0])
+    }
+
+    /// Generate a wrapper of the entry function with signature
+    /// `void (i8* args, i8* ret)`.
+    ///
+    /// The args points to a struct of all parameters, and the result is
+    /// stored at ret. Layouts of both follow the layout module.
+    pub fn gen_entry_wrapper(
+        &mut self,
+        prog: &SirProgram,
+        name: &str,
+    ) -> Result<FunctionValue<'ctx>> {
+        let entry = prog.entry();
+        let i8_ptr_ty = self.ctx.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self
+            .ctx
+            .void_type()
+            .fn_type(&[i8_ptr_ty.into(), i8_ptr_ty.into()], false);
+        let wrapper = self.module.add_function(name, fn_type, None);
+        let bb = self.ctx.append_basic_block(wrapper, "entry");
+        self.builder.position_at_end(bb);
+
+        let params_ty = entry
+            .params
+            .iter()
+            .map(|p| self.llvm_type(&p.ty))
+            .collect::<Result<Vec<_>>>()?;
+        let args_ty = self.ctx.struct_type(&params_ty, false);
+        let args_ptr = self.builder.build_pointer_cast(
+            wrapper.get_nth_param(0).unwrap().into_pointer_value(),
+            args_ty.ptr_type(AddressSpace::Generic),
+            "args",
+        );
+        let args = self
+            .builder
+            .build_load(args_ptr, "args")
+            .into_struct_value();
+        let call_args = (0..params_ty.len())
+            .map(|i| self.extract(args, i as u32))
+            .collect::<Vec<_>>();
+        let ret = self
+            .builder
+            .build_call(self.funcs[entry.id.0], &call_args, "ret")
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| compile_err!("Entry function returns no value"))?;
+        let ret_ty = self.llvm_type(&entry.ret_ty)?;
+        let ret_ptr = self.builder.build_pointer_cast(
+            wrapper.get_nth_param(1).unwrap().into_pointer_value(),
+            ret_ty.ptr_type(AddressSpace::Generic),
+            "ret",
+        );
+        self.builder.build_store(ret_ptr, ret);
+        self.builder.build_return(None);
+        Ok(wrapper)
+    }
+
+    fn declare_function(&self, func: &SirFunction) -> Result<FunctionValue<'ctx>> {
+        let params_ty = func
+            .args()
+            .map(|arg| self.llvm_type(&arg.ty))
+            .collect::<Result<Vec<_>>>()?;
+        let ret_ty = self.llvm_type(&func.ret_ty)?;
+        let fn_type = ret_ty.fn_type(&params_ty, false);
+        Ok(self
+            .module
+            .add_function(&func.id.to_string(), fn_type, None))
+    }
+
+    fn gen_function(&mut self, func: &SirFunction) -> Result<()> {
+        let fn_value = self.funcs[func.id.0];
+        self.locals.clear();
+        let entry = self.ctx.append_basic_block(fn_value, "entry");
+        let blocks: Vec<_> = func
+            .blocks
+            .iter()
+            .map(|b| self.ctx.append_basic_block(fn_value, &b.id.to_string()))
+            .collect();
+        // allocate all symbols in entry block
+        self.builder.position_at_end(entry);
+        for (i, arg) in func.args().enumerate() {
+            let ptr = self.alloca(arg)?;
+            let value = fn_value.get_nth_param(i as u32).unwrap();
+            self.builder.build_store(ptr, value);
+        }
+        for block in &func.blocks {
+            for stmt in &block.stmts {
+                if !self.locals.contains_key(&stmt.sym) {
+                    self.alloca(&stmt.sym)?;
+                }
+            }
+        }
+        self.builder.build_unconditional_branch(blocks[0]);
+
+        for block in &func.blocks {
+            self.builder.position_at_end(blocks[block.id.0]);
+            for stmt in &block.stmts {
+                self.gen_stmt(stmt)?;
+            }
+            match &block.terminator {
+                Terminator::Branch {
+                    cond,
+                    on_true,
+                    on_false,
+                } => {
+                    let cond = self.load(cond)?.into_int_value();
+                    self.builder.build_conditional_branch(
+                        cond,
+                        blocks[on_true.0],
+                        blocks[on_false.0],
+                    );
+                }
+                Terminator::JumpBlock(id) => {
+                    self.builder.build_unconditional_branch(blocks[id.0]);
+                }
+                Terminator::Return(sym) => {
+                    let value = self.load(sym)?;
+                    self.builder.build_return(Some(&value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn gen_stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        let value = match &stmt.expr {
+            StmtExpr::Literal(lit) => self.gen_literal(lit)?,
+            StmtExpr::Assign(sym) => self.load(sym)?,
+            StmtExpr::BinOp { op_ty, left, right } => {
+                let l = self.load(left)?;
+                let r = self.load(right)?;
+                self.gen_bin_op(*op_ty, &left.ty, l, r)?
+            }
+            StmtExpr::UnaryOp { op_ty, value } => {
+                let v = self.load(value)?;
+                self.gen_unary_op(*op_ty, &value.ty, v)?
+            }
+            StmtExpr::Cast { ty, value } => {
+                let v = self.load(value)?;
+                self.gen_cast(&value.ty, ty, v)?
+            }
+            StmtExpr::GetField { value, index } => {
+                let v = self.load(value)?.into_struct_value();
+                self.extract(v, *index)
+            }
+            StmtExpr::Length(sym) => {
+                let v = self.load(sym)?.into_struct_value();
+                self.extract(v, 1)
+            }
+            StmtExpr::NewTuple(items) => {
+                let values = items
+                    .iter()
+                    .map(|item| self.load(item))
+                    .collect::<Result<Vec<_>>>()?;
+                let ty = self.llvm_type(&stmt.sym.ty)?.into_struct_type();
+                self.build_struct(ty.get_undef(), &values).into()
+            }
+            StmtExpr::NewVector(items) => self.gen_new_vector(&stmt.sym.ty, items)?,
+            StmtExpr::NewAppender { .. } => {
+                let ty = self.llvm_type(&stmt.sym.ty)?.into_struct_type();
+                ty.const_zero().into()
+            }
+            StmtExpr::NewMerger { item_ty, op_ty } => {
+                let lit = op_ty.identity(item_ty).ok_or_else(|| {
+                    compile_err!("No identity of {} on type[{}] for merger", op_ty, item_ty)
+                })?;
+                self.gen_literal(&lit)?
+            }
+            StmtExpr::Merge { builder, value } => {
+                let b = self.load(builder)?;
+                let v = self.load(value)?;
+                match &builder.ty {
+                    Type::Merger(MergerType { item_ty, op_ty }) => {
+                        self.gen_bin_op(*op_ty, item_ty, b, v)?
+                    }
+                    Type::Appender(AppenderType { item_ty }) => {
+                        self.gen_appender_merge(item_ty, b.into_struct_value(), v)?
+                    }
+                    other => {
+                        return Err(compile_err!(
+                            "Unsupported builder type[{}] in code generation",
+                            other
+                        ))
+                    }
+                }
+            }
+            StmtExpr::Eval(builder) => {
+                let b = self.load(builder)?;
+                self.gen_eval(&builder.ty, b)?
+            }
+            StmtExpr::For {
+                iters,
+                builder,
+                func,
+                env,
+            } => self.gen_for(iters, builder, env.as_ref(), *func)?,
+            other => {
+                return Err(compile_err!(
+                    "Unsupported statement {} in code generation",
+                    other
+                ))
+            }
+        };
+        let ptr = self.locals[&stmt.sym];
+        self.builder.build_store(ptr, value);
+        Ok(())
+    }
+
+    /// Generate the result of builder, or tuple of builders.
+    fn gen_eval(&self, ty: &Type, builder: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>> {
+        let value = match ty {
+            Type::Merger(_) => builder,
+            Type::Appender(_) => {
+                let b = builder.into_struct_value();
+                let vec_ty = self.llvm_type(&ty.try_eval().unwrap())?.into_struct_type();
+                let values = [self.extract(b, 0), self.extract(b, 1)];
+                self.build_struct(vec_ty.get_undef(), &values).into()
+            }
+            Type::Tuple(TupleType(tys)) if ty.is_builder() => {
+                let b = builder.into_struct_value();
+                let values = tys
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| self.gen_eval(ty, self.extract(b, i as u32)))
+                    .collect::<Result<Vec<_>>>()?;
+                let tuple_ty = self.llvm_type(&ty.try_eval().unwrap())?.into_struct_type();
+                self.build_struct(tuple_ty.get_undef(), &values).into()
+            }
+            other => {
+                return Err(compile_err!(
+                    "Unsupported builder type[{}] in code generation",
+                    other
+                ))
+            }
+        };
+        Ok(value)
+    }
+
+    fn gen_new_vector(&self, ty: &Type, items: &[Symbol]) -> Result<BasicValueEnum<'ctx>> {
+        let item_ty = match ty {
+            Type::Vector(VectorType { item_ty }) => item_ty,
+            other => return Err(compile_err!("Invalid vector type[{}]", other)),
+        };
+        let (item_size, _) = size_align(item_ty)?;
+        let i64_type = self.ctx.i64_type();
+        let ptr_ty = self.llvm_type(item_ty)?.ptr_type(AddressSpace::Generic);
+        let size = i64_type.const_int((item_size * items.len()) as u64, false);
+        let ptr = self.gen_grow(ptr_ty.const_null(), i64_type.const_zero(), size);
+        for (i, item) in items.iter().enumerate() {
+            let v = self.load(item)?;
+            let idx = i64_type.const_int(i as u64, false);
+            let item_ptr = unsafe { self.builder.build_in_bounds_gep(ptr, &[idx], "item") };
+            self.builder.build_store(item_ptr, v);
+        }
+        let len = i64_type.const_int(items.len() as u64, false);
+        let vec_ty = self.llvm_type(ty)?.into_struct_type();
+        Ok(self
+            .build_struct(vec_ty.get_undef(), &[ptr.into(), len.into()])
+            .into())
+    }
+
+    /// Push a value into appender, growing its buffer if full.
+    fn gen_appender_merge(
+        &self,
+        item_ty: &Type,
+        app: StructValue<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+        let i64_type = self.ctx.i64_type();
+        let ptr = self.extract(app, 0).into_pointer_value();
+        let len = self.extract(app, 1).into_int_value();
+        let cap = self.extract(app, 2).into_int_value();
+        let fn_value = self.current_function();
+        let cur_bb = b.get_insert_block().unwrap();
+        let grow_bb = self.ctx.append_basic_block(fn_value, "app.grow");
+        let push_bb = self.ctx.append_basic_block(fn_value, "app.push");
+        let full = b.build_int_compare(IntPredicate::EQ, len, cap, "full");
+        b.build_conditional_branch(full, grow_bb, push_bb);
+
+        b.position_at_end(grow_bb);
+        let (item_size, _) = size_align(item_ty)?;
+        let item_size = i64_type.const_int(item_size as u64, false);
+        let empty = b.build_int_compare(IntPredicate::EQ, cap, i64_type.const_zero(), "empty");
+        let doubled = b.build_int_mul(cap, i64_type.const_int(2, false), "doubled");
+        let init_cap = i64_type.const_int(APPENDER_INIT_CAP, false);
+        let new_cap = b
+            .build_select(empty, init_cap, doubled, "cap")
+            .into_int_value();
+        let used = b.build_int_mul(len, item_size, "used");
+        let size = b.build_int_mul(new_cap, item_size, "size");
+        let new_ptr = self.gen_grow(ptr, used, size);
+        b.build_unconditional_branch(push_bb);
+
+        b.position_at_end(push_bb);
+        let ptr_phi = b.build_phi(ptr.get_type(), "ptr");
+        ptr_phi.add_incoming(&[(&ptr, cur_bb), (&new_ptr, grow_bb)]);
+        let cap_phi = b.build_phi(i64_type, "cap");
+        cap_phi.add_incoming(&[(&cap, cur_bb), (&new_cap, grow_bb)]);
+        let ptr = ptr_phi.as_basic_value().into_pointer_value();
+        let item_ptr = unsafe { b.build_in_bounds_gep(ptr, &[len], "item") };
+        b.build_store(item_ptr, value);
+        let new_len = b.build_int_add(len, i64_type.const_int(1, false), "len");
+        let values = [ptr.into(), new_len.into(), cap_phi.as_basic_value()];
+        Ok(self
+            .build_struct(app.get_type().get_undef(), &values)
+            .into())
+    }
+
+    /// Call runtime to allocate a new buffer of size bytes, copying used
+    /// bytes from old buffer, returns pointer of same type as old one.
+    fn gen_grow(
+        &self,
+        old: PointerValue<'ctx>,
+        used: IntValue<'ctx>,
+        size: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let b = &self.builder;
+        let i8_ptr_ty = self.ctx.i8_type().ptr_type(AddressSpace::Generic);
+        let i64_type = self.ctx.i64_type();
+        let fn_type =
+            i8_ptr_ty.fn_type(&[i8_ptr_ty.into(), i64_type.into(), i64_type.into()], false);
+        let func = Intrinsics::get_or_declare(&self.module, RT_GROW, fn_type);
+        let old_raw = b.build_pointer_cast(old, i8_ptr_ty, "old");
+        let raw = b
+            .build_call(func, &[old_raw.into(), used.into(), size.into()], "buf")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        b.build_pointer_cast(raw, old.get_type(), "buf")
+    }
+
+    /// Generate the loop of For statement.
+    ///
+    /// The loop calls the function of loop body in each iteration, with
+    /// builder, index, item, environment and captured symbols.
+    /// Start and end of first iterator decide the number of iterations,
+    /// and the other iterators start at their own offsets.
+    /// Same as the interpreter, a range out of its vector or iterators
+    /// differing in length report an error and the loop is skipped.
+    fn gen_for(
+        &mut self,
+        iters: &[StmtIter],
+        builder: &Symbol,
+        env: Option<&Symbol>,
+        func: FunctionId,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let i64_type = self.ctx.i64_type();
+        let b = &self.builder;
+        let mut datas = Vec::with_capacity(iters.len());
+        let mut count = None;
+        let mut invalid = self.ctx.bool_type().const_zero();
+        for it in iters {
+            let data = self.load(&it.data)?.into_struct_value();
+            let ptr = self.extract(data, 0).into_pointer_value();
+            let len = self.extract(data, 1).into_int_value();
+            let start = match &it.start {
+                Some(s) => self.load(s)?.into_int_value(),
+                None => i64_type.const_zero(),
+            };
+            let end = match &it.end {
+                Some(e) => self.load(e)?.into_int_value(),
+                None => len,
+            };
+            let reversed = b.build_int_compare(IntPredicate::UGT, start, end, "reversed");
+            let overrun = b.build_int_compare(IntPredicate::UGT, end, len, "overrun");
+            invalid = b.build_or(invalid, reversed, "invalid");
+            invalid = b.build_or(invalid, overrun, "invalid");
+            let n = b.build_int_sub(end, start, "n");
+            match count {
+                Some(count) => {
+                    let differ = b.build_int_compare(IntPredicate::NE, count, n, "differ");
+                    invalid = b.build_or(invalid, differ, "invalid");
+                }
+                None => count = Some(n),
+            }
+            datas.push((ptr, start));
+        }
+        let count = count.ok_or_else(|| compile_err!("No iterator in for loop"))?;
+        self.gen_check(invalid, RT_ERR_INVALID_ITERATOR);
+        let count = b
+            .build_select(invalid, i64_type.const_zero(), count, "count")
+            .into_int_value();
+        let item_ty = if iters.len() == 1 {
+            None
+        } else {
+            let tys = iters
+                .iter()
+                .map(|it| match &it.data.ty {
+                    Type::Vector(VectorType { item_ty }) => Ok(item_ty.as_ref().clone()),
+                    other => Err(compile_err!("Invalid iterator type[{}]", other)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Some(
+                self.llvm_type(&Type::Tuple(TupleType(tys)))?
+                    .into_struct_type(),
+            )
+        };
+        let env = env.map(|sym| self.load(sym)).transpose()?;
+        let captures = self.captures[func.0]
+            .iter()
+            .map(|sym| self.load(sym))
+            .collect::<Result<Vec<_>>>()?;
+        let init = self.load(builder)?;
+
+        let fn_value = self.current_function();
+        let pre_bb = self.builder.get_insert_block().unwrap();
+        let cond_bb = self.ctx.append_basic_block(fn_value, "for.cond");
+        let body_bb = self.ctx.append_basic_block(fn_value, "for.body");
+        let exit_bb = self.ctx.append_basic_block(fn_value, "for.exit");
+        self.builder.build_unconditional_branch(cond_bb);
+
+        self.builder.position_at_end(cond_bb);
+        let i_phi = self.builder.build_phi(i64_type, "i");
+        let b_phi = self.builder.build_phi(init.get_type(), "b");
+        let i = i_phi.as_basic_value().into_int_value();
+        let c = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, i, count, "c");
+        self.builder.build_conditional_branch(c, body_bb, exit_bb);
+
+        self.builder.position_at_end(body_bb);
+        let mut items = Vec::with_capacity(datas.len());
+        for (ptr, start) in datas {
+            let idx = self.builder.build_int_add(start, i, "idx");
+            let item_ptr = unsafe { self.builder.build_in_bounds_gep(ptr, &[idx], "item") };
+            items.push(self.builder.build_load(item_ptr, "item"));
+        }
+        let item = match item_ty {
+            Some(ty) => self.build_struct(ty.get_undef(), &items).into(),
+            None => items[0],
+        };
+        let mut args = vec![b_phi.as_basic_value(), i.into(), item];
+        args.extend(env);
+        args.extend(captures);
+        let next_b = self
+            .builder
+            .build_call(self.funcs[func.0], &args, "b")
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| compile_err!("Function {} returns no value", func))?;
+        let next_i = self
+            .builder
+            .build_int_add(i, i64_type.const_int(1, false), "i");
+        self.builder.build_unconditional_branch(cond_bb);
+        i_phi.add_incoming(&[(&i64_type.const_zero(), pre_bb), (&next_i, body_bb)]);
+        b_phi.add_incoming(&[(&init, pre_bb), (&next_b, body_bb)]);
+
+        self.builder.position_at_end(exit_bb);
+        Ok(b_phi.as_basic_value())
+    }
+
+    fn gen_literal(&self, lit: &Literal) -> Result<BasicValueEnum<'ctx>> {
+        let value = match lit {
+            Literal::Bool(v) => self.ctx.bool_type().const_int(*v as u64, false).into(),
+            Literal::U8(v) => self.ctx.i8_type().const_int(*v as u64, false).into(),
+            Literal::I32(v) => self.ctx.i32_type().const_int(*v as u64, true).into(),
+            Literal::U32(v) => self.ctx.i32_type().const_int(*v as u64, false).into(),
+            Literal::I64(v) => self.ctx.i64_type().const_int(*v as u64, true).into(),
+            Literal::U64(v) => self.ctx.i64_type().const_int(*v, false).into(),
+            Literal::F32(v) => self
+                .ctx
+                .f32_type()
+                .const_float(f32::from_bits(*v) as f64)
+                .into(),
+            Literal::F64(v) => self.ctx.f64_type().const_float(f64::from_bits(*v)).into(),
+            Literal::Str(_) => {
+                return Err(compile_err!(
+                    "Unsupported literal {} in code generation",
+                    lit
+                ))
+            }
+        };
+        Ok(value)
+    }
+
+    fn gen_bin_op(
+        &self,
+        op_ty: BinOpType,
+        ty: &Type,
+        left: BasicValueEnum<'ctx>,
+        right: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+        if ty.is_float() {
+            let (l, r) = (left.into_float_value(), right.into_float_value());
+            if let Some(pred) = float_predicate(op_ty) {
+                return Ok(b.build_float_compare(pred, l, r, "cmp").into());
+            }
+            let value = match op_ty {
+                BinOpType::Add => b.build_float_add(l, r, "add").into(),
+                BinOpType::Subtract => b.build_float_sub(l, r, "sub").into(),
+                BinOpType::Multiply => b.build_float_mul(l, r, "mul").into(),
+                BinOpType::Divide => b.build_float_div(l, r, "div").into(),
+                BinOpType::Modulo => b.build_float_rem(l, r, "rem").into(),
+                BinOpType::Max => {
+                    let cond = b.build_float_compare(FloatPredicate::OGT, l, r, "gt");
+                    b.build_select(cond, l, r, "max")
+                }
+                BinOpType::Min => {
+                    let cond = b.build_float_compare(FloatPredicate::OLT, l, r, "lt");
+                    b.build_select(cond, l, r, "min")
+                }
+                BinOpType::Pow | BinOpType::Atan2 => {
+                    let name = match ty {
+                        Type::F32(t) => Intrinsics::binary_math(op_ty, *t),
+                        Type::F64(t) => Intrinsics::binary_math(op_ty, *t),
+                        _ => None,
+                    }
+                    .ok_or_else(|| {
+                        compile_err!("incompatible type[{}] in {} operation", ty, op_ty)
+                    })?;
+                    self.gen_math_call(&name, &[left, right])?
+                }
+                _ => {
+                    return Err(compile_err!(
+                        "incompatible type[{}] in {} operation",
+                        ty,
+                        op_ty
+                    ))
+                }
+            };
+            return Ok(value);
+        }
+        if !ty.is_integer() && !ty.is_bool() {
+            return Err(compile_err!(
+                "incompatible type[{}] in {} operation",
+                ty,
+                op_ty
+            ));
+        }
+        let (l, r) = (left.into_int_value(), right.into_int_value());
+        let signed = ty.is_signed();
+        if let Some(pred) = int_predicate(op_ty, signed) {
+            return Ok(b.build_int_compare(pred, l, r, "cmp").into());
+        }
+        let value = match op_ty {
+            BinOpType::Add => b.build_int_add(l, r, "add").into(),
+            BinOpType::Subtract => b.build_int_sub(l, r, "sub").into(),
+            BinOpType::Multiply => b.build_int_mul(l, r, "mul").into(),
+            BinOpType::Divide | BinOpType::Modulo => self.gen_int_div(op_ty, signed, l, r),
+            BinOpType::LogicalAnd | BinOpType::BitwiseAnd => b.build_and(l, r, "and").into(),
+            BinOpType::LogicalOr | BinOpType::BitwiseOr => b.build_or(l, r, "or").into(),
+            BinOpType::Xor => b.build_xor(l, r, "xor").into(),
+            BinOpType::ShiftLeft | BinOpType::ShiftRight => self.gen_int_shift(op_ty, signed, l, r),
+            BinOpType::Max => {
+                let pred = if signed {
+                    IntPredicate::SGT
+                } else {
+                    IntPredicate::UGT
+                };
+                let cond = b.build_int_compare(pred, l, r, "gt");
+                b.build_select(cond, l, r, "max")
+            }
+            BinOpType::Min => {
+                let pred = if signed {
+                    IntPredicate::SLT
+                } else {
+                    IntPredicate::ULT
+                };
+                let cond = b.build_int_compare(pred, l, r, "lt");
+                b.build_select(cond, l, r, "min")
+            }
+            BinOpType::Pow | BinOpType::Atan2 => {
+                return Err(compile_err!(
+                    "incompatible type[{}] in {} operation",
+                    ty,
+                    op_ty
+                ))
+            }
+            // comparisons are handled above
+            _ => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    /// Generate integer division or remainder.
+    ///
+    /// Dividing by zero reports an error, and minimum divided by -1 wraps,
+    /// so neither reaches undefined behaviour of LLVM.
+    fn gen_int_div(
+        &self,
+        op_ty: BinOpType,
+        signed: bool,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let b = &self.builder;
+        let int_type = r.get_type();
+        let zero = int_type.const_zero();
+        let one = int_type.const_int(1, false);
+        let is_zero = b.build_int_compare(IntPredicate::EQ, r, zero, "is_zero");
+        self.gen_check(is_zero, RT_ERR_DIVIDE_BY_ZERO);
+        if !signed {
+            let r = b.build_select(is_zero, one, r, "divisor").into_int_value();
+            return match op_ty {
+                BinOpType::Divide => b.build_int_unsigned_div(l, r, "div").into(),
+                _ => b.build_int_unsigned_rem(l, r, "rem").into(),
+            };
+        }
+        let minus_one = int_type.const_all_ones();
+        let is_minus_one = b.build_int_compare(IntPredicate::EQ, r, minus_one, "is_minus_one");
+        let trivial = b.build_or(is_zero, is_minus_one, "trivial");
+        let divisor = b.build_select(trivial, one, r, "divisor").into_int_value();
+        match op_ty {
+            BinOpType::Divide => {
+                let div = b.build_int_signed_div(l, divisor, "div");
+                let neg = b.build_int_neg(l, "neg");
+                b.build_select(is_minus_one, neg, div, "div")
+            }
+            _ => {
+                let rem = b.build_int_signed_rem(l, divisor, "rem");
+                b.build_select(is_minus_one, zero, rem, "rem")
+            }
+        }
+    }
+
+    /// Generate integer shift.
+    ///
+    /// Amount not less than the bit width, including negative amounts,
+    /// reports an error instead of producing poison value.
+    fn gen_int_shift(
+        &self,
+        op_ty: BinOpType,
+        signed: bool,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let b = &self.builder;
+        let int_type = r.get_type();
+        let width = int_type.const_int(int_type.get_bit_width() as u64, false);
+        let out = b.build_int_compare(IntPredicate::UGE, r, width, "out_of_range");
+        self.gen_check(out, RT_ERR_SHIFT_OUT_OF_RANGE);
+        let r = b
+            .build_select(out, int_type.const_zero(), r, "amount")
+            .into_int_value();
+        match op_ty {
+            BinOpType::ShiftLeft => b.build_left_shift(l, r, "shl").into(),
+            _ => b.build_right_shift(l, r, signed, "shr").into(),
+        }
+    }
+
+    /// Call runtime to report error of given code if failed is true,
+    /// then continue in a new block.
+    fn gen_check(&self, failed: IntValue<'ctx>, code: u32) {
+        let b = &self.builder;
+        let fn_value = self.current_function();
+        let fail_bb = self.ctx.append_basic_block(fn_value, "check.fail");
+        let cont_bb = self.ctx.append_basic_block(fn_value, "check.cont");
+        b.build_conditional_branch(failed, fail_bb, cont_bb);
+
+        b.position_at_end(fail_bb);
+        let i32_type = self.ctx.i32_type();
+        let fn_type = self.ctx.void_type().fn_type(&[i32_type.into()], false);
+        let func = Intrinsics::get_or_declare(&self.module, RT_ERROR, fn_type);
+        let code = i32_type.const_int(code as u64, false);
+        b.build_call(func, &[code.into()], "");
+        b.build_unconditional_branch(cont_bb);
+
+        b.position_at_end(cont_bb);
+    }
+
+    fn gen_unary_op(
+        &self,
+        op_ty: UnaryOpType,
+        ty: &Type,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+        match op_ty {
+            UnaryOpType::Not if ty.is_bool() => {
+                Ok(b.build_not(value.into_int_value(), "not").into())
+            }
+            UnaryOpType::Neg if ty.is_float() => {
+                Ok(b.build_float_neg(value.into_float_value(), "neg").into())
+            }
+            UnaryOpType::Neg if ty.is_integer() => {
+                Ok(b.build_int_neg(value.into_int_value(), "neg").into())
+            }
+            UnaryOpType::Abs if ty.is_integer() => {
+                if !ty.is_signed() {
+                    return Ok(value);
+                }
+                // LLVM 10 has no intrinsic of integer abs
+                let v = value.into_int_value();
+                let cond =
+                    b.build_int_compare(IntPredicate::SLT, v, v.get_type().const_zero(), "lt");
+                let neg = b.build_int_neg(v, "neg");
+                Ok(b.build_select(cond, neg, v, "abs"))
+            }
+            _ => {
+                let name = match ty {
+                    Type::F32(t) => Intrinsics::unary_math(op_ty, *t),
+                    Type::F64(t) => Intrinsics::unary_math(op_ty, *t),
+                    _ => None,
+                }
+                .ok_or_else(|| compile_err!("incompatible type[{}] in {} operation", ty, op_ty))?;
+                self.gen_math_call(&name, &[value])
+            }
+        }
+    }
+
+    /// Call the math function of given name, all arguments and result
+    /// have the same type.
+    fn gen_math_call(
+        &self,
+        name: &str,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let ty = args[0].get_type();
+        let arg_tys: Vec<_> = args.iter().map(|arg| arg.get_type()).collect();
+        let fn_type = ty.fn_type(&arg_tys, false);
+        let func = Intrinsics::get_or_declare(&self.module, name, fn_type);
+        self.builder
+            .build_call(func, args, name)
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| compile_err!("Function {} returns no value", name))
+    }
+
+    fn gen_cast(
+        &self,
+        from: &Type,
+        to: &Type,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+        let to_ty = self.llvm_type(to)?;
+        // cast to bool is same as comparing with zero
+        if to.is_bool() {
+            let r = if from.is_float() {
+                let v = value.into_float_value();
+                let zero = v.get_type().const_zero();
+                b.build_float_compare(FloatPredicate::UNE, v, zero, "cast")
+            } else {
+                let v = value.into_int_value();
+                let zero = v.get_type().const_zero();
+                b.build_int_compare(IntPredicate::NE, v, zero, "cast")
+            };
+            return Ok(r.into());
+        }
+        let r = match (from.is_float(), to.is_float()) {
+            (true, true) => {
+                let v = value.into_float_value();
+                match (from, to) {
+                    (Type::F32(_), Type::F64(_)) => {
+                        b.build_float_ext(v, to_ty.into_float_type(), "cast").into()
+                    }
+                    (Type::F64(_), Type::F32(_)) => b
+                        .build_float_trunc(v, to_ty.into_float_type(), "cast")
+                        .into(),
+                    _ => value,
+                }
+            }
+            (true, false) => self.gen_float_to_int(
+                value.into_float_value(),
+                to_ty.into_int_type(),
+                to.is_signed(),
+            ),
+            (false, true) => {
+                let v = value.into_int_value();
+                if from.is_signed() {
+                    b.build_signed_int_to_float(v, to_ty.into_float_type(), "cast")
+                        .into()
+                } else {
+                    b.build_unsigned_int_to_float(v, to_ty.into_float_type(), "cast")
+                        .into()
+                }
+            }
+            (false, false) => {
+                let v = value.into_int_value();
+                let int_ty = to_ty.into_int_type();
+                let from_bits = v.get_type().get_bit_width();
+                let to_bits = int_ty.get_bit_width();
+                if from_bits > to_bits {
+                    b.build_int_truncate(v, int_ty, "cast").into()
+                } else if from_bits == to_bits {
+                    value
+                } else if from.is_signed() {
+                    b.build_int_s_extend(v, int_ty, "cast").into()
+                } else {
+                    b.build_int_z_extend(v, int_ty, "cast").into()
+                }
+            }
+        };
+        Ok(r)
+    }
+
+    /// Convert float to integer towards zero, saturating at bounds of the
+    /// integer type with NaN converted to zero, same as the interpreter.
+    ///
+    /// Conversion instructions of LLVM give poison on values out of range,
+    /// which are replaced by selects.
+    fn gen_float_to_int(
+        &self,
+        v: FloatValue<'ctx>,
+        int_ty: IntType<'ctx>,
+        signed: bool,
+    ) -> BasicValueEnum<'ctx> {
+        let b = &self.builder;
+        let float_ty = v.get_type();
+        let bits = int_ty.get_bit_width();
+        // bounds are powers of two, exact in both float types
+        let (conv, lower, upper, min, max) = if signed {
+            let bound = 2f64.powi(bits as i32 - 1);
+            (
+                b.build_float_to_signed_int(v, int_ty, "conv"),
+                float_ty.const_float(-bound),
+                float_ty.const_float(bound),
+                int_ty.const_int(1 << (bits - 1), false),
+                int_ty.const_int((1 << (bits - 1)) - 1, false),
+            )
+        } else {
+            // values in (-1, 0) are converted to zero as well
+            (
+                b.build_float_to_unsigned_int(v, int_ty, "conv"),
+                float_ty.const_zero(),
+                float_ty.const_float(2f64.powi(bits as i32)),
+                int_ty.const_zero(),
+                int_ty.const_all_ones(),
+            )
+        };
+        let below = b.build_float_compare(FloatPredicate::OLT, v, lower, "below");
+        let above = b.build_float_compare(FloatPredicate::OGE, v, upper, "above");
+        let nan = b.build_float_compare(FloatPredicate::UNO, v, v, "nan");
+        let r = b.build_select(below, min, conv, "cast").into_int_value();
+        let r = b.build_select(above, max, r, "cast").into_int_value();
+        b.build_select(nan, int_ty.const_zero(), r, "cast")
+    }
+
+    fn alloca(&mut self, sym: &Symbol) -> Result<PointerValue<'ctx>> {
+        let ty = self.llvm_type(&sym.ty)?;
+        let ptr = self.builder.build_alloca(ty, &sym.to_string());
+        self.locals.insert(sym.clone(), ptr);
+        Ok(ptr)
+    }
+
+    fn extract(&self, value: StructValue<'ctx>, index: u32) -> BasicValueEnum<'ctx> {
+        self.builder
+            .build_extract_value(value, index, "field")
+            .unwrap()
+    }
+
+    fn build_struct(
+        &self,
+        init: StructValue<'ctx>,
+        values: &[BasicValueEnum<'ctx>],
+    ) -> StructValue<'ctx> {
+        values.iter().enumerate().fold(init, |acc, (i, v)| {
+            self.builder
+                .build_insert_value(acc, *v, i as u32, "field")
+                .unwrap()
+                .into_struct_value()
+        })
+    }
+
+    fn current_function(&self) -> FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .and_then(|bb| bb.get_parent())
+            .unwrap()
+    }
+
+    fn load(&self, sym: &Symbol) -> Result<BasicValueEnum<'ctx>> {
+        let ptr = self
+            .locals
+            .get(sym)
+            .ok_or_else(|| compile_err!("Undefined symbol {} in code generation", sym))?;
+        Ok(self.builder.build_load(*ptr, &sym.to_string()))
+    }
+
+    /// Returns the LLVM type of given type.
+    pub fn llvm_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>> {
+        let llvm_ty = match ty {
+            Type::Bool(_) => self.ctx.bool_type().into(),
+            Type::U8(_) => self.ctx.i8_type().into(),
+            Type::U32(_) | Type::I32(_) => self.ctx.i32_type().into(),
+            Type::U64(_) | Type::I64(_) => self.ctx.i64_type().into(),
+            Type::F32(_) => self.ctx.f32_type().into(),
+            Type::F64(_) => self.ctx.f64_type().into(),
+            Type::Merger(MergerType { item_ty, .. }) => self.llvm_type(item_ty)?,
+            Type::Vector(VectorType { item_ty }) => {
+                let ptr_ty = self.llvm_type(item_ty)?.ptr_type(AddressSpace::Generic);
+                self.ctx
+                    .struct_type(&[ptr_ty.into(), self.ctx.i64_type().into()], false)
+                    .into()
+            }
+            Type::Appender(AppenderType { item_ty }) => {
+                let ptr_ty = self.llvm_type(item_ty)?.ptr_type(AddressSpace::Generic);
+                let i64_type = self.ctx.i64_type();
+                self.ctx
+                    .struct_type(&[ptr_ty.into(), i64_type.into(), i64_type.into()], false)
+                    .into()
+            }
+            Type::Tuple(TupleType(tys)) => {
+                let fields = tys
+                    .iter()
+                    .map(|ty| self.llvm_type(ty))
+                    .collect::<Result<Vec<_>>>()?;
+                self.ctx.struct_type(&fields, false).into()
+            }
+            other => {
+                return Err(compile_err!(
+                    "Unsupported type[{}] in code generation",
+                    other
+                ))
+            }
+        };
+        Ok(llvm_ty)
+    }
+}
+
+fn int_predicate(op_ty: BinOpType, signed: bool) -> Option<IntPredicate> {
+    let pred = match (op_ty, signed) {
+        (BinOpType::Equal, _) => IntPredicate::EQ,
+        (BinOpType::NotEqual, _) => IntPredicate::NE,
+        (BinOpType::LessThan, true) => IntPredicate::SLT,
+        (BinOpType::LessThan, false) => IntPredicate::ULT,
+        (BinOpType::LessThanOrEqual, true) => IntPredicate::SLE,
+        (BinOpType::LessThanOrEqual, false) => IntPredicate::ULE,
+        (BinOpType::GreaterThan, true) => IntPredicate::SGT,
+        (BinOpType::GreaterThan, false) => IntPredicate::UGT,
+        (BinOpType::GreaterThanOrEqual, true) => IntPredicate::SGE,
+        (BinOpType::GreaterThanOrEqual, false) => IntPredicate::UGE,
+        _ => return None,
+    };
+    Some(pred)
+}
+
+/// Returns the ordered predicate of comparison, so comparisons on NaN
+/// are false except not equal, same as the interpreter.
+fn float_predicate(op_ty: BinOpType) -> Option<FloatPredicate> {
+    let pred = match op_ty {
+        BinOpType::Equal => FloatPredicate::OEQ,
+        BinOpType::NotEqual => FloatPredicate::UNE,
+        BinOpType::LessThan => FloatPredicate::OLT,
+        BinOpType::LessThanOrEqual => FloatPredicate::OLE,
+        BinOpType::GreaterThan => FloatPredicate::OGT,
+        BinOpType::GreaterThanOrEqual => FloatPredicate::OGE,
+        _ => return None,
+    };
+    Some(pred)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sir::lower;
+    use inkwell::execution_engine::JitFunction;
+    use inkwell::OptimizationLevel;
+
+    type AbsDiffFunc = unsafe extern "C" fn(i32, i32) -> i32;
+    type SqrtFunc = unsafe extern "C" fn(f64) -> f64;
+
+    fn lambda(params: Vec<Symbol>, body: Expr) -> Expr {
+        Expr::Lambda(Lambda {
+            params,
+            body: Box::new(body),
+        })
+    }
+
+    #[test]
+    fn test_codegen_branch() -> Result<()> {
+        let x = Symbol::named("x", I32);
+        let y = Symbol::named("y", I32);
+        let (vx, vy) = (Expr::Symbol(x.clone()), Expr::Symbol(y.clone()));
+        let body = Expr::IfThenElse(IfThenElse {
+            i: Box::new(Expr::BinOp(BinOp {
+                op_ty: BinOpType::GreaterThan,
+                left: Box::new(vx.clone()),
+                right: Box::new(vy.clone()),
+            })),
+            t: Box::new(Expr::BinOp(BinOp::sub(vx.clone(), vy.clone()))),
+            e: Box::new(Expr::BinOp(BinOp::sub(vy, vx))),
+        });
+        let prog = lower(&lambda(vec![x, y], body))?;
+
+        let ctx = Context::create();
+        let mut cg = CodeGen::new(&ctx, "abs_diff");
+        let entry = cg.gen_program(&prog)?;
+        let name = entry.get_name().to_str().unwrap().to_owned();
+        let exec = cg
+            .module()
+            .create_jit_execution_engine(OptimizationLevel::None)?;
+        let f: JitFunction<AbsDiffFunc> = unsafe { exec.get_function(&name)? };
+        unsafe {
+            assert_eq!(3, f.call(5, 2));
+            assert_eq!(4, f.call(-1, 3));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_codegen_intrinsic_and_cast() -> Result<()> {
+        let x = Symbol::named("x", F64);
+        let sqrt = Expr::UnaryOp(UnaryOp {
+            op_ty: UnaryOpType::Sqrt,
+            value: Box::new(Expr::Symbol(x.clone())),
+        });
+        // cast sqrt(x) to i64 and back to drop the fraction
+        let body = Expr::Cast(Cast {
+            ty: F64.into(),
+            value: Box::new(Expr::Cast(Cast {
+                ty: I64.into(),
+                value: Box::new(sqrt),
+            })),
+        });
+        let prog = lower(&lambda(vec![x], body))?;
+
+        let ctx = Context::create();
+        let mut cg = CodeGen::new(&ctx, "sqrt");
+        let entry = cg.gen_program(&prog)?;
+        let name = entry.get_name().to_str().unwrap().to_owned();
+        let exec = cg
+            .module()
+            .create_jit_execution_engine(OptimizationLevel::None)?;
+        let f: JitFunction<SqrtFunc> = unsafe { exec.get_function(&name)? };
+        unsafe {
+            assert_eq!(3.0, f.call(10.0));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_codegen_pow_abs_floor() -> Result<()> {
+        let x = Symbol::named("x", F64);
+        let xv = Var::<F64>::clone_symbol(x.clone());
+        let body = xv.clone().abs().pow(Var::lit_f64(3.0)) + xv.floor();
+        let prog = lower(&lambda(vec![x], body.expr))?;
+
+        let ctx = Context::create();
+        let mut cg = CodeGen::new(&ctx, "pow_abs_floor");
+        let entry = cg.gen_program(&prog)?;
+        let name = entry.get_name().to_str().unwrap().to_owned();
+        let exec = cg
+            .module()
+            .create_jit_execution_engine(OptimizationLevel::None)?;
+        let f: JitFunction<SqrtFunc> = unsafe { exec.get_function(&name)? };
+        unsafe {
+            assert_eq!(12.625, f.call(-2.5));
+            assert_eq!(10.0, f.call(2.0));
+        }
+        Ok(())
+    }
+}
@@ -7,6 +7,8 @@ pub enum Error {
     #[error("{0}")]
     CompileError(String),
     #[error("{0}")]
+    RuntimeError(String),
+    #[error("{0}")]
     LLVMError(String),
     #[error("{0}")]
     FunctionLookupError(#[from] FunctionLookupError),
@@ -18,6 +20,12 @@ macro_rules! compile_err {
     }
 }
 
+macro_rules! runtime_err {
+    ( $($arg:tt)* ) => {
+        $crate::Error::RuntimeError(format!($($arg)*))
+    }
+}
+
 impl From<LLVMString> for Error {
     fn from(src: LLVMString) -> Self {
         Error::LLVMError(src.to_string())
@@ -0,0 +1,107 @@
+//! Marshal values between Rust and generated code.
+use crate::ast::*;
+use crate::codegen::layout::{size_align, struct_layout};
+use crate::runtime::Value;
+use crate::Result;
+use std::ptr::{read_unaligned, write_unaligned};
+
+/// Encoder writes values into memory in layout of generated code.
+///
+/// Items of vectors are written into buffers owned by the encoder,
+/// so the encoder must outlive the execution.
+pub(super) struct Encoder {
+    buffers: Vec<Vec<u64>>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder { buffers: vec![] }
+    }
+
+    /// Allocate a zeroed buffer of at least size bytes, aligned to 8 bytes.
+    pub fn alloc(&mut self, size: usize) -> *mut u8 {
+        let mut buf = vec![0u64; ((size + 7) / 8).max(1)];
+        let ptr = buf.as_mut_ptr() as *mut u8;
+        self.buffers.push(buf);
+        ptr
+    }
+
+    /// Write the value of given type at ptr.
+    ///
+    /// # Safety
+    ///
+    /// ptr must be valid for writes of the size of given type.
+    pub unsafe fn encode(&mut self, value: &Value, ty: &Type, ptr: *mut u8) -> Result<()> {
+        match (value, ty) {
+            (Value::Bool(v), Type::Bool(_)) => write_unaligned(ptr, *v as u8),
+            (Value::U8(v), Type::U8(_)) => write_unaligned(ptr, *v),
+            (Value::U32(v), Type::U32(_)) => write_unaligned(ptr as *mut u32, *v),
+            (Value::I32(v), Type::I32(_)) => write_unaligned(ptr as *mut i32, *v),
+            (Value::U64(v), Type::U64(_)) => write_unaligned(ptr as *mut u64, *v),
+            (Value::I64(v), Type::I64(_)) => write_unaligned(ptr as *mut i64, *v),
+            (Value::F32(v), Type::F32(_)) => write_unaligned(ptr as *mut f32, *v),
+            (Value::F64(v), Type::F64(_)) => write_unaligned(ptr as *mut f64, *v),
+            (Value::Vector(items), Type::Vector(VectorType { item_ty })) => {
+                let (item_size, _) = size_align(item_ty)?;
+                let data = self.alloc(item_size * items.len());
+                for (i, item) in items.iter().enumerate() {
+                    self.encode(item, item_ty, data.add(i * item_size))?;
+                }
+                write_unaligned(ptr as *mut usize, data as usize);
+                write_unaligned(ptr.add(8) as *mut u64, items.len() as u64);
+            }
+            (Value::Tuple(items), Type::Tuple(TupleType(tys))) if items.len() == tys.len() => {
+                let (offsets, _, _) = struct_layout(tys)?;
+                for ((item, ty), offset) in items.iter().zip(tys).zip(offsets) {
+                    self.encode(item, ty, ptr.add(offset))?;
+                }
+            }
+            (value, ty) => return Err(runtime_err!("Value {} does not match type[{}]", value, ty)),
+        }
+        Ok(())
+    }
+}
+
+/// Read the value of given type at ptr.
+///
+/// # Safety
+///
+/// ptr must point to a valid value of given type in layout of generated code.
+pub(super) unsafe fn decode(ty: &Type, ptr: *const u8) -> Result<Value> {
+    let value = match ty {
+        Type::Bool(_) => Value::Bool(read_unaligned(ptr) != 0),
+        Type::U8(_) => Value::U8(read_unaligned(ptr)),
+        Type::U32(_) => Value::U32(read_unaligned(ptr as *const u32)),
+        Type::I32(_) => Value::I32(read_unaligned(ptr as *const i32)),
+        Type::U64(_) => Value::U64(read_unaligned(ptr as *const u64)),
+        Type::I64(_) => Value::I64(read_unaligned(ptr as *const i64)),
+        Type::F32(_) => Value::F32(read_unaligned(ptr as *const f32)),
+        Type::F64(_) => Value::F64(read_unaligned(ptr as *const f64)),
+        Type::Vector(VectorType { item_ty }) => {
+            let (item_size, _) = size_align(item_ty)?;
+            let data = read_unaligned(ptr as *const usize) as *const u8;
+            let len = read_unaligned(ptr.add(8) as *const u64) as usize;
+            let mut items = Vec::with_capacity(len);
+            for i in 0..len {
+                items.push(decode(item_ty, data.add(i * item_size))?);
+            }
+            Value::Vector(items)
+        }
+        Type::Tuple(TupleType(tys)) => {
+            let (offsets, _, _) = struct_layout(tys)?;
+            let items = tys
+                .iter()
+                .zip(offsets)
+                .map(|(ty, offset)| decode(ty, ptr.add(offset)))
+                .collect::<Result<Vec<_>>>()?;
+            Value::Tuple(items)
+        }
+        other => {
+            return Err(runtime_err!(
+                "Unsupported type[{}] of program output",
+                other
+            ))
+        }
+    };
+    Ok(value)
+}
@@ -0,0 +1,307 @@
+//! JIT module compiles program into native code and runs it in process.
+mod marshal;
+mod rt;
+
+use crate::ast::*;
+use crate::codegen::layout::{size_align, struct_layout};
+use crate::codegen::CodeGen;
+use crate::opt::closure_convert;
+use crate::runtime::Value;
+use crate::sir::lower_program;
+use crate::stage::Program;
+use crate::sym::{check_linearity_program, typecheck_program, uniquify_program};
+use crate::Result;
+use inkwell::context::Context;
+use inkwell::execution_engine::{ExecutionEngine, JitFunction};
+use inkwell::module::Module;
+use inkwell::OptimizationLevel;
+use marshal::{decode, Encoder};
+
+const ENTRY_NAME: &str = "lms_entry";
+
+type EntryFunc = unsafe extern "C" fn(*const u8, *mut u8);
+
+/// Compile the program into native code.
+///
+/// Parameters of the program are inputs of the compiled program.
+pub fn compile<'ctx>(ctx: &'ctx Context, program: &Program) -> Result<CompiledProgram<'ctx>> {
+    let mut program = program.clone();
+    uniquify_program(&mut program)?;
+    closure_convert(&mut program.body)?;
+    typecheck_program(&program)?;
+    check_linearity_program(&program)?;
+    let sir = lower_program(&program)?;
+    let entry = sir.entry();
+    let params_ty = entry.params.iter().map(|p| p.ty.clone()).collect();
+    let ret_ty = entry.ret_ty.clone();
+
+    let mut cg = CodeGen::new(ctx, "lms");
+    cg.gen_program(&sir)?;
+    cg.gen_entry_wrapper(&sir, ENTRY_NAME)?;
+    let module = cg.into_module();
+    module.verify()?;
+    let exec = module.create_jit_execution_engine(OptimizationLevel::Default)?;
+    rt::register(&module, &exec);
+    Ok(CompiledProgram {
+        module,
+        exec,
+        params_ty,
+        ret_ty,
+    })
+}
+
+/// CompiledProgram is the native code of a program, ready to run.
+pub struct CompiledProgram<'ctx> {
+    module: Module<'ctx>,
+    exec: ExecutionEngine<'ctx>,
+    params_ty: Vec<Type>,
+    ret_ty: Type,
+}
+
+impl<'ctx> CompiledProgram<'ctx> {
+    /// Returns types of the program inputs.
+    pub fn params_ty(&self) -> &[Type] {
+        &self.params_ty
+    }
+
+    /// Returns type of the program output.
+    pub fn ret_ty(&self) -> &Type {
+        &self.ret_ty
+    }
+
+    /// Returns the generated LLVM IR.
+    pub fn ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
+
+    /// Run the program with given inputs and returns its output.
+    ///
+    /// Inputs are checked against parameter types of the program, and
+    /// errors reported by the generated code, e.g. division by zero,
+    /// are returned same as the interpreter.
+    pub fn run(&self, inputs: &[Value]) -> Result<Value> {
+        if inputs.len() != self.params_ty.len() {
+            return Err(runtime_err!(
+                "Program requires {} inputs but {} provided",
+                self.params_ty.len(),
+                inputs.len()
+            ));
+        }
+        let mut enc = Encoder::new();
+        let (offsets, args_size, _) = struct_layout(&self.params_ty)?;
+        let args = enc.alloc(args_size);
+        for ((input, ty), offset) in inputs.iter().zip(&self.params_ty).zip(offsets) {
+            input.check_type(ty)?;
+            unsafe { enc.encode(input, ty, args.add(offset))? }
+        }
+        let (ret_size, _) = size_align(&self.ret_ty)?;
+        let ret = enc.alloc(ret_size);
+        let f: JitFunction<EntryFunc> = unsafe { self.exec.get_function(ENTRY_NAME)? };
+        let output = unsafe {
+            f.call(args, ret);
+            // the result is unspecified once an error is reported
+            match rt::take_error() {
+                Some(err) => Err(err),
+                None => decode(&self.ret_ty, ret),
+            }
+        };
+        // buffers allocated by generated code are copied in decoding
+        rt::clear_arena();
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::runtime::interpret_program;
+    use crate::sym::Symbol;
+
+    fn vec_ty<T: Into<Type>>(item_ty: T) -> VectorType {
+        VectorType {
+            item_ty: Box::new(item_ty.into()),
+        }
+    }
+
+    #[test]
+    fn test_jit_merger_with_capture() -> Result<()> {
+        let params = vec![Symbol::named("v", vec_ty(I32)), Symbol::named("x", I32)];
+        let v = Var::clone_symbol(params[0].clone());
+        let x = Var::<I32>::clone_symbol(params[1].clone());
+        let m = Var::new_merger(I32, BinOpType::Add);
+        let body = m.pfor(v, move |b, _i, e: Var<I32>| b.merge(e * x)).eval();
+
+        let ctx = Context::create();
+        let prog = compile(&ctx, &Program::new(params, body))?;
+        let input = Value::Vector(vec![Value::I32(1), Value::I32(2), Value::I32(3)]);
+        assert_eq!(Value::I32(12), prog.run(&[input, Value::I32(2)])?);
+        let empty = Value::Vector(vec![]);
+        assert_eq!(Value::I32(0), prog.run(&[empty, Value::I32(2)])?);
+        assert!(prog.run(&[Value::I32(2)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jit_division_and_shift_errors() -> Result<()> {
+        let ctx = Context::create();
+        let compile_op = |op_ty: BinOpType| {
+            let params = vec![Symbol::named("x", I32), Symbol::named("y", I32)];
+            let body = Expr::BinOp(BinOp {
+                op_ty,
+                left: Box::new(Expr::Symbol(params[0].clone())),
+                right: Box::new(Expr::Symbol(params[1].clone())),
+            });
+            compile(&ctx, &Program::new(params, body))
+        };
+        let div = compile_op(BinOpType::Divide)?;
+        let rem = compile_op(BinOpType::Modulo)?;
+        let shl = compile_op(BinOpType::ShiftLeft)?;
+        let shr = compile_op(BinOpType::ShiftRight)?;
+        let args = |x, y| [Value::I32(x), Value::I32(y)];
+
+        assert_eq!(Value::I32(2), div.run(&args(7, 3))?);
+        assert_eq!(Value::I32(1), rem.run(&args(7, 3))?);
+        assert!(div.run(&args(7, 0)).is_err());
+        assert!(rem.run(&args(7, 0)).is_err());
+        // minimum divided by -1 wraps instead of trapping
+        assert_eq!(Value::I32(i32::MIN), div.run(&args(i32::MIN, -1))?);
+        assert_eq!(Value::I32(0), rem.run(&args(i32::MIN, -1))?);
+        // errors do not leak into the next run
+        assert_eq!(Value::I32(-3), div.run(&args(-7, 2))?);
+
+        assert_eq!(Value::I32(56), shl.run(&args(7, 3))?);
+        assert_eq!(Value::I32(-4), shr.run(&args(-7, 1))?);
+        assert!(shl.run(&args(1, 32)).is_err());
+        assert!(shr.run(&args(1, -1)).is_err());
+        assert_eq!(Value::I32(i32::MIN), shl.run(&args(1, 31))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_jit_zip_iterators() -> Result<()> {
+        let params = vec![
+            Symbol::named("x", vec_ty(I32)),
+            Symbol::named("y", vec_ty(I32)),
+        ];
+        let iter = |sym: &Symbol| Iter {
+            data: Box::new(Expr::Symbol(sym.clone())),
+            start: None,
+            end: None,
+        };
+        let merger = NewMerger {
+            item_ty: I32.into(),
+            op_ty: BinOpType::Add,
+        };
+        let b = Symbol::named("b", merger.ty());
+        let i = Symbol::named("i", U64);
+        let e = Symbol::named("e", TupleType(vec![I32.into(), I32.into()]));
+        let field = |index| {
+            Expr::GetField(GetField {
+                tuple: Box::new(Expr::Symbol(e.clone())),
+                index,
+            })
+        };
+        let merge = Expr::Merge(Merge {
+            builder: Box::new(Expr::Symbol(b.clone())),
+            value: Box::new(Expr::BinOp(BinOp::mul(field(0), field(1)))),
+        });
+        let pfor = For {
+            iters: vec![iter(&params[0]), iter(&params[1])],
+            builder: Box::new(Expr::NewMerger(merger)),
+            func: Box::new(Expr::Lambda(Lambda {
+                params: vec![b, i, e],
+                body: Box::new(merge),
+            })),
+            env: None,
+        };
+        let program = Program::new(params, Expr::Eval(Eval(Box::new(Expr::For(pfor)))));
+
+        let ctx = Context::create();
+        let prog = compile(&ctx, &program)?;
+        let vec = |items: &[i32]| Value::Vector(items.iter().copied().map(Value::I32).collect());
+        assert_eq!(Value::I32(11), prog.run(&[vec(&[1, 2]), vec(&[3, 4])])?);
+        // vectors of different lengths fail, same as the interpreter
+        for args in [
+            [vec(&[1, 2]), vec(&[3, 4, 5])],
+            [vec(&[1, 2, 3]), vec(&[3])],
+        ] {
+            assert!(interpret_program(&program, &args).is_err());
+            assert!(prog.run(&args).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_jit_float_semantics_match_interpreter() -> Result<()> {
+        let x = Symbol::named("x", F64);
+        let xv = Expr::Symbol(x.clone());
+        let cmp = |op_ty, right: &Expr| {
+            Expr::BinOp(BinOp {
+                op_ty,
+                left: Box::new(xv.clone()),
+                right: Box::new(right.clone()),
+            })
+        };
+        let cast = |ty: Type| {
+            Expr::Cast(Cast {
+                ty,
+                value: Box::new(xv.clone()),
+            })
+        };
+        let one = Expr::Literal(1f64.into());
+        let body = Expr::Tuple(Tuple(vec![
+            cmp(BinOpType::LessThan, &one),
+            cmp(BinOpType::GreaterThanOrEqual, &one),
+            cmp(BinOpType::Equal, &xv),
+            cmp(BinOpType::NotEqual, &xv),
+            cast(I32.into()),
+            cast(U8.into()),
+            cast(I64.into()),
+            cast(U64.into()),
+        ]));
+        let program = Program::new(vec![x], body);
+
+        let ctx = Context::create();
+        let prog = compile(&ctx, &program)?;
+        let inputs = [
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            1e10,
+            -1e10,
+            1e20,
+            -0.5,
+            255.9,
+            256.0,
+            -2147483648.5,
+            3.7,
+        ];
+        for v in inputs.iter().copied() {
+            let args = [Value::F64(v)];
+            assert_eq!(interpret_program(&program, &args)?, prog.run(&args)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_jit_appender_and_tuple() -> Result<()> {
+        let params = vec![Symbol::named("v", vec_ty(I64))];
+        let v = Var::<Vec<I64>>::clone_symbol(params[0].clone());
+        let len = v.clone().len();
+        let a = Var::appender(I64);
+        let doubled = a.pfor(v, |b, _i, e| b.merge(e * 2)).eval();
+        let body = Var::new_tuple((len, doubled));
+
+        let ctx = Context::create();
+        let prog = compile(&ctx, &Program::new(params, body))?;
+        // more items than initial capacity of appender
+        let input = Value::Vector((0..40).map(Value::I64).collect());
+        let expected = Value::Tuple(vec![
+            Value::U64(40),
+            Value::Vector((0..40).map(|i| Value::I64(i * 2)).collect()),
+        ]);
+        assert_eq!(expected, prog.run(&[input])?);
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+//! Runtime functions called by generated code.
+use crate::codegen::{
+    RT_ERROR, RT_ERR_DIVIDE_BY_ZERO, RT_ERR_INVALID_ITERATOR, RT_ERR_SHIFT_OUT_OF_RANGE, RT_GROW,
+};
+use crate::Error;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    // buffers allocated by generated code, alive until the execution completes
+    static ARENA: RefCell<Vec<Vec<u64>>> = RefCell::new(vec![]);
+    // code of the first error reported by generated code, 0 if none
+    static ERROR: Cell<u32> = Cell::new(0);
+}
+
+/// Allocate a buffer of size bytes and copy used bytes of old buffer
+/// into it.
+///
+/// The old buffer is not freed, all buffers are released together
+/// when the execution completes.
+extern "C" fn lms_rt_grow(old: *const u8, used: u64, size: u64) -> *mut u8 {
+    let mut buf = vec![0u64; ((size as usize + 7) / 8).max(1)];
+    let ptr = buf.as_mut_ptr() as *mut u8;
+    if used > 0 {
+        unsafe { std::ptr::copy_nonoverlapping(old, ptr, used as usize) }
+    }
+    ARENA.with(|arena| arena.borrow_mut().push(buf));
+    ptr
+}
+
+/// Record the error reported by generated code, only the first one
+/// is kept.
+extern "C" fn lms_rt_error(code: u32) {
+    ERROR.with(|err| {
+        if err.get() == 0 {
+            err.set(code)
+        }
+    })
+}
+
+/// Map runtime functions declared in module to their implementations.
+pub(super) fn register<'ctx>(module: &Module<'ctx>, exec: &ExecutionEngine<'ctx>) {
+    if let Some(f) = module.get_function(RT_GROW) {
+        exec.add_global_mapping(&f, lms_rt_grow as usize);
+    }
+    if let Some(f) = module.get_function(RT_ERROR) {
+        exec.add_global_mapping(&f, lms_rt_error as usize);
+    }
+}
+
+/// Take the error reported by generated code in current thread.
+pub(super) fn take_error() -> Option<Error> {
+    let err = match ERROR.with(|err| err.replace(0)) {
+        0 => return None,
+        RT_ERR_DIVIDE_BY_ZERO => runtime_err!("division by zero in compiled program"),
+        RT_ERR_INVALID_ITERATOR => {
+            runtime_err!("invalid range or length of iterators in compiled program")
+        }
+        RT_ERR_SHIFT_OUT_OF_RANGE => runtime_err!("shift amount out of range in compiled program"),
+        code => runtime_err!("unknown error code {} in compiled program", code),
+    };
+    Some(err)
+}
+
+/// Release all buffers allocated by generated code in current thread.
+pub(super) fn clear_arena() {
+    ARENA.with(|arena| arena.borrow_mut().clear())
+}
@@ -7,9 +7,15 @@
 pub mod error;
 pub mod ast;
 pub mod codegen;
+pub mod jit;
+pub mod opt;
+pub mod runtime;
 pub mod sir;
 pub mod stage;
 pub mod sym;
 
 pub use error::Error;
+pub use jit::{compile, CompiledProgram};
+pub use runtime::{interpret, interpret_program, Value};
+pub use stage::Program;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,136 @@
+use crate::ast::*;
+use crate::sym::{free_symbols, substitute, Symbol};
+use crate::Result;
+use std::collections::HashMap;
+
+/// Name of environment parameters of loop functions.
+const ENV_NAME: &str = "env";
+
+/// Convert functions of for loops into closed functions.
+///
+/// Symbols a function captures from outer scopes are packed into an
+/// environment tuple, which is evaluated once by the loop and passed
+/// to the function as its last parameter. Inside the function, each
+/// captured symbol is replaced by a field of the environment.
+/// Inner loops are converted first, so their environments are in turn
+/// captured by the outer loops.
+pub fn closure_convert(expr: &mut Expr) -> Result<bool> {
+    ClosureConversion.transform_expr(expr)
+}
+
+struct ClosureConversion;
+
+impl ExprTransformer for ClosureConversion {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        let mut changed = expr.apply_children(self)?;
+        if let Expr::For(pfor) = expr {
+            changed |= self.convert(pfor)?;
+        }
+        Ok(changed)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+impl ClosureConversion {
+    fn convert(&mut self, pfor: &mut For) -> Result<bool> {
+        if pfor.env.is_some() {
+            return Ok(false);
+        }
+        let mut captures: Vec<Symbol> = free_symbols(&pfor.func).into_iter().collect();
+        if captures.is_empty() {
+            return Ok(false);
+        }
+        // fields of environment in a stable order
+        captures.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        let lambda = match pfor.func.as_mut() {
+            Expr::Lambda(lambda) => lambda,
+            other => return Err(compile_err!("Non-lambda function {} in For", other)),
+        };
+        let env = Expr::Tuple(Tuple(captures.iter().cloned().map(Expr::Symbol).collect()));
+        let env_sym = Symbol::fresh_named(ENV_NAME, env.ty());
+        let map: HashMap<_, _> = captures
+            .into_iter()
+            .enumerate()
+            .map(|(k, sym)| {
+                let field = Expr::GetField(GetField {
+                    tuple: Box::new(Expr::Symbol(env_sym.clone())),
+                    index: k as u32,
+                });
+                (sym, field)
+            })
+            .collect();
+        substitute(lambda.body.as_mut(), &map)?;
+        lambda.params.push(env_sym);
+        pfor.env = Some(Box::new(env));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::check_pass;
+    use crate::runtime::Value;
+    use crate::sir::lower_program;
+    use crate::stage::Program;
+
+    /// Returns true if all loop functions are closed.
+    fn all_closed(expr: &Expr) -> bool {
+        struct Closed(bool);
+        impl ExprVisitor for Closed {
+            fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+                if let Expr::For(pfor) = expr {
+                    self.0 &= free_symbols(&pfor.func).is_empty();
+                }
+                expr.traverse_children(self)
+            }
+
+            fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+                self.visit_expr(&lambda.body)
+            }
+        }
+        let mut c = Closed(true);
+        c.visit_expr(expr).unwrap();
+        c.0
+    }
+
+    #[test]
+    fn test_closure_convert_nested_loops() -> Result<()> {
+        let vec_ty = Type::Vector(VectorType {
+            item_ty: Box::new(I64.into()),
+        });
+        let params = vec![Symbol::named("v", vec_ty), Symbol::named("x", I64)];
+        let v = Var::clone_symbol(params[0].clone());
+        let x = Var::<I64>::clone_symbol(params[1].clone());
+        let inner_v = v.clone();
+        // inner loop captures the item of outer loop and the parameter
+        let body = Var::new_merger(I64, BinOpType::Add)
+            .pfor(v, move |b, _i, e1: Var<I64>| {
+                let x = x.clone();
+                let inner = Var::new_merger(I64, BinOpType::Add)
+                    .pfor(inner_v.clone(), move |b, _i, e2: Var<I64>| {
+                        b.merge(e1.clone() * e2 + x)
+                    })
+                    .eval();
+                b.merge(inner)
+            })
+            .eval();
+        let mut program = Program::new(params, body);
+        let inputs = [Value::from(vec![1i64, 2, 3]), Value::I64(10)];
+        assert!(!all_closed(&program.body));
+
+        let changed = check_pass(&mut program, &inputs, |p| closure_convert(&mut p.body))?;
+        assert!(changed);
+        assert!(all_closed(&program.body));
+        assert!(!closure_convert(&mut program.body)?);
+
+        // no function captures any symbol after lowering
+        let sir = lower_program(&program)?;
+        assert!(sir.funcs.iter().all(|f| f.captures.is_empty()));
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use crate::ast::*;
+use crate::sym::Symbol;
+use crate::Result;
+use std::collections::HashMap;
+
+/// Name of symbols bound to common subexpressions.
+const CSE_NAME: &str = "c";
+
+/// Eliminate common subexpressions by binding them to symbols.
+///
+/// Structurally identical subexpressions evaluated in the same scope
+/// are hoisted into a let-binding at root of the scope, and each
+/// occurrence is replaced by the bound symbol.
+/// Bodies of lambda and let, and branches of condition are separate
+/// scopes, so that nothing is evaluated out of its scope or condition.
+/// Builder-typed expressions are linear and never shared.
+pub fn cse(expr: &mut Expr) -> Result<bool> {
+    Cse.eliminate(expr)
+}
+
+struct Cse;
+
+impl ExprTransformer for Cse {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        match expr {
+            Expr::IfThenElse(IfThenElse { i, t, e }) => {
+                let mut r = self.transform_expr(i.as_mut())?;
+                r |= self.eliminate(t.as_mut())?;
+                r |= self.eliminate(e.as_mut())?;
+                Ok(r)
+            }
+            Expr::Let(Let { value, body, .. }) => {
+                let mut r = self.transform_expr(value.as_mut())?;
+                r |= self.eliminate(body.as_mut())?;
+                Ok(r)
+            }
+            other => other.apply_children(self),
+        }
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.eliminate(lambda.body.as_mut())
+    }
+}
+
+impl Cse {
+    /// Eliminate common subexpressions in the scope rooted at expr.
+    fn eliminate(&mut self, root: &mut Expr) -> Result<bool> {
+        // inner scopes first
+        let mut changed = self.transform_expr(root)?;
+        let mut bindings: Vec<(Symbol, Expr)> = vec![];
+        loop {
+            let mut counter = Counter::default();
+            counter.visit_expr(root)?;
+            for (_, value) in &bindings {
+                counter.visit_expr(value)?;
+            }
+            // largest first, so its subexpressions are not hoisted separately
+            let Counter { exprs, counts } = counter;
+            let common = exprs.into_iter().filter(|e| counts[e] > 1).max_by_key(size);
+            let value = match common {
+                Some(value) => value,
+                None => break,
+            };
+            let sym = Symbol::fresh_named(CSE_NAME, value.ty());
+            let mut rp = Replace {
+                target: &value,
+                sym: &sym,
+            };
+            rp.transform_expr(root)?;
+            for (_, v) in &mut bindings {
+                rp.transform_expr(v)?;
+            }
+            bindings.push((sym, value));
+        }
+        // values hoisted later are parts of earlier ones, so bound outside
+        for (sym, value) in bindings {
+            let body = std::mem::replace(root, Expr::Literal(Literal::Bool(false)));
+            *root = Expr::Let(Let {
+                sym,
+                value: Box::new(value),
+                body: Box::new(body),
+            });
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+/// Returns true if the expression can be shared.
+fn is_candidate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_)
+        | Expr::Symbol(_)
+        | Expr::Broadcast(_)
+        | Expr::Lambda(_)
+        | Expr::Dict(_) => false,
+        other => !other.ty().is_builder(),
+    }
+}
+
+fn size(expr: &Expr) -> usize {
+    let mut sz = Size(0);
+    // counting won't fail
+    sz.visit_expr(expr).unwrap();
+    sz.0
+}
+
+struct Size(usize);
+
+impl ExprVisitor for Size {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        self.0 += 1;
+        expr.traverse_children(self)
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        self.visit_expr(&lambda.body)
+    }
+}
+
+/// Counts candidates evaluated in current scope, in order of first occurrence.
+#[derive(Default)]
+struct Counter {
+    exprs: Vec<Expr>,
+    counts: HashMap<Expr, usize>,
+}
+
+impl ExprVisitor for Counter {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        if is_candidate(expr) {
+            let n = self.counts.entry(expr.clone()).or_insert(0);
+            if *n == 0 {
+                self.exprs.push(expr.clone());
+            }
+            *n += 1;
+        }
+        match expr {
+            Expr::IfThenElse(IfThenElse { i, .. }) => self.visit_expr(i),
+            Expr::Let(Let { value, .. }) => self.visit_expr(value),
+            other => other.traverse_children(self),
+        }
+    }
+
+    fn visit_lambda(&mut self, _lambda: &Lambda) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Replace occurrences of target in current scope with the symbol.
+struct Replace<'a> {
+    target: &'a Expr,
+    sym: &'a Symbol,
+}
+
+impl ExprTransformer for Replace<'_> {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        if expr == self.target {
+            *expr = Expr::Symbol(self.sym.clone());
+            return Ok(true);
+        }
+        match expr {
+            Expr::IfThenElse(IfThenElse { i, .. }) => self.transform_expr(i.as_mut()),
+            Expr::Let(Let { value, .. }) => self.transform_expr(value.as_mut()),
+            other => other.apply_children(self),
+        }
+    }
+
+    fn transform_lambda(&mut self, _lambda: &mut Lambda) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::check_pass;
+    use crate::runtime::Value;
+    use crate::stage::Program;
+    use crate::sym::{typecheck_program, uniquify_program};
+
+    fn count_lets(expr: &Expr) -> usize {
+        match expr {
+            Expr::Let(Let { body, .. }) => 1 + count_lets(body),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_cse_tuple_fields() -> Result<()> {
+        let params = vec![Symbol::named("x", I32), Symbol::named("y", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let y = Var::<I32>::clone_symbol(params[1].clone());
+        let t = Var::new_tuple((x.clone() * y.clone(), x + y));
+        let a = t.get::<0>();
+        let b = t.get::<1>();
+        let body = a.clone() * b + a;
+        let mut program = Program::new(params, body);
+        let inputs = [Value::I32(3), Value::I32(4)];
+        assert!(check_pass(&mut program, &inputs, |p| cse(&mut p.body))?);
+        // the tuple is shared by all fields, and the first field twice
+        assert_eq!(2, count_lets(&program.body));
+        assert!(!cse(&mut program.body)?);
+        // bound symbols are renamed in scope
+        uniquify_program(&mut program)?;
+        typecheck_program(&program)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cse_skip_builders_and_branches() -> Result<()> {
+        let params = vec![Symbol::named("x", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let v = Var::new_vector(vec![1, 2, 3]);
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v, |b, _i, e: Var<I32>| b.merge(e * 2))
+            .eval();
+        let cond = Expr::BinOp(BinOp {
+            op_ty: BinOpType::GreaterThan,
+            left: Box::new(x.expr.clone()),
+            right: Box::new(0.into()),
+        });
+        let ite = Expr::IfThenElse(IfThenElse {
+            i: Box::new(cond),
+            t: Box::new((x.clone() + 1).expr),
+            e: Box::new((x.clone() + 1).expr),
+        });
+        let body = Var::new_tuple((Var::<I32>::new(ite), sum.clone(), sum));
+        let mut program = Program::new(params, body);
+        let inputs = [Value::I32(-1)];
+        assert!(check_pass(&mut program, &inputs, |p| cse(&mut p.body))?);
+        // only the loop result is shared, branches are untouched
+        assert_eq!(1, count_lets(&program.body));
+        match &program.body {
+            Expr::Let(Let { value, .. }) => assert!(!value.ty().is_builder()),
+            other => panic!("unexpected {}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use crate::ast::*;
+use crate::sym::{free_symbols, is_pure, Symbol};
+use crate::Result;
+
+/// Eliminate dead code: unused let-bindings, tuple fields never read
+/// and builders whose results are never read.
+///
+/// A let-bound tuple whose fields are only read by `GetField` keeps
+/// the fields read. When the tuple is the result of a loop over a tuple
+/// of builders, unread builders and their merges are removed from the
+/// loop.
+/// Expressions that may fail at runtime, such as dict lookup, integer
+/// division and shift, merging a vecmerger, or a loop over a range or
+/// zipped vectors, are never removed, so errors are not hidden.
+pub fn dce(expr: &mut Expr) -> Result<bool> {
+    Dce.transform_expr(expr)
+}
+
+struct Dce;
+
+impl ExprTransformer for Dce {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        // inner bindings first, so bindings only used by them die as well
+        let mut changed = expr.apply_children(self)?;
+        let replaced = match expr {
+            Expr::Let(Let { sym, value, body }) => {
+                if !free_symbols(body).contains(sym) {
+                    if is_pure(value) {
+                        Some(take(body))
+                    } else {
+                        None
+                    }
+                } else {
+                    if let Some(keep) = field_uses(sym, body) {
+                        if let Some((remap, pruned)) = prune_fields(value, &keep)? {
+                            let new_sym = Symbol::new(sym.name.clone(), pruned.ty(), sym.id);
+                            Reindex {
+                                sym,
+                                new_sym: &new_sym,
+                                remap: &remap,
+                            }
+                            .transform_expr(body)?;
+                            *sym = new_sym;
+                            **value = pruned;
+                            changed = true;
+                        }
+                    }
+                    None
+                }
+            }
+            Expr::GetField(GetField { tuple, index }) => {
+                let mut keep = vec![false; field_count(&tuple.ty())];
+                if let Some(k) = keep.get_mut(*index as usize) {
+                    *k = true;
+                }
+                match prune_fields(tuple, &keep)? {
+                    Some((_, Expr::Tuple(Tuple(mut items)))) => items.pop(),
+                    Some((_, pruned)) => {
+                        **tuple = pruned;
+                        *index = 0;
+                        changed = true;
+                        None
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(e) = replaced {
+            *expr = e;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+fn take(expr: &mut Expr) -> Expr {
+    std::mem::replace(expr, Expr::Literal(Literal::Bool(false)))
+}
+
+fn field_count(ty: &Type) -> usize {
+    match ty {
+        Type::Tuple(TupleType(tys)) => tys.len(),
+        _ => 0,
+    }
+}
+
+/// Returns new index of each field kept.
+fn remap(keep: &[bool]) -> Vec<Option<u32>> {
+    let mut next = 0;
+    keep.iter()
+        .map(|k| {
+            if *k {
+                next += 1;
+                Some(next - 1)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Remove fields not kept from the tuple, or from the loop building it.
+///
+/// Returns None if nothing can be removed.
+fn prune_fields(value: &Expr, keep: &[bool]) -> Result<Option<(Vec<Option<u32>>, Expr)>> {
+    if keep.iter().all(|k| *k) || !keep.iter().any(|k| *k) {
+        return Ok(None);
+    }
+    let pruned = match value {
+        Expr::Tuple(Tuple(items)) if items.len() == keep.len() => match kept_items(items, keep) {
+            Some(items) => Expr::Tuple(Tuple(items)),
+            None => return Ok(None),
+        },
+        Expr::Eval(Eval(b)) => match b.as_ref() {
+            Expr::For(pfor) => match prune_builders(pfor, keep)? {
+                Some(pfor) => Expr::Eval(Eval(Box::new(Expr::For(pfor)))),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some((remap(keep), pruned)))
+}
+
+/// Returns items kept, if all others can be removed.
+fn kept_items(items: &[Expr], keep: &[bool]) -> Option<Vec<Expr>> {
+    let mut kept = vec![];
+    for (item, k) in items.iter().zip(keep) {
+        if *k {
+            kept.push(item.clone());
+        } else if !is_pure(item) {
+            return None;
+        }
+    }
+    Some(kept)
+}
+
+/// Remove builders not kept from a loop over a tuple of builders.
+///
+/// The function must return a tuple, and each of its fields may only
+/// refer to builders kept.
+fn prune_builders(pfor: &For, keep: &[bool]) -> Result<Option<For>> {
+    let (params, body) = match pfor.func.as_ref() {
+        Expr::Lambda(Lambda { params, body }) if !params.is_empty() => (params, body),
+        _ => return Ok(None),
+    };
+    let (builders, items) = match (pfor.builder.as_ref(), body.as_ref()) {
+        (Expr::Tuple(Tuple(bs)), Expr::Tuple(Tuple(items)))
+            if bs.len() == keep.len() && items.len() == keep.len() =>
+        {
+            (bs, items)
+        }
+        _ => return Ok(None),
+    };
+    let b = &params[0];
+    for (item, k) in items.iter().zip(keep) {
+        if !*k {
+            continue;
+        }
+        match field_uses(b, item) {
+            Some(read) if read.iter().zip(keep).all(|(r, k)| *k || !*r) => (),
+            _ => return Ok(None),
+        }
+    }
+    let (builders, mut items) = match (kept_items(builders, keep), kept_items(items, keep)) {
+        (Some(bs), Some(items)) => (bs, items),
+        _ => return Ok(None),
+    };
+    let builder = Expr::Tuple(Tuple(builders));
+    let new_b = Symbol::new(b.name.clone(), builder.ty(), b.id);
+    let remap = remap(keep);
+    let mut rx = Reindex {
+        sym: b,
+        new_sym: &new_b,
+        remap: &remap,
+    };
+    for item in &mut items {
+        rx.transform_expr(item)?;
+    }
+    let mut params = params.clone();
+    params[0] = new_b;
+    Ok(Some(For {
+        iters: pfor.iters.clone(),
+        builder: Box::new(builder),
+        func: Box::new(Expr::Lambda(Lambda {
+            params,
+            body: Box::new(Expr::Tuple(Tuple(items))),
+        })),
+        env: pfor.env.clone(),
+    }))
+}
+
+/// Returns fields of the tuple symbol read in expr, or None if the
+/// symbol is used other than reading its fields.
+fn field_uses(sym: &Symbol, expr: &Expr) -> Option<Vec<bool>> {
+    let n = field_count(&sym.ty);
+    if n == 0 {
+        return None;
+    }
+    let mut fu = FieldUses {
+        sym,
+        read: vec![false; n],
+        other: false,
+    };
+    // visiting won't fail
+    fu.visit_expr(expr).unwrap();
+    if fu.other {
+        None
+    } else {
+        Some(fu.read)
+    }
+}
+
+struct FieldUses<'a> {
+    sym: &'a Symbol,
+    read: Vec<bool>,
+    other: bool,
+}
+
+impl ExprVisitor for FieldUses<'_> {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::GetField(GetField { tuple, index }) => match tuple.as_ref() {
+                Expr::Symbol(s) if s == self.sym => {
+                    match self.read.get_mut(*index as usize) {
+                        Some(r) => *r = true,
+                        None => self.other = true,
+                    }
+                    Ok(())
+                }
+                _ => expr.traverse_children(self),
+            },
+            Expr::Symbol(s) if s == self.sym => {
+                self.other = true;
+                Ok(())
+            }
+            other => other.traverse_children(self),
+        }
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        self.visit_expr(&lambda.body)
+    }
+}
+
+/// Rewrite field reads of the symbol to the pruned symbol.
+struct Reindex<'a> {
+    sym: &'a Symbol,
+    new_sym: &'a Symbol,
+    remap: &'a [Option<u32>],
+}
+
+impl ExprTransformer for Reindex<'_> {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        if let Expr::GetField(GetField { tuple, index }) = expr {
+            if let Expr::Symbol(s) = tuple.as_mut() {
+                if s == self.sym {
+                    *index = self.remap[*index as usize].ok_or_else(|| {
+                        compile_err!("Field {} of {} is removed but read", index, s)
+                    })?;
+                    *s = self.new_sym.clone();
+                    return Ok(true);
+                }
+            }
+        }
+        expr.apply_children(self)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::{check_pass, horizontal_fusion};
+    use crate::runtime::{interpret_program, Value};
+    use crate::stage::Program;
+
+    fn is_let(expr: &Expr) -> bool {
+        match expr {
+            Expr::Let(_) => true,
+            _ => false,
+        }
+    }
+
+    fn check_dce(program: &mut Program, inputs: &[Value]) -> Result<()> {
+        assert!(check_pass(program, inputs, |p| dce(&mut p.body))?);
+        assert!(!dce(&mut program.body)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dce_unused_lets() -> Result<()> {
+        let params = vec![Symbol::named("x", I32), Symbol::named("y", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let y = Var::<I32>::clone_symbol(params[1].clone());
+        let (x1, y1) = (x.clone(), y.clone());
+        let body = Var::let_in(x.clone() + 1, move |a| {
+            Var::let_in(a * 2, move |_b| {
+                Var::let_in(x1.clone() / y1, move |_c| x1 + 3)
+            })
+        });
+        let mut program = Program::new(params, body);
+        check_dce(&mut program, &[Value::I32(2), Value::I32(1)])?;
+        // only the division is kept
+        match &program.body {
+            Expr::Let(Let { value, body, .. }) => {
+                match value.as_ref() {
+                    Expr::BinOp(BinOp { op_ty, .. }) => assert_eq!(BinOpType::Divide, *op_ty),
+                    other => panic!("unexpected {}", other),
+                }
+                assert!(!is_let(body));
+            }
+            other => panic!("unexpected {}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dce_keeps_fallible() -> Result<()> {
+        let params = vec![Symbol::named("x", I32), Symbol::named("y", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let y = Var::<I32>::clone_symbol(params[1].clone());
+        let x1 = x.clone();
+        let v = Var::new_vector(vec![1u64, 5]);
+        let vm = Var::vecmerger(I32, BinOpType::Add, 4u64)
+            .pfor(v, |b, _i, e| b.merge((e, 1)))
+            .eval();
+        let body = Var::let_in(x.clone() << y, move |_s| Var::let_in(vm, move |_v| x1 + 1));
+        let mut expr = body.expr;
+        let orig = expr.clone();
+        assert!(!dce(&mut expr)?);
+        assert_eq!(orig, expr);
+        let program = Program::new(params, expr);
+        assert!(interpret_program(&program, &[Value::I32(1), Value::I32(40)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dce_keeps_fallible_loops() -> Result<()> {
+        let params = vec![Symbol::named("x", I32)];
+        let x = Var::<I32>::clone_symbol(params[0].clone());
+        let pfor = |iters: Vec<Iter>| {
+            let sum = Var::new_merger(I32, BinOpType::Add)
+                .pfor(Var::new_vector(vec![1, 2]), |b, _i, e: Var<I32>| b.merge(e));
+            let mut sum = sum.expr;
+            if let Expr::For(pfor) = &mut sum {
+                pfor.iters = iters;
+            }
+            Var::<Merger<I32>>::new(sum).eval()
+        };
+        let iter = |items: Vec<i32>, end: Option<u64>| Iter {
+            data: Box::new(Var::new_vector(items).expr),
+            start: None,
+            end: end.map(|e| Box::new(e.into())),
+        };
+        // range out of the vector, and zipped vectors of different lengths
+        let out_of_range = pfor(vec![iter(vec![1, 2], Some(3))]);
+        let zipped = pfor(vec![iter(vec![1, 2], None), iter(vec![3], None)]);
+        for value in [out_of_range, zipped] {
+            let x = x.clone();
+            let body = Var::let_in(value, move |_s| x + 1);
+            let mut program = Program::new(params.clone(), body);
+            assert!(interpret_program(&program, &[Value::I32(1)]).is_err());
+            assert!(!dce(&mut program.body)?);
+            assert!(interpret_program(&program, &[Value::I32(1)]).is_err());
+        }
+        // a loop over a whole vector never fails
+        let x1 = x.clone();
+        let body = Var::let_in(pfor(vec![iter(vec![1, 2], None)]), move |_s| x1 + 1);
+        let mut program = Program::new(params, body);
+        check_dce(&mut program, &[Value::I32(1)])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dce_tuple_fields() -> Result<()> {
+        let params = vec![Symbol::named("x", I64)];
+        let x = Var::<I64>::clone_symbol(params[0].clone());
+        let t = Var::new_tuple((x.clone() + 1, x.clone() * 2, x - 1));
+        let body = Var::let_in(t, |t| t.get::<0>() + t.get::<2>());
+        let mut program = Program::new(params, body);
+        check_dce(&mut program, &[Value::I64(5)])?;
+        match &program.body {
+            Expr::Let(Let { sym, .. }) => assert_eq!(2, field_count(&sym.ty)),
+            other => panic!("unexpected {}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dce_fused_builders() -> Result<()> {
+        let params = vec![Symbol::named("v", Vec::<I64>::static_ty())];
+        let v = Var::<Vec<I64>>::clone_symbol(params[0].clone());
+        let sum = Var::new_merger(I64, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I64>| b.merge(e))
+            .eval();
+        let doubled = Var::appender(I64)
+            .pfor(v, |b, _i, e: Var<I64>| b.merge(e * 2))
+            .eval();
+        let mut loops = Var::new_tuple((sum, doubled)).expr;
+        assert!(horizontal_fusion(&mut loops)?);
+        let body = Expr::GetField(GetField {
+            tuple: Box::new(loops),
+            index: 0,
+        });
+        let mut program = Program::new(params, body);
+        check_dce(&mut program, &[Value::from(vec![1i64, 5, 2])])?;
+        // the appender is removed from the loop
+        match &program.body {
+            Expr::GetField(GetField { tuple, .. }) => assert_eq!(1, field_count(&tuple.ty())),
+            other => panic!("unexpected {}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,549 @@
+use super::{Bindings, Pattern, Rule};
+use crate::ast::*;
+use crate::Result;
+use std::collections::HashMap;
+
+/// Maximum iterations of equality saturation.
+const ITER_LIMIT: usize = 16;
+/// Maximum nodes in e-graph, saturation stops if exceeded.
+const NODE_LIMIT: usize = 10_000;
+
+/// Optimize the expression by equality saturation over the rules,
+/// and extract the cheapest equivalent expression.
+///
+/// The cost function returns cost of an operator, excluding its
+/// children. The expression is only replaced if strictly cheaper.
+pub fn saturate<F>(expr: &mut Expr, rules: &[Rule], cost: F) -> Result<bool>
+where
+    F: Fn(&ENode) -> usize,
+{
+    let mut eg = EGraph::new();
+    let root = eg.add_expr(expr);
+    let (orig_cost, _) = eg.extract(root, &cost)?;
+    eg.run(rules)?;
+    let (new_cost, new_expr) = eg.extract(root, &cost)?;
+    if new_cost < orig_cost {
+        *expr = new_expr;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Cost function counting nodes of expression.
+pub fn ast_size(_node: &ENode) -> usize {
+    1
+}
+
+/// Id of an e-class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(usize);
+
+/// ENode is an operator with e-classes as its children.
+///
+/// Expressions not supported by rewriting, such as loops and lambdas,
+/// are kept as opaque leaves, together with symbols and literals.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ENode {
+    Leaf(Expr),
+    BinOp(BinOpType, [Id; 2]),
+    UnaryOp(UnaryOpType, Id),
+    Cast(Type, Id),
+    GetField(Id, u32),
+    Length(Id),
+    IfThenElse([Id; 3]),
+    Tuple(Vec<Id>),
+}
+
+impl ENode {
+    pub fn children(&self) -> Vec<Id> {
+        match self {
+            ENode::Leaf(_) => vec![],
+            ENode::BinOp(_, ids) => ids.to_vec(),
+            ENode::UnaryOp(_, id)
+            | ENode::Cast(_, id)
+            | ENode::GetField(id, _)
+            | ENode::Length(id) => {
+                vec![*id]
+            }
+            ENode::IfThenElse(ids) => ids.to_vec(),
+            ENode::Tuple(ids) => ids.clone(),
+        }
+    }
+
+    fn map_children<F: FnMut(Id) -> Id>(&self, mut f: F) -> Self {
+        match self {
+            ENode::Leaf(expr) => ENode::Leaf(expr.clone()),
+            ENode::BinOp(op_ty, [l, r]) => ENode::BinOp(*op_ty, [f(*l), f(*r)]),
+            ENode::UnaryOp(op_ty, id) => ENode::UnaryOp(*op_ty, f(*id)),
+            ENode::Cast(ty, id) => ENode::Cast(ty.clone(), f(*id)),
+            ENode::GetField(id, index) => ENode::GetField(f(*id), *index),
+            ENode::Length(id) => ENode::Length(f(*id)),
+            ENode::IfThenElse([i, t, e]) => ENode::IfThenElse([f(*i), f(*t), f(*e)]),
+            ENode::Tuple(ids) => ENode::Tuple(ids.iter().map(|id| f(*id)).collect()),
+        }
+    }
+
+    /// Build the expression with extracted children.
+    fn to_expr(&self, mut children: Vec<Expr>) -> Expr {
+        let mut next = || Box::new(children.remove(0));
+        match self {
+            ENode::Leaf(expr) => expr.clone(),
+            ENode::BinOp(op_ty, _) => Expr::BinOp(BinOp {
+                op_ty: *op_ty,
+                left: next(),
+                right: next(),
+            }),
+            ENode::UnaryOp(op_ty, _) => Expr::UnaryOp(UnaryOp {
+                op_ty: *op_ty,
+                value: next(),
+            }),
+            ENode::Cast(ty, _) => Expr::Cast(Cast {
+                ty: ty.clone(),
+                value: next(),
+            }),
+            ENode::GetField(_, index) => Expr::GetField(GetField {
+                tuple: next(),
+                index: *index,
+            }),
+            ENode::Length(_) => Expr::Length(Length(next())),
+            ENode::IfThenElse(_) => Expr::IfThenElse(IfThenElse {
+                i: next(),
+                t: next(),
+                e: next(),
+            }),
+            ENode::Tuple(_) => Expr::Tuple(Tuple(children)),
+        }
+    }
+}
+
+// substitution of metavariables in e-matching
+type Subst = HashMap<String, Id>;
+
+/// EGraph represents equivalence classes of expressions compactly.
+///
+/// Nodes are hash-consed, so identical expressions always share the
+/// same e-class. After unions, `rebuild` must be called to restore
+/// congruence before searching.
+#[derive(Debug, Default)]
+pub struct EGraph {
+    // union-find of e-class ids
+    parents: Vec<Id>,
+    // canonical node to its e-class
+    memo: HashMap<ENode, Id>,
+}
+
+impl EGraph {
+    pub fn new() -> Self {
+        EGraph::default()
+    }
+
+    /// Returns number of nodes in the e-graph.
+    pub fn len(&self) -> usize {
+        self.memo.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memo.is_empty()
+    }
+
+    /// Returns the canonical id of the e-class.
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.parents[id.0] != id {
+            id = self.parents[id.0];
+        }
+        id
+    }
+
+    /// Add the node and returns its e-class.
+    pub fn add(&mut self, node: ENode) -> Id {
+        let node = node.map_children(|id| self.find(id));
+        if let Some(id) = self.memo.get(&node) {
+            return self.find(*id);
+        }
+        let id = Id(self.parents.len());
+        self.parents.push(id);
+        self.memo.insert(node, id);
+        id
+    }
+
+    /// Add the expression and returns e-class of its root.
+    pub fn add_expr(&mut self, expr: &Expr) -> Id {
+        let node = match expr {
+            Expr::BinOp(BinOp { op_ty, left, right }) => {
+                ENode::BinOp(*op_ty, [self.add_expr(left), self.add_expr(right)])
+            }
+            Expr::UnaryOp(UnaryOp { op_ty, value }) => ENode::UnaryOp(*op_ty, self.add_expr(value)),
+            Expr::Cast(Cast { ty, value }) => ENode::Cast(ty.clone(), self.add_expr(value)),
+            Expr::GetField(GetField { tuple, index }) => {
+                ENode::GetField(self.add_expr(tuple), *index)
+            }
+            Expr::Length(Length(value)) => ENode::Length(self.add_expr(value)),
+            Expr::IfThenElse(IfThenElse { i, t, e }) => {
+                ENode::IfThenElse([self.add_expr(i), self.add_expr(t), self.add_expr(e)])
+            }
+            Expr::Tuple(Tuple(items)) => {
+                ENode::Tuple(items.iter().map(|item| self.add_expr(item)).collect())
+            }
+            other => ENode::Leaf(other.clone()),
+        };
+        self.add(node)
+    }
+
+    /// Merge two e-classes, returns true if they were different.
+    pub fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // keep the smaller id as root, so ids are stable
+        let (root, child) = if a < b { (a, b) } else { (b, a) };
+        self.parents[child.0] = root;
+        true
+    }
+
+    /// Restore congruence: nodes with equivalent children are merged
+    /// into the same e-class.
+    pub fn rebuild(&mut self) {
+        loop {
+            let mut memo = HashMap::with_capacity(self.memo.len());
+            let mut merges = vec![];
+            for (node, id) in std::mem::take(&mut self.memo) {
+                let node = node.map_children(|id| self.find(id));
+                let id = self.find(id);
+                match memo.get(&node) {
+                    Some(other) if *other != id => merges.push((*other, id)),
+                    Some(_) => (),
+                    None => {
+                        memo.insert(node, id);
+                    }
+                }
+            }
+            self.memo = memo;
+            let mut changed = false;
+            for (a, b) in merges {
+                changed |= self.union(a, b);
+            }
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// Run equality saturation with the rules, returns true if saturated,
+    /// false if stopped by limits.
+    pub fn run(&mut self, rules: &[Rule]) -> Result<bool> {
+        for _ in 0..ITER_LIMIT {
+            let classes = self.classes();
+            let best = self.best_nodes(&classes, &ast_size);
+            // search all rules before applying any
+            let mut matches = vec![];
+            for rule in rules {
+                for id in classes.keys() {
+                    for subst in self.search(&classes, &rule.pattern, *id, Subst::new()) {
+                        if let Some(guard) = &rule.guard {
+                            let bindings = self.bindings(&subst, &best)?;
+                            if !guard(&bindings) {
+                                continue;
+                            }
+                        }
+                        matches.push((rule, *id, subst));
+                    }
+                }
+            }
+            let mut changed = false;
+            for (rule, id, subst) in matches {
+                let new_id = self.add_template(&rule.template, &subst)?;
+                changed |= self.union(id, new_id);
+            }
+            self.rebuild();
+            if !changed {
+                return Ok(true);
+            }
+            if self.len() > NODE_LIMIT {
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Extract the cheapest expression of the e-class, and its cost.
+    pub fn extract<F>(&self, id: Id, cost: F) -> Result<(usize, Expr)>
+    where
+        F: Fn(&ENode) -> usize,
+    {
+        let classes = self.classes();
+        let best = self.best_nodes(&classes, &cost);
+        let id = self.find(id);
+        let (c, _) = best
+            .get(&id)
+            .ok_or_else(|| compile_err!("No expression extracted from e-class {:?}", id))?;
+        Ok((*c, self.build_expr(id, &best)?))
+    }
+
+    /// Returns nodes of each e-class, with canonical children.
+    fn classes(&self) -> HashMap<Id, Vec<ENode>> {
+        let mut classes: HashMap<Id, Vec<ENode>> = HashMap::new();
+        for (node, id) in &self.memo {
+            let node = node.map_children(|id| self.find(id));
+            classes.entry(self.find(*id)).or_default().push(node);
+        }
+        classes
+    }
+
+    /// Returns the cheapest node of each e-class, and its total cost.
+    fn best_nodes<F>(
+        &self,
+        classes: &HashMap<Id, Vec<ENode>>,
+        cost: &F,
+    ) -> HashMap<Id, (usize, ENode)>
+    where
+        F: Fn(&ENode) -> usize,
+    {
+        let mut best: HashMap<Id, (usize, ENode)> = HashMap::new();
+        // costs only decrease, so iteration terminates
+        loop {
+            let mut changed = false;
+            for (id, nodes) in classes {
+                for node in nodes {
+                    let mut c = cost(node);
+                    let mut complete = true;
+                    for child in node.children() {
+                        match best.get(&child) {
+                            Some((cc, _)) => c = c.saturating_add(*cc),
+                            None => complete = false,
+                        }
+                    }
+                    if !complete {
+                        continue;
+                    }
+                    match best.get(id) {
+                        Some((bc, _)) if *bc <= c => (),
+                        _ => {
+                            best.insert(*id, (c, node.clone()));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                return best;
+            }
+        }
+    }
+
+    fn build_expr(&self, id: Id, best: &HashMap<Id, (usize, ENode)>) -> Result<Expr> {
+        let (_, node) = best
+            .get(&self.find(id))
+            .ok_or_else(|| compile_err!("No expression extracted from e-class {:?}", id))?;
+        let children = node
+            .children()
+            .into_iter()
+            .map(|child| self.build_expr(child, best))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(node.to_expr(children))
+    }
+
+    fn bindings(&self, subst: &Subst, best: &HashMap<Id, (usize, ENode)>) -> Result<Bindings> {
+        let mut bindings = Bindings::default();
+        for (name, id) in subst {
+            bindings.0.insert(name.clone(), self.build_expr(*id, best)?);
+        }
+        Ok(bindings)
+    }
+
+    /// Match the pattern against all nodes of the e-class.
+    fn search(
+        &self,
+        classes: &HashMap<Id, Vec<ENode>>,
+        pattern: &Pattern,
+        id: Id,
+        subst: Subst,
+    ) -> Vec<Subst> {
+        let id = self.find(id);
+        if let Pattern::Var(name) = pattern {
+            return match subst.get(name) {
+                Some(bound) if self.find(*bound) != id => vec![],
+                Some(_) => vec![subst],
+                None => {
+                    let mut subst = subst;
+                    subst.insert(name.clone(), id);
+                    vec![subst]
+                }
+            };
+        }
+        let mut res = vec![];
+        for node in classes.get(&id).into_iter().flatten() {
+            match (pattern, node) {
+                (Pattern::Lit(lit), ENode::Leaf(Expr::Literal(l))) if lit == l => {
+                    res.push(subst.clone())
+                }
+                (Pattern::BinOp(op_ty, pl, pr), ENode::BinOp(o, ids)) if op_ty == o => {
+                    res.extend(self.search_all(classes, &[&**pl, &**pr], ids, subst.clone()))
+                }
+                (Pattern::UnaryOp(op_ty, pv), ENode::UnaryOp(o, v)) if op_ty == o => {
+                    res.extend(self.search(classes, pv, *v, subst.clone()))
+                }
+                (Pattern::Cast(ty, pv), ENode::Cast(t, v)) if ty == t => {
+                    res.extend(self.search(classes, pv, *v, subst.clone()))
+                }
+                (Pattern::GetField(pt, index), ENode::GetField(t, i)) if index == i => {
+                    res.extend(self.search(classes, pt, *t, subst.clone()))
+                }
+                (Pattern::Length(pv), ENode::Length(v)) => {
+                    res.extend(self.search(classes, pv, *v, subst.clone()))
+                }
+                (Pattern::IfThenElse(pi, pt, pe), ENode::IfThenElse(ids)) => {
+                    res.extend(self.search_all(classes, &[&**pi, &**pt, &**pe], ids, subst.clone()))
+                }
+                (Pattern::Tuple(ps), ENode::Tuple(ids)) if ps.len() == ids.len() => {
+                    let ps: Vec<_> = ps.iter().collect();
+                    res.extend(self.search_all(classes, &ps, ids, subst.clone()))
+                }
+                _ => (),
+            }
+        }
+        res
+    }
+
+    /// Match patterns against e-classes pairwise, with consistent substitution.
+    fn search_all(
+        &self,
+        classes: &HashMap<Id, Vec<ENode>>,
+        patterns: &[&Pattern],
+        ids: &[Id],
+        subst: Subst,
+    ) -> Vec<Subst> {
+        match (patterns.split_first(), ids.split_first()) {
+            (Some((p, ps)), Some((id, ids))) => self
+                .search(classes, p, *id, subst)
+                .into_iter()
+                .flat_map(|s| self.search_all(classes, ps, ids, s))
+                .collect(),
+            _ => vec![subst],
+        }
+    }
+
+    /// Add the template with metavariables substituted.
+    fn add_template(&mut self, template: &Pattern, subst: &Subst) -> Result<Id> {
+        let node = match template {
+            Pattern::Var(name) => {
+                return subst
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| compile_err!("Unbound metavariable {} in template", name))
+            }
+            Pattern::Lit(lit) => ENode::Leaf(Expr::Literal(lit.clone())),
+            Pattern::BinOp(op_ty, pl, pr) => ENode::BinOp(
+                *op_ty,
+                [self.add_template(pl, subst)?, self.add_template(pr, subst)?],
+            ),
+            Pattern::UnaryOp(op_ty, pv) => ENode::UnaryOp(*op_ty, self.add_template(pv, subst)?),
+            Pattern::Cast(ty, pv) => ENode::Cast(ty.clone(), self.add_template(pv, subst)?),
+            Pattern::GetField(pt, index) => ENode::GetField(self.add_template(pt, subst)?, *index),
+            Pattern::Length(pv) => ENode::Length(self.add_template(pv, subst)?),
+            Pattern::IfThenElse(pi, pt, pe) => ENode::IfThenElse([
+                self.add_template(pi, subst)?,
+                self.add_template(pt, subst)?,
+                self.add_template(pe, subst)?,
+            ]),
+            Pattern::Tuple(ps) => ENode::Tuple(
+                ps.iter()
+                    .map(|p| self.add_template(p, subst))
+                    .collect::<Result<_>>()?,
+            ),
+        };
+        Ok(self.add(node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::rewrite;
+    use crate::rule;
+
+    fn arith_rules() -> Vec<Rule> {
+        vec![
+            rule!("add_comm", (Add a b) => (Add b a)),
+            rule!("add_assoc", (Add (Add a b) c) => (Add a (Add b c))),
+            rule!("add_sub", (Subtract (Add a b) b) => a),
+        ]
+    }
+
+    #[test]
+    fn test_egraph_hash_consing() {
+        let x = Var::<I32>::new_symbol("x", I32);
+        let mut eg = EGraph::new();
+        let a = eg.add_expr(&(x.clone() + 1).expr);
+        let b = eg.add_expr(&(x.clone() + 1).expr);
+        assert_eq!(a, b);
+        // x, 1, x + 1
+        assert_eq!(3, eg.len());
+
+        // congruence: f(a) == f(b) after a == b
+        let y = Var::<I32>::new_symbol("y", I32);
+        let fx = eg.add_expr(&(-x.clone()).expr);
+        let fy = eg.add_expr(&(-y.clone()).expr);
+        let (ix, iy) = (eg.add_expr(&x.expr), eg.add_expr(&y.expr));
+        assert_ne!(eg.find(fx), eg.find(fy));
+        eg.union(ix, iy);
+        eg.rebuild();
+        assert_eq!(eg.find(fx), eg.find(fy));
+    }
+
+    #[test]
+    fn test_saturate_reassociation() -> Result<()> {
+        let x = Var::<I32>::new_symbol("x", I32);
+        let y = Var::<I32>::new_symbol("y", I32);
+        let z = Var::<I32>::new_symbol("z", I32);
+        // ((y + x) + z) - (y + z) needs commutation, which never
+        // converges in greedy rewriting
+        let expr = ((y.clone() + x.clone()) + z.clone() - (y + z)).expr;
+        let rules = arith_rules();
+        assert!(rewrite(&mut expr.clone(), &rules).is_err());
+
+        let mut opt = expr;
+        assert!(saturate(&mut opt, &rules, ast_size)?);
+        assert_eq!(x.expr, opt);
+        assert!(!saturate(&mut opt, &rules, ast_size)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_saturate_cost_function() -> Result<()> {
+        let x = Var::<I64>::new_symbol("x", I64);
+        let rules = vec![rule!("mul_shl", (Multiply a 2i64) => (ShiftLeft a 1i64))];
+        let expr = (x.clone() * 2i64).expr;
+        let cheap_shift = |node: &ENode| match node {
+            ENode::BinOp(BinOpType::Multiply, _) => 4,
+            _ => 1,
+        };
+        let mut opt = expr.clone();
+        assert!(saturate(&mut opt, &rules, cheap_shift)?);
+        let shl = Expr::BinOp(BinOp {
+            op_ty: BinOpType::ShiftLeft,
+            left: Box::new(x.expr),
+            right: Box::new(1i64.into()),
+        });
+        assert_eq!(shl, opt);
+
+        let mut opt = expr.clone();
+        assert!(!saturate(&mut opt, &rules, ast_size)?);
+        assert_eq!(expr, opt);
+        Ok(())
+    }
+
+    #[test]
+    fn test_saturate_opaque_leaf() -> Result<()> {
+        let v = Var::new_vector(vec![1, 2, 3]);
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v, |b, _i, e: Var<I32>| b.merge(e))
+            .eval();
+        let x = Var::<I32>::new_symbol("x", I32);
+        let mut expr = ((sum.clone() + x.clone()) - x).expr;
+        assert!(saturate(&mut expr, &arith_rules(), ast_size)?);
+        assert_eq!(sum.expr, expr);
+        Ok(())
+    }
+}
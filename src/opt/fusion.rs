@@ -0,0 +1,366 @@
+use crate::ast::*;
+use crate::sym::{free_symbols, substitute, Symbol};
+use crate::Result;
+use std::collections::HashMap;
+
+/// Fuse a for loop over the result of another for loop into one loop.
+///
+/// The inner loop must build an appender, and its function may only
+/// merge into the builder, optionally under conditions. Each merge is
+/// replaced by the function of outer loop, so the intermediate vector
+/// is never materialized.
+/// If the outer function uses its index, fusion is only applied when
+/// the inner function merges exactly once per item.
+pub fn vertical_fusion(expr: &mut Expr) -> Result<bool> {
+    VerticalFusion.transform_expr(expr)
+}
+
+/// Fuse a tuple of for loops over same iterators into one loop.
+///
+/// The fused loop builds a tuple of the original builders, and its
+/// result is evaluated into the tuple of original results.
+/// Fusion is only applied if every item of the tuple is evaluation
+/// of such a loop.
+pub fn horizontal_fusion(expr: &mut Expr) -> Result<bool> {
+    HorizontalFusion.transform_expr(expr)
+}
+
+struct VerticalFusion;
+
+impl ExprTransformer for VerticalFusion {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        // fuse inner loops first, so a chain of loops collapses into one
+        let mut changed = expr.apply_children(self)?;
+        if let Expr::For(outer) = expr {
+            if let Some(fused) = fuse_vertical(outer)? {
+                *expr = Expr::For(fused);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+fn fuse_vertical(outer: &For) -> Result<Option<For>> {
+    if outer.iters.len() != 1 {
+        return Ok(None);
+    }
+    let it = &outer.iters[0];
+    if it.start.is_some() || it.end.is_some() {
+        return Ok(None);
+    }
+    let inner = match it.data.as_ref() {
+        Expr::Eval(Eval(b)) => match b.as_ref() {
+            Expr::For(inner) => inner,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    if !matches_new_appender(&inner.builder) {
+        return Ok(None);
+    }
+    let (inner_params, inner_body) = match lambda_of(inner) {
+        Some(lambda) => lambda,
+        None => return Ok(None),
+    };
+    let (outer_params, outer_body) = match lambda_of(outer) {
+        Some(lambda) => lambda,
+        None => return Ok(None),
+    };
+    let b1 = &inner_params[0];
+    if !only_merges(inner_body, b1) {
+        return Ok(None);
+    }
+    // index of outer loop is only preserved if each item is merged once
+    if free_symbols(outer_body).contains(&outer_params[1]) && !merges_once(inner_body, b1) {
+        return Ok(None);
+    }
+    let rw = MergeRewriter {
+        b1,
+        b2: &outer_params[0],
+        i1: &inner_params[1],
+        i2: &outer_params[1],
+        e2: &outer_params[2],
+        body: outer_body,
+    };
+    let body = rw.rewrite(inner_body)?;
+    Ok(Some(For {
+        iters: inner.iters.clone(),
+        builder: outer.builder.clone(),
+        func: Box::new(Expr::Lambda(Lambda {
+            params: vec![
+                outer_params[0].clone(),
+                inner_params[1].clone(),
+                inner_params[2].clone(),
+            ],
+            body: Box::new(body),
+        })),
+        env: None,
+    }))
+}
+
+fn matches_new_appender(expr: &Expr) -> bool {
+    match expr {
+        Expr::NewAppender(_) => true,
+        _ => false,
+    }
+}
+
+fn lambda_of(pfor: &For) -> Option<(&[Symbol], &Expr)> {
+    // closure converted loops are not fused
+    if pfor.env.is_some() {
+        return None;
+    }
+    match pfor.func.as_ref() {
+        Expr::Lambda(Lambda { params, body }) if params.len() == 3 => Some((params, body)),
+        _ => None,
+    }
+}
+
+/// Returns true if the expression only merges values into the builder,
+/// and the builder is not used elsewhere.
+fn only_merges(expr: &Expr, builder: &Symbol) -> bool {
+    match expr {
+        Expr::Symbol(sym) => sym == builder,
+        Expr::Merge(Merge { builder: b, value }) => {
+            only_merges(b, builder) && !free_symbols(value).contains(builder)
+        }
+        Expr::IfThenElse(IfThenElse { i, t, e }) => {
+            !free_symbols(i).contains(builder) && only_merges(t, builder) && only_merges(e, builder)
+        }
+        _ => false,
+    }
+}
+
+fn merges_once(expr: &Expr, builder: &Symbol) -> bool {
+    match expr {
+        Expr::Merge(Merge { builder: b, .. }) => match b.as_ref() {
+            Expr::Symbol(sym) => sym == builder,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Rewrites merges into inner builder as calls of outer function.
+struct MergeRewriter<'a> {
+    b1: &'a Symbol,
+    b2: &'a Symbol,
+    i1: &'a Symbol,
+    i2: &'a Symbol,
+    e2: &'a Symbol,
+    body: &'a Expr,
+}
+
+impl MergeRewriter<'_> {
+    fn rewrite(&self, expr: &Expr) -> Result<Expr> {
+        let res = match expr {
+            Expr::Symbol(sym) if sym == self.b1 => Expr::Symbol(self.b2.clone()),
+            Expr::Merge(Merge { builder, value }) => {
+                let mut map = HashMap::new();
+                map.insert(self.b2.clone(), self.rewrite(builder)?);
+                map.insert(self.e2.clone(), value.as_ref().clone());
+                map.insert(self.i2.clone(), Expr::Symbol(self.i1.clone()));
+                let mut body = self.body.clone();
+                substitute(&mut body, &map)?;
+                body
+            }
+            Expr::IfThenElse(IfThenElse { i, t, e }) => Expr::IfThenElse(IfThenElse {
+                i: i.clone(),
+                t: Box::new(self.rewrite(t)?),
+                e: Box::new(self.rewrite(e)?),
+            }),
+            other => {
+                return Err(compile_err!(
+                    "Unexpected expression {} in vertical fusion",
+                    other
+                ))
+            }
+        };
+        Ok(res)
+    }
+}
+
+struct HorizontalFusion;
+
+impl ExprTransformer for HorizontalFusion {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        let mut changed = expr.apply_children(self)?;
+        if let Expr::Tuple(Tuple(items)) = expr {
+            if let Some(fused) = fuse_horizontal(items)? {
+                *expr = fused;
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+fn fuse_horizontal(items: &[Expr]) -> Result<Option<Expr>> {
+    if items.len() < 2 {
+        return Ok(None);
+    }
+    let mut loops = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Expr::Eval(Eval(b)) => match b.as_ref() {
+                Expr::For(pfor) => match lambda_of(pfor) {
+                    Some((params, body)) => loops.push((pfor, params, body)),
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        }
+    }
+    let (first, first_params, _) = loops[0];
+    if loops
+        .iter()
+        .any(|(pfor, params, _)| pfor.iters != first.iters || params[2].ty != first_params[2].ty)
+    {
+        return Ok(None);
+    }
+
+    let builder_tys: Vec<Type> = loops
+        .iter()
+        .map(|(_, params, _)| params[0].ty.clone())
+        .collect();
+    let bs = Symbol::fresh_named("b", Type::Tuple(TupleType(builder_tys)));
+    let i = &first_params[1];
+    let e = &first_params[2];
+    let mut builders = Vec::with_capacity(loops.len());
+    let mut bodies = Vec::with_capacity(loops.len());
+    for (k, (pfor, params, body)) in loops.into_iter().enumerate() {
+        let mut map = HashMap::new();
+        map.insert(
+            params[0].clone(),
+            Expr::GetField(GetField {
+                tuple: Box::new(Expr::Symbol(bs.clone())),
+                index: k as u32,
+            }),
+        );
+        map.insert(params[1].clone(), Expr::Symbol(i.clone()));
+        map.insert(params[2].clone(), Expr::Symbol(e.clone()));
+        let mut body = body.clone();
+        substitute(&mut body, &map)?;
+        builders.push(pfor.builder.as_ref().clone());
+        bodies.push(body);
+    }
+    let fused = For {
+        iters: first.iters.clone(),
+        builder: Box::new(Expr::Tuple(Tuple(builders))),
+        func: Box::new(Expr::Lambda(Lambda {
+            params: vec![bs, i.clone(), e.clone()],
+            body: Box::new(Expr::Tuple(Tuple(bodies))),
+        })),
+        env: None,
+    };
+    Ok(Some(Expr::Eval(Eval(Box::new(Expr::For(fused))))))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::check_pass;
+    use crate::runtime::Value;
+    use crate::stage::Program;
+
+    fn vec_ty<T: Into<Type>>(item_ty: T) -> VectorType {
+        VectorType {
+            item_ty: Box::new(item_ty.into()),
+        }
+    }
+
+    fn check_fusion<F>(params: Vec<Symbol>, body: Expr, f: F, input: Value) -> Result<Program>
+    where
+        F: Fn(&mut Expr) -> Result<bool>,
+    {
+        let mut program = Program::new(params, body);
+        assert!(check_pass(&mut program, &[input], |p| f(&mut p.body))?);
+        Ok(program)
+    }
+
+    #[test]
+    fn test_vertical_fusion() -> Result<()> {
+        let params = vec![Symbol::named("v", vec_ty(I32))];
+        let v = Var::clone_symbol(params[0].clone());
+        let doubled = Var::appender(I32)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| b.merge(e * 2))
+            .eval();
+        let plus = Var::appender(I32)
+            .pfor(doubled, |b, _i, e: Var<I32>| b.merge(e + 1))
+            .eval();
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(plus, |b, _i, e: Var<I32>| b.merge(e))
+            .eval();
+        let input = Value::from(vec![1, 2, 3]);
+        let fused = check_fusion(params.clone(), sum.expr, vertical_fusion, input)?;
+        match &fused.body {
+            Expr::Eval(Eval(b)) => match b.as_ref() {
+                Expr::For(pfor) => assert!(matches_symbol(&pfor.iters[0].data)),
+                other => panic!("unexpected {}", other),
+            },
+            other => panic!("unexpected {}", other),
+        }
+
+        // filter in inner loop
+        let filtered = Var::appender(I32).pfor(v, |b, _i, e: Var<I32>| {
+            let cond = Expr::BinOp(BinOp {
+                op_ty: BinOpType::GreaterThan,
+                left: Box::new(e.expr.clone()),
+                right: Box::new(1.into()),
+            });
+            let merged = b.clone().merge(e);
+            Var::new(Expr::IfThenElse(IfThenElse {
+                i: Box::new(cond),
+                t: Box::new(merged.expr),
+                e: Box::new(b.expr),
+            }))
+        });
+        let max = Var::new_merger(I32, BinOpType::Max)
+            .pfor(filtered.eval(), |b, _i, e: Var<I32>| b.merge(e * 10))
+            .eval();
+        let input = Value::from(vec![3, 1, 2]);
+        check_fusion(params, max.expr, vertical_fusion, input)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_horizontal_fusion() -> Result<()> {
+        let params = vec![Symbol::named("v", vec_ty(I64))];
+        let v = Var::clone_symbol(params[0].clone());
+        let sum = Var::new_merger(I64, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I64>| b.merge(e))
+            .eval();
+        let doubled = Var::appender(I64)
+            .pfor(v, |b, _i, e: Var<I64>| b.merge(e * 2))
+            .eval();
+        let body = Var::new_tuple((sum, doubled));
+        let input = Value::from(vec![1i64, 5, 2]);
+        let fused = check_fusion(params, body.expr, horizontal_fusion, input)?;
+        match &fused.body {
+            Expr::Eval(Eval(b)) => match b.as_ref() {
+                Expr::For(pfor) => assert!(pfor.builder.ty().is_builder()),
+                other => panic!("unexpected {}", other),
+            },
+            other => panic!("unexpected {}", other),
+        }
+        Ok(())
+    }
+
+    fn matches_symbol(expr: &Expr) -> bool {
+        match expr {
+            Expr::Symbol(_) => true,
+            _ => false,
+        }
+    }
+}
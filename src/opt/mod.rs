@@ -0,0 +1,42 @@
+//! Optimizations on expression tree.
+//!
+//! Passes in this module assume the expression is uniquified, so that
+//! symbols can be substituted without capture.
+mod closure;
+mod cse;
+mod dce;
+mod egraph;
+mod fusion;
+mod pass;
+mod rewrite;
+
+pub use closure::closure_convert;
+pub use cse::cse;
+pub use dce::dce;
+pub use egraph::{ast_size, saturate, EGraph, ENode, Id};
+pub use fusion::{horizontal_fusion, vertical_fusion};
+pub use pass::{Pass, PassManager, PassRecord};
+pub use rewrite::{rewrite, Bindings, Pattern, Rule};
+
+/// Run a pass on the uniquified program, and check the result
+/// typechecks and evaluates to the same value as the input program.
+/// Returns true if the pass changed the program.
+#[cfg(test)]
+fn check_pass<F>(
+    program: &mut crate::stage::Program,
+    inputs: &[crate::runtime::Value],
+    pass: F,
+) -> crate::Result<bool>
+where
+    F: FnOnce(&mut crate::stage::Program) -> crate::Result<bool>,
+{
+    crate::sym::uniquify_program(program)?;
+    let expected = crate::runtime::interpret_program(program, inputs)?;
+    let changed = pass(program)?;
+    crate::sym::typecheck_program(program)?;
+    assert_eq!(
+        expected,
+        crate::runtime::interpret_program(program, inputs)?
+    );
+    Ok(changed)
+}
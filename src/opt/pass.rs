@@ -0,0 +1,230 @@
+use super::{cse, dce, horizontal_fusion, vertical_fusion};
+use crate::ast::Expr;
+use crate::stage::Program;
+use crate::sym::{simplify, uniquify};
+use crate::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default maximum iterations of the pipeline.
+const MAX_ITERS: usize = 8;
+
+type PassFn = Box<dyn Fn(&mut Expr) -> Result<bool>>;
+
+/// Pass is a named transformation, returns true if the expression
+/// is changed.
+pub struct Pass {
+    name: String,
+    func: PassFn,
+}
+
+impl Pass {
+    pub fn new<S, F>(name: S, func: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&mut Expr) -> Result<bool> + 'static,
+    {
+        Pass {
+            name: name.into(),
+            func: Box::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// PassRecord is the result of running a pass once.
+#[derive(Debug, Clone)]
+pub struct PassRecord {
+    pub name: String,
+    pub iteration: usize,
+    pub changed: bool,
+    pub elapsed: Duration,
+    /// IR after the pass, only recorded if dump is enabled.
+    pub ir: Option<String>,
+}
+
+impl std::fmt::Display for PassRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {}: changed={} in {:?}",
+            self.iteration, self.name, self.changed, self.elapsed
+        )?;
+        if let Some(ir) = &self.ir {
+            write!(f, "\n{}", ir)?;
+        }
+        Ok(())
+    }
+}
+
+/// PassManager runs an ordered pipeline of passes.
+///
+/// The pipeline is repeated until no pass changes the expression,
+/// or the maximum iterations is reached.
+pub struct PassManager {
+    passes: Vec<Pass>,
+    max_iters: usize,
+    dump: bool,
+    records: Vec<PassRecord>,
+}
+
+/// The default pipeline runs uniquify, simplify, fusion, CSE and DCE.
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new()
+            .add_pass(Pass::new("uniquify", uniquify))
+            .add_pass(Pass::new("simplify", |expr: &mut Expr| {
+                simplify(expr, &HashMap::new())
+            }))
+            .add_pass(Pass::new("vertical_fusion", vertical_fusion))
+            .add_pass(Pass::new("horizontal_fusion", horizontal_fusion))
+            .add_pass(Pass::new("cse", cse))
+            .add_pass(Pass::new("dce", dce))
+    }
+}
+
+impl PassManager {
+    /// Create an empty pipeline.
+    pub fn new() -> Self {
+        PassManager {
+            passes: vec![],
+            max_iters: MAX_ITERS,
+            dump: false,
+            records: vec![],
+        }
+    }
+
+    pub fn add_pass(mut self, pass: Pass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn max_iters(mut self, max_iters: usize) -> Self {
+        self.max_iters = max_iters;
+        self
+    }
+
+    /// Record the IR after each pass.
+    pub fn dump_ir(mut self, dump: bool) -> Self {
+        self.dump = dump;
+        self
+    }
+
+    /// Returns names of passes in order.
+    pub fn passes(&self) -> Vec<&str> {
+        self.passes.iter().map(Pass::name).collect()
+    }
+
+    /// Returns records of the last run.
+    pub fn records(&self) -> &[PassRecord] {
+        &self.records
+    }
+
+    /// Run the pipeline on the expression, returns true if it is changed.
+    pub fn run(&mut self, expr: &mut Expr) -> Result<bool> {
+        self.records.clear();
+        let mut changed = false;
+        for iteration in 0..self.max_iters {
+            let mut iter_changed = false;
+            for pass in &self.passes {
+                let start = Instant::now();
+                let r = (pass.func)(expr)
+                    .map_err(|e| compile_err!("Pass {} failed: {}", pass.name, e))?;
+                self.records.push(PassRecord {
+                    name: pass.name.clone(),
+                    iteration,
+                    changed: r,
+                    elapsed: start.elapsed(),
+                    ir: if self.dump {
+                        Some(expr.to_string())
+                    } else {
+                        None
+                    },
+                });
+                iter_changed |= r;
+            }
+            if !iter_changed {
+                break;
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Run the pipeline on the program, parameters are bound in the body.
+    pub fn run_program(&mut self, program: &mut Program) -> Result<bool> {
+        let mut expr = Expr::from(program.clone());
+        let changed = self.run(&mut expr)?;
+        *program = Program::from(expr);
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ast::*;
+    use crate::opt::check_pass;
+    use crate::runtime::Value;
+    use crate::sym::Symbol;
+
+    #[test]
+    fn test_pass_manager() -> Result<()> {
+        let params = vec![Symbol::named(
+            "v",
+            Type::Vector(VectorType {
+                item_ty: Box::new(I64.into()),
+            }),
+        )];
+        let v = Var::clone_symbol(params[0].clone());
+        let doubled = Var::appender(I64)
+            .pfor(v.clone(), |b, _i, e: Var<I64>| b.merge(e * 2 + 0))
+            .eval();
+        let sum = Var::new_merger(I64, BinOpType::Add)
+            .pfor(doubled, |b, _i, e: Var<I64>| b.merge(e))
+            .eval();
+        let count = Var::new_merger(I64, BinOpType::Add)
+            .pfor(v, |b, _i, _e: Var<I64>| b.merge(Var::lit_i64(1)))
+            .eval();
+        let body = Var::new_tuple((sum.clone(), sum, count));
+        let mut program = Program::new(params, body);
+        let inputs = [Value::from(vec![1i64, 5, 2])];
+        let mut pm = PassManager::default().dump_ir(true);
+        assert!(check_pass(&mut program, &inputs, |p| pm.run_program(p))?);
+        // converged: nothing changed in the last iteration
+        let n = pm.passes().len();
+        let records = pm.records();
+        assert!(records.len() < n * MAX_ITERS);
+        assert!(records[records.len() - n..].iter().all(|r| !r.changed));
+        assert!(records.iter().all(|r| r.ir.is_some()));
+        assert!(records
+            .iter()
+            .any(|r| r.name == "vertical_fusion" && r.changed));
+
+        assert!(!pm.run_program(&mut program)?);
+        assert_eq!(n, pm.records().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pass_manager_max_iters() -> Result<()> {
+        // never converges
+        let mut pm = PassManager::new()
+            .add_pass(Pass::new("noop", |_: &mut Expr| Ok(true)))
+            .max_iters(3);
+        let mut expr = Expr::from(1);
+        assert!(pm.run(&mut expr)?);
+        assert_eq!(3, pm.records().len());
+        assert!(pm.records().iter().all(|r| r.ir.is_none()));
+
+        let mut pm = PassManager::new().add_pass(Pass::new("fail", |_: &mut Expr| {
+            Err(compile_err!("always fails"))
+        }));
+        assert!(pm.run(&mut expr).is_err());
+        Ok(())
+    }
+}
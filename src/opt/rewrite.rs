@@ -0,0 +1,317 @@
+use crate::ast::*;
+use crate::Result;
+use std::collections::HashMap;
+
+/// Maximum passes over the expression before rewriting is considered
+/// not converging.
+const MAX_PASSES: usize = 64;
+
+/// Pattern of expression, with metavariables bound to matched
+/// subexpressions.
+///
+/// Patterns are also used as replacement templates, where each
+/// metavariable is substituted with its bound expression.
+/// They can be written with `pattern!` macro in s-expression style,
+/// e.g. `pattern!((Add x 0))`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// Matches any expression. Multiple occurrences of the same
+    /// metavariable must match identical expressions.
+    Var(String),
+    /// Matches the literal.
+    Lit(Literal),
+    BinOp(BinOpType, Box<Pattern>, Box<Pattern>),
+    UnaryOp(UnaryOpType, Box<Pattern>),
+    Cast(Type, Box<Pattern>),
+    GetField(Box<Pattern>, u32),
+    Length(Box<Pattern>),
+    IfThenElse(Box<Pattern>, Box<Pattern>, Box<Pattern>),
+    Tuple(Vec<Pattern>),
+}
+
+/// Expressions bound to metavariables in matching.
+#[derive(Debug, Clone, Default)]
+pub struct Bindings(pub(crate) HashMap<String, Expr>);
+
+impl Bindings {
+    /// Returns the expression bound to the metavariable.
+    pub fn get(&self, name: &str) -> Option<&Expr> {
+        self.0.get(name)
+    }
+
+    /// Returns the literal bound to the metavariable.
+    pub fn lit(&self, name: &str) -> Option<&Literal> {
+        self.get(name).and_then(Expr::as_lit)
+    }
+}
+
+impl Pattern {
+    /// Match the expression, adding metavariables to bindings.
+    ///
+    /// Bindings may be partially updated if the match fails.
+    pub fn matches(&self, expr: &Expr, bindings: &mut Bindings) -> bool {
+        match (self, expr) {
+            (Pattern::Var(name), _) => match bindings.0.get(name) {
+                Some(bound) => bound == expr,
+                None => {
+                    bindings.0.insert(name.clone(), expr.clone());
+                    true
+                }
+            },
+            (Pattern::Lit(lit), Expr::Literal(l)) => lit == l,
+            (Pattern::BinOp(op_ty, pl, pr), Expr::BinOp(bo)) => {
+                *op_ty == bo.op_ty
+                    && pl.matches(&bo.left, bindings)
+                    && pr.matches(&bo.right, bindings)
+            }
+            (Pattern::UnaryOp(op_ty, pv), Expr::UnaryOp(uo)) => {
+                *op_ty == uo.op_ty && pv.matches(&uo.value, bindings)
+            }
+            (Pattern::Cast(ty, pv), Expr::Cast(c)) => *ty == c.ty && pv.matches(&c.value, bindings),
+            (Pattern::GetField(pt, index), Expr::GetField(gf)) => {
+                *index == gf.index && pt.matches(&gf.tuple, bindings)
+            }
+            (Pattern::Length(pv), Expr::Length(Length(value))) => pv.matches(value, bindings),
+            (Pattern::IfThenElse(pi, pt, pe), Expr::IfThenElse(ite)) => {
+                pi.matches(&ite.i, bindings)
+                    && pt.matches(&ite.t, bindings)
+                    && pe.matches(&ite.e, bindings)
+            }
+            (Pattern::Tuple(ps), Expr::Tuple(Tuple(items))) => {
+                ps.len() == items.len()
+                    && ps
+                        .iter()
+                        .zip(items)
+                        .all(|(p, item)| p.matches(item, bindings))
+            }
+            _ => false,
+        }
+    }
+
+    /// Build the expression with metavariables substituted.
+    pub fn instantiate(&self, bindings: &Bindings) -> Result<Expr> {
+        let expr = match self {
+            Pattern::Var(name) => bindings
+                .get(name)
+                .cloned()
+                .ok_or_else(|| compile_err!("Unbound metavariable {} in template", name))?,
+            Pattern::Lit(lit) => Expr::Literal(lit.clone()),
+            Pattern::BinOp(op_ty, pl, pr) => Expr::BinOp(BinOp {
+                op_ty: *op_ty,
+                left: Box::new(pl.instantiate(bindings)?),
+                right: Box::new(pr.instantiate(bindings)?),
+            }),
+            Pattern::UnaryOp(op_ty, pv) => Expr::UnaryOp(UnaryOp {
+                op_ty: *op_ty,
+                value: Box::new(pv.instantiate(bindings)?),
+            }),
+            Pattern::Cast(ty, pv) => Expr::Cast(Cast {
+                ty: ty.clone(),
+                value: Box::new(pv.instantiate(bindings)?),
+            }),
+            Pattern::GetField(pt, index) => Expr::GetField(GetField {
+                tuple: Box::new(pt.instantiate(bindings)?),
+                index: *index,
+            }),
+            Pattern::Length(pv) => Expr::Length(Length(Box::new(pv.instantiate(bindings)?))),
+            Pattern::IfThenElse(pi, pt, pe) => Expr::IfThenElse(IfThenElse {
+                i: Box::new(pi.instantiate(bindings)?),
+                t: Box::new(pt.instantiate(bindings)?),
+                e: Box::new(pe.instantiate(bindings)?),
+            }),
+            Pattern::Tuple(ps) => Expr::Tuple(Tuple(
+                ps.iter()
+                    .map(|p| p.instantiate(bindings))
+                    .collect::<Result<_>>()?,
+            )),
+        };
+        Ok(expr)
+    }
+}
+
+/// Rule rewrites expressions matching the pattern into the template,
+/// if the optional guard accepts the bindings.
+pub struct Rule {
+    pub(crate) name: String,
+    pub(crate) pattern: Pattern,
+    pub(crate) template: Pattern,
+    pub(crate) guard: Option<Guard>,
+}
+
+type Guard = Box<dyn Fn(&Bindings) -> bool>;
+
+impl Rule {
+    pub fn new<S: Into<String>>(name: S, pattern: Pattern, template: Pattern) -> Self {
+        Rule {
+            name: name.into(),
+            pattern,
+            template,
+            guard: None,
+        }
+    }
+
+    /// Returns the rule only applied if the guard returns true.
+    pub fn with_guard<F>(mut self, guard: F) -> Self
+    where
+        F: Fn(&Bindings) -> bool + 'static,
+    {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Apply the rule on root of the expression, returns the replacement.
+    pub fn apply(&self, expr: &Expr) -> Result<Option<Expr>> {
+        let mut bindings = Bindings::default();
+        if !self.pattern.matches(expr, &mut bindings) {
+            return Ok(None);
+        }
+        if let Some(guard) = &self.guard {
+            if !guard(&bindings) {
+                return Ok(None);
+            }
+        }
+        self.template.instantiate(&bindings).map(Some)
+    }
+}
+
+/// Rewrite the expression with rules until none applies.
+///
+/// Returns names of fired rules in order.
+/// Rules are tried in order on each subexpression, bottom-up.
+/// An error is returned if the rules do not converge, e.g. rewriting
+/// back and forth.
+pub fn rewrite(expr: &mut Expr, rules: &[Rule]) -> Result<Vec<String>> {
+    let mut rw = Rewriter {
+        rules,
+        fired: vec![],
+    };
+    for _ in 0..MAX_PASSES {
+        if !rw.transform_expr(expr)? {
+            return Ok(rw.fired);
+        }
+    }
+    Err(compile_err!(
+        "Rewrite rules do not converge after {} passes",
+        MAX_PASSES
+    ))
+}
+
+struct Rewriter<'a> {
+    rules: &'a [Rule],
+    fired: Vec<String>,
+}
+
+impl ExprTransformer for Rewriter<'_> {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        let mut changed = expr.apply_children(self)?;
+        for rule in self.rules {
+            if let Some(new_expr) = rule.apply(expr)? {
+                *expr = new_expr;
+                self.fired.push(rule.name.clone());
+                changed = true;
+                break;
+            }
+        }
+        Ok(changed)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        self.transform_expr(lambda.body.as_mut())
+    }
+}
+
+/// Build a pattern in s-expression style.
+///
+/// An identifier is a metavariable, a literal matches itself, and
+/// `(Op a)` or `(Op a b)` matches unary or binary operation, where
+/// `Op` is a variant of `UnaryOpType` or `BinOpType`.
+#[macro_export]
+macro_rules! pattern {
+    ($var:ident) => {
+        $crate::opt::Pattern::Var(stringify!($var).to_owned())
+    };
+    ($lit:literal) => {
+        $crate::opt::Pattern::Lit($crate::ast::Literal::from($lit))
+    };
+    (($op:ident $value:tt)) => {
+        $crate::opt::Pattern::UnaryOp(
+            $crate::ast::UnaryOpType::$op,
+            Box::new($crate::pattern!($value)),
+        )
+    };
+    (($op:ident $left:tt $right:tt)) => {
+        $crate::opt::Pattern::BinOp(
+            $crate::ast::BinOpType::$op,
+            Box::new($crate::pattern!($left)),
+            Box::new($crate::pattern!($right)),
+        )
+    };
+}
+
+/// Build a rewrite rule with patterns in s-expression style.
+///
+/// `rule!("add_zero", (Add x 0) => x)` rewrites `x + 0` to `x`, and an
+/// optional guard follows the template, e.g. `=> x, if |b| ...`.
+#[macro_export]
+macro_rules! rule {
+    ($name:expr, $pattern:tt => $template:tt) => {
+        $crate::opt::Rule::new($name, $crate::pattern!($pattern), $crate::pattern!($template))
+    };
+    ($name:expr, $pattern:tt => $template:tt, if $guard:expr) => {
+        $crate::rule!($name, $pattern => $template).with_guard($guard)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pattern_match() {
+        let x = Var::<I32>::new_symbol("x", I32);
+        let y = Var::<I32>::new_symbol("y", I32);
+        let p = pattern!((Subtract a a));
+        let mut b = Bindings::default();
+        assert!(p.matches(&(x.clone() - x.clone()).expr, &mut b));
+        assert_eq!(Some(&x.expr), b.get("a"));
+        let mut b = Bindings::default();
+        assert!(!p.matches(&(x.clone() - y).expr, &mut b));
+
+        let p = pattern!((Neg (Add a 1)));
+        let expr = (-(x.clone() + 1)).expr;
+        let mut b = Bindings::default();
+        assert!(p.matches(&expr, &mut b));
+        assert_eq!(Some(&x.expr), b.get("a"));
+        assert_eq!(expr, p.instantiate(&b).unwrap());
+    }
+
+    #[test]
+    fn test_rewrite_rules() -> Result<()> {
+        let rules = vec![
+            rule!("add_zero", (Add x 0) => x),
+            rule!("double", (Multiply x 2) => (Add x x), if |b| b.get("x").map(Expr::is_symbol).unwrap_or(false)),
+        ];
+        let x = Var::<I32>::new_symbol("x", I32);
+        let mut expr = (((x.clone() + 0) + 0) * 2).expr;
+        let fired = rewrite(&mut expr, &rules)?;
+        assert_eq!(vec!["add_zero", "add_zero", "double"], fired);
+        assert_eq!((x.clone() + x.clone()).expr, expr);
+
+        // guard rejects non-symbol operand
+        let mut expr = ((x.clone() + 1) * 2).expr;
+        let orig = expr.clone();
+        assert!(rewrite(&mut expr, &rules)?.is_empty());
+        assert_eq!(orig, expr);
+
+        // rules rewriting back and forth never converge
+        let rules = vec![rule!("commute", (Add a b) => (Add b a))];
+        let mut expr = (x + 1).expr;
+        assert!(rewrite(&mut expr, &rules).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use super::Value;
+use crate::ast::*;
+use crate::Result;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// BuilderValue is the state of a builder during interpretation.
+///
+/// Merging updates the state in place, and evaluation consumes it
+/// into the result value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuilderValue {
+    Appender {
+        item_ty: Type,
+        items: Vec<Value>,
+    },
+    Merger {
+        item_ty: Type,
+        op_ty: BinOpType,
+        value: Value,
+    },
+    DictMerger {
+        key_ty: Type,
+        value_ty: Type,
+        op_ty: BinOpType,
+        items: HashMap<Value, Value>,
+    },
+    GroupMerger {
+        key_ty: Type,
+        value_ty: Type,
+        groups: HashMap<Value, Vec<Value>>,
+    },
+    VecMerger {
+        item_ty: Type,
+        op_ty: BinOpType,
+        items: Vec<Value>,
+    },
+}
+
+impl BuilderValue {
+    /// Create an empty builder of given type.
+    ///
+    /// Vecmerger created by this function has no slot, use
+    /// `new_vecmerger` to create one of given length.
+    pub fn new(ty: &Type) -> Result<Self> {
+        let b = match ty {
+            Type::Appender(AppenderType { item_ty }) => BuilderValue::Appender {
+                item_ty: item_ty.as_ref().clone(),
+                items: vec![],
+            },
+            Type::Merger(MergerType { item_ty, op_ty }) => {
+                let init = op_ty.identity(item_ty).ok_or_else(|| {
+                    runtime_err!("No identity of {} on type[{}] for merger", op_ty, item_ty)
+                })?;
+                BuilderValue::Merger {
+                    item_ty: item_ty.as_ref().clone(),
+                    op_ty: *op_ty,
+                    value: init.into(),
+                }
+            }
+            Type::DictMerger(DictMergerType {
+                key_ty,
+                value_ty,
+                op_ty,
+            }) => BuilderValue::DictMerger {
+                key_ty: key_ty.as_ref().clone(),
+                value_ty: value_ty.as_ref().clone(),
+                op_ty: *op_ty,
+                items: HashMap::new(),
+            },
+            Type::GroupMerger(GroupMergerType { key_ty, value_ty }) => BuilderValue::GroupMerger {
+                key_ty: key_ty.as_ref().clone(),
+                value_ty: value_ty.as_ref().clone(),
+                groups: HashMap::new(),
+            },
+            Type::VecMerger(VecMergerType { item_ty, op_ty }) => {
+                BuilderValue::new_vecmerger(item_ty, *op_ty, 0)?
+            }
+            other => return Err(runtime_err!("Type[{}] is not a builder", other)),
+        };
+        Ok(b)
+    }
+
+    /// Create a vecmerger of given length, each slot holds the identity
+    /// of the operator.
+    pub fn new_vecmerger(item_ty: &Type, op_ty: BinOpType, len: u64) -> Result<Self> {
+        let init: Value = op_ty
+            .identity(item_ty)
+            .ok_or_else(|| {
+                runtime_err!(
+                    "No identity of {} on type[{}] for vecmerger",
+                    op_ty,
+                    item_ty
+                )
+            })?
+            .into();
+        // length comes from data, so allocation failure is an error instead of abort
+        let mut items = vec![];
+        usize::try_from(len)
+            .ok()
+            .and_then(|len| items.try_reserve_exact(len).ok())
+            .ok_or_else(|| runtime_err!("Length {} of vecmerger is too large", len))?;
+        items.resize(len as usize, init);
+        Ok(BuilderValue::VecMerger {
+            item_ty: item_ty.clone(),
+            op_ty,
+            items,
+        })
+    }
+
+    /// Returns the builder type.
+    pub fn ty(&self) -> Type {
+        match self {
+            BuilderValue::Appender { item_ty, .. } => Type::Appender(AppenderType {
+                item_ty: Box::new(item_ty.clone()),
+            }),
+            BuilderValue::Merger { item_ty, op_ty, .. } => Type::Merger(MergerType {
+                item_ty: Box::new(item_ty.clone()),
+                op_ty: *op_ty,
+            }),
+            BuilderValue::DictMerger {
+                key_ty,
+                value_ty,
+                op_ty,
+                ..
+            } => Type::DictMerger(DictMergerType {
+                key_ty: Box::new(key_ty.clone()),
+                value_ty: Box::new(value_ty.clone()),
+                op_ty: *op_ty,
+            }),
+            BuilderValue::GroupMerger {
+                key_ty, value_ty, ..
+            } => Type::GroupMerger(GroupMergerType {
+                key_ty: Box::new(key_ty.clone()),
+                value_ty: Box::new(value_ty.clone()),
+            }),
+            BuilderValue::VecMerger { item_ty, op_ty, .. } => Type::VecMerger(VecMergerType {
+                item_ty: Box::new(item_ty.clone()),
+                op_ty: *op_ty,
+            }),
+        }
+    }
+
+    /// Returns true if the builder is of given type.
+    pub fn is_type_of(&self, ty: &Type) -> bool {
+        self.ty() == *ty
+    }
+
+    /// Merge the value into builder.
+    pub fn merge(&mut self, value: Value) -> Result<()> {
+        match self {
+            BuilderValue::Appender { items, .. } => items.push(value),
+            BuilderValue::Merger {
+                op_ty, value: acc, ..
+            } => *acc = apply_op(*op_ty, acc, &value)?,
+            BuilderValue::DictMerger { op_ty, items, .. } => {
+                let (k, v) = into_pair(value)?;
+                match items.get_mut(&k) {
+                    Some(acc) => *acc = apply_op(*op_ty, acc, &v)?,
+                    None => {
+                        items.insert(k, v);
+                    }
+                }
+            }
+            BuilderValue::GroupMerger { groups, .. } => {
+                let (k, v) = into_pair(value)?;
+                groups.entry(k).or_insert_with(Vec::new).push(v);
+            }
+            BuilderValue::VecMerger { op_ty, items, .. } => {
+                let (idx, v) = match into_pair(value)? {
+                    (Value::U64(idx), v) => (idx, v),
+                    (other, _) => return Err(runtime_err!("Invalid index {} of vecmerger", other)),
+                };
+                let len = items.len();
+                let slot = usize::try_from(idx)
+                    .ok()
+                    .and_then(|i| items.get_mut(i))
+                    .ok_or_else(|| {
+                        runtime_err!("Index {} out of bounds of vecmerger of length {}", idx, len)
+                    })?;
+                *slot = apply_op(*op_ty, slot, &v)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume the builder and returns its result.
+    pub fn eval(self) -> Value {
+        match self {
+            BuilderValue::Appender { items, .. } | BuilderValue::VecMerger { items, .. } => {
+                Value::Vector(items)
+            }
+            BuilderValue::Merger { value, .. } => value,
+            BuilderValue::DictMerger { items, .. } => Value::Dict(items),
+            BuilderValue::GroupMerger { groups, .. } => Value::Dict(
+                groups
+                    .into_iter()
+                    .map(|(k, vs)| (k, Value::Vector(vs)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for BuilderValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.ty().fmt(f)
+    }
+}
+
+fn apply_op(op_ty: BinOpType, acc: &Value, value: &Value) -> Result<Value> {
+    match (acc.to_literal(), value.to_literal()) {
+        (Some(l), Some(r)) => Ok(l.apply_bin_op(&r, &op_ty)?.into()),
+        _ => Err(runtime_err!(
+            "Unsupported values {} and {} in {} operation",
+            acc,
+            value,
+            op_ty
+        )),
+    }
+}
+
+fn into_pair(value: Value) -> Result<(Value, Value)> {
+    match value {
+        Value::Tuple(mut items) if items.len() == 2 => {
+            let v = items.pop().unwrap();
+            let k = items.pop().unwrap();
+            Ok((k, v))
+        }
+        other => Err(runtime_err!("Expect a pair but got {}", other)),
+    }
+}
@@ -0,0 +1,165 @@
+//! Conversions between Value and Rust types.
+use super::Value;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+macro_rules! impl_value_conversion {
+    ($ty:ty, $path:path) => {
+        impl From<$ty> for Value {
+            fn from(src: $ty) -> Self {
+                $path(src)
+            }
+        }
+
+        impl TryFrom<Value> for $ty {
+            type Error = Error;
+
+            fn try_from(src: Value) -> Result<Self> {
+                match src {
+                    $path(v) => Ok(v),
+                    other => Err(runtime_err!(
+                        "Value {} cannot convert to {}",
+                        other,
+                        stringify!($ty)
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_value_conversion!(bool, Value::Bool);
+impl_value_conversion!(u8, Value::U8);
+impl_value_conversion!(u32, Value::U32);
+impl_value_conversion!(i32, Value::I32);
+impl_value_conversion!(u64, Value::U64);
+impl_value_conversion!(i64, Value::I64);
+impl_value_conversion!(f32, Value::F32);
+impl_value_conversion!(f64, Value::F64);
+impl_value_conversion!(String, Value::Str);
+
+impl From<&str> for Value {
+    fn from(src: &str) -> Self {
+        Value::Str(src.to_owned())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(src: Vec<T>) -> Self {
+        Value::Vector(src.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: TryFrom<Value, Error = Error>> TryFrom<Value> for Vec<T> {
+    type Error = Error;
+
+    fn try_from(src: Value) -> Result<Self> {
+        match src {
+            Value::Vector(items) => items.into_iter().map(T::try_from).collect(),
+            other => Err(runtime_err!("Value {} cannot convert to Vec", other)),
+        }
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> From<HashMap<K, V>> for Value {
+    fn from(src: HashMap<K, V>) -> Self {
+        Value::Dict(src.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl<K, V> TryFrom<Value> for HashMap<K, V>
+where
+    K: TryFrom<Value, Error = Error> + Eq + Hash,
+    V: TryFrom<Value, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(src: Value) -> Result<Self> {
+        match src {
+            Value::Dict(m) => m
+                .into_iter()
+                .map(|(k, v)| Ok((K::try_from(k)?, V::try_from(v)?)))
+                .collect(),
+            other => Err(runtime_err!("Value {} cannot convert to HashMap", other)),
+        }
+    }
+}
+
+macro_rules! impl_tuple_conversion {
+    ($n:expr; $($t:ident),+) => {
+        impl<$($t: Into<Value>),+> From<($($t,)+)> for Value {
+            #[allow(non_snake_case)]
+            fn from(src: ($($t,)+)) -> Self {
+                let ($($t,)+) = src;
+                Value::Tuple(vec![$($t.into()),+])
+            }
+        }
+
+        impl<$($t: TryFrom<Value, Error = Error>),+> TryFrom<Value> for ($($t,)+) {
+            type Error = Error;
+
+            fn try_from(src: Value) -> Result<Self> {
+                match src {
+                    Value::Tuple(items) if items.len() == $n => {
+                        let mut items = items.into_iter();
+                        Ok(($($t::try_from(items.next().unwrap())?,)+))
+                    }
+                    other => Err(runtime_err!(
+                        "Value {} cannot convert to tuple of {} fields",
+                        other,
+                        $n
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple_conversion!(1; A);
+impl_tuple_conversion!(2; A, B);
+impl_tuple_conversion!(3; A, B, C);
+impl_tuple_conversion!(4; A, B, C, D);
+impl_tuple_conversion!(5; A, B, C, D, E);
+impl_tuple_conversion!(6; A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ast::*;
+
+    #[test]
+    fn test_value_conversion() -> Result<()> {
+        let v: Value = vec![1, 2, 3].into();
+        assert_eq!(vec![1, 2, 3], Vec::<i32>::try_from(v.clone())?);
+        assert!(Vec::<i64>::try_from(v).is_err());
+
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), vec![1.5f64]);
+        let v: Value = m.clone().into();
+        assert_eq!(m, HashMap::<String, Vec<f64>>::try_from(v)?);
+
+        let v: Value = (1u64, (true, "x"), vec![(1i32, 2i32)]).into();
+        let (a, (b, c), d) = <(u64, (bool, String), Vec<(i32, i32)>)>::try_from(v)?;
+        assert_eq!((1, true, "x".to_owned(), vec![(1, 2)]), (a, b, c, d));
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_check_type() {
+        let ty = Type::Tuple(TupleType(vec![
+            I32.into(),
+            Type::Vector(VectorType {
+                item_ty: Box::new(F64.into()),
+            }),
+        ]));
+        let v: Value = (1, vec![1.0f64]).into();
+        assert!(v.check_type(&ty).is_ok());
+        let v: Value = (1, vec![1.0f32]).into();
+        assert!(v.check_type(&ty).is_err());
+        let v: Value = (1, Vec::<f32>::new()).into();
+        assert!(v.check_type(&ty).is_ok());
+    }
+}
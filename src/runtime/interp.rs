@@ -0,0 +1,516 @@
+use super::{BuilderValue, Value};
+use crate::ast::*;
+use crate::stage::Program;
+use crate::sym::{free_symbols, Symbol};
+use crate::Result;
+use std::collections::HashMap;
+
+/// Interpret the expression with values of free symbols in env.
+///
+/// If the expression is a lambda, its parameters must be provided
+/// in env and the result is the value of its body.
+/// Values in env are checked against types of their symbols.
+/// Interpretation walks the tree directly, without any optimization,
+/// and serves as reference of the compiled program.
+pub fn interpret(expr: &Expr, env: &HashMap<Symbol, Value>) -> Result<Value> {
+    for (sym, value) in env {
+        value.check_type(&sym.ty)?;
+    }
+    let mut interp = Interpreter { env: env.clone() };
+    match expr {
+        Expr::Lambda(Lambda { body, .. }) => interp.eval(body),
+        other => interp.eval(other),
+    }
+}
+
+/// Interpret the program with inputs bound to its parameters in order.
+pub fn interpret_program(program: &Program, inputs: &[Value]) -> Result<Value> {
+    if inputs.len() != program.params.len() {
+        return Err(runtime_err!(
+            "Program requires {} inputs but {} provided",
+            program.params.len(),
+            inputs.len()
+        ));
+    }
+    // symbols in branches not taken are never evaluated
+    for sym in free_symbols(&program.body) {
+        if !program.params.contains(&sym) {
+            return Err(runtime_err!("Undefined symbol {} in program", sym));
+        }
+    }
+    let env = program
+        .params
+        .iter()
+        .cloned()
+        .zip(inputs.iter().cloned())
+        .collect();
+    interpret(&program.body, &env)
+}
+
+struct Interpreter {
+    env: HashMap<Symbol, Value>,
+}
+
+impl Interpreter {
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        let value = match expr {
+            Expr::Literal(lit) => lit.clone().into(),
+            // builders are linear, so moved out instead of copied, and
+            // merges update them in place
+            Expr::Symbol(sym) if sym.ty.is_builder() => self
+                .env
+                .remove(sym)
+                .ok_or_else(|| runtime_err!("Undefined or consumed builder {}", sym))?,
+            Expr::Symbol(sym) => self
+                .env
+                .get(sym)
+                .cloned()
+                .ok_or_else(|| runtime_err!("Undefined symbol {}", sym))?,
+            Expr::Broadcast(_) => {
+                return Err(runtime_err!("Broadcast can only be evaluated as iterator"))
+            }
+            Expr::BinOp(BinOp { op_ty, left, right }) => {
+                let l = self.eval_lit(left)?;
+                let r = self.eval_lit(right)?;
+                l.apply_bin_op(&r, op_ty)?.into()
+            }
+            Expr::UnaryOp(UnaryOp { op_ty, value }) => {
+                self.eval_lit(value)?.apply_unary_op(op_ty)?.into()
+            }
+            Expr::Cast(Cast { ty, value }) => self.eval_lit(value)?.cast(ty)?.into(),
+            Expr::GetField(GetField { tuple, index }) => match tuple.as_ref() {
+                Expr::Symbol(sym) if sym.ty.is_builder() => self.take_field(sym, *index)?,
+                _ => match self.eval(tuple)? {
+                    Value::Tuple(mut items) if (*index as usize) < items.len() => {
+                        items.swap_remove(*index as usize)
+                    }
+                    other => {
+                        return Err(runtime_err!("Invalid field {} of value {}", index, other))
+                    }
+                },
+            },
+            Expr::Length(Length(value)) => match self.eval(value)? {
+                Value::Vector(items) => Value::U64(items.len() as u64),
+                other => return Err(runtime_err!("Value {} has no length", other)),
+            },
+            Expr::Lookup(Lookup { dict, index }) => {
+                let key = self.eval(index)?;
+                match self.eval(dict)? {
+                    Value::Dict(mut m) => m
+                        .remove(&key)
+                        .ok_or_else(|| runtime_err!("Key {} not found in dict", key))?,
+                    other => return Err(runtime_err!("Value {} is not a dict", other)),
+                }
+            }
+            Expr::IfThenElse(IfThenElse { i, t, e }) => match self.eval(i)? {
+                Value::Bool(true) => self.eval(t)?,
+                Value::Bool(false) => self.eval(e)?,
+                other => return Err(runtime_err!("Condition {} is not a bool", other)),
+            },
+            Expr::Let(Let { sym, value, body }) => {
+                let v = self.eval(value)?;
+                self.call(std::slice::from_ref(sym), body, vec![v])?
+            }
+            Expr::For(pfor) => self.eval_for(pfor)?,
+            Expr::Merge(Merge { builder, value }) => {
+                let mut b = self.eval_builder(builder)?;
+                let v = self.eval(value)?;
+                b.merge(v)?;
+                Value::Builder(Box::new(b))
+            }
+            Expr::Lambda(_) => {
+                return Err(runtime_err!(
+                    "Lambda can only be evaluated as function of for loop"
+                ))
+            }
+            Expr::Vector(Vector { items, .. }) => Value::Vector(self.eval_list(items)?),
+            Expr::Dict(_) => Value::Dict(HashMap::new()),
+            Expr::Tuple(Tuple(items)) => Value::Tuple(self.eval_list(items)?),
+            Expr::NewAppender(_)
+            | Expr::NewMerger(_)
+            | Expr::NewDictMerger(_)
+            | Expr::NewGroupMerger(_) => Value::Builder(Box::new(BuilderValue::new(&expr.ty())?)),
+            Expr::NewVecMerger(NewVecMerger {
+                item_ty,
+                op_ty,
+                len,
+            }) => {
+                let len = self.eval_lit(len)?;
+                let len = len
+                    .as_u64()
+                    .ok_or_else(|| runtime_err!("Invalid length {} of vecmerger", len))?;
+                let b = BuilderValue::new_vecmerger(item_ty, *op_ty, len)?;
+                Value::Builder(Box::new(b))
+            }
+            Expr::Eval(Eval(builder)) => {
+                let b = self.eval(builder)?;
+                eval_result(b)?
+            }
+        };
+        Ok(value)
+    }
+
+    fn eval_lit(&mut self, expr: &Expr) -> Result<Literal> {
+        let value = self.eval(expr)?;
+        value
+            .to_literal()
+            .ok_or_else(|| runtime_err!("Value {} is not a scalar", value))
+    }
+
+    fn eval_builder(&mut self, expr: &Expr) -> Result<BuilderValue> {
+        match self.eval(expr)? {
+            Value::Builder(b) => Ok(*b),
+            other => Err(runtime_err!("Value {} is not a builder", other)),
+        }
+    }
+
+    /// Move the field out of the tuple of builders bound to the symbol,
+    /// each field is consumed separately.
+    fn take_field(&mut self, sym: &Symbol, index: u32) -> Result<Value> {
+        match self.env.get_mut(sym) {
+            Some(Value::Tuple(items)) if (index as usize) < items.len() => {
+                // the consumed field is left as an empty tuple
+                let field = &mut items[index as usize];
+                Ok(std::mem::replace(field, Value::Tuple(vec![])))
+            }
+            _ => Err(runtime_err!("Invalid field {} of builder {}", index, sym)),
+        }
+    }
+
+    fn eval_list(&mut self, exprs: &[Expr]) -> Result<Vec<Value>> {
+        exprs.iter().map(|e| self.eval(e)).collect()
+    }
+
+    /// Evaluate the for loop by calling its function on each item.
+    ///
+    /// All iterators must have same number of items, except broadcast
+    /// which repeats its value.
+    fn eval_for(&mut self, pfor: &For) -> Result<Value> {
+        let nparams = if pfor.env.is_some() { 4 } else { 3 };
+        let (params, body) = match pfor.func.as_ref() {
+            Expr::Lambda(Lambda { params, body }) if params.len() == nparams => (params, body),
+            other => return Err(runtime_err!("Invalid function {} of for loop", other)),
+        };
+        let env = pfor.env.as_ref().map(|env| self.eval(env)).transpose()?;
+        let mut iters = Vec::with_capacity(pfor.iters.len());
+        let mut count = None;
+        for it in &pfor.iters {
+            if let Expr::Broadcast(Broadcast { value }) = it.data.as_ref() {
+                iters.push(IterValue::Repeat(self.eval(value)?));
+                continue;
+            }
+            let items = match self.eval(&it.data)? {
+                Value::Vector(items) => items,
+                other => return Err(runtime_err!("Value {} is not iterable", other)),
+            };
+            let start = match &it.start {
+                Some(s) => self.eval_index(s)?,
+                None => 0,
+            };
+            let end = match &it.end {
+                Some(e) => self.eval_index(e)?,
+                None => items.len(),
+            };
+            if start > end || end > items.len() {
+                return Err(runtime_err!(
+                    "Invalid range [{}, {}) of vector with {} items",
+                    start,
+                    end,
+                    items.len()
+                ));
+            }
+            match count {
+                Some(n) if n != end - start => {
+                    return Err(runtime_err!("Iterators of for loop differ in length"))
+                }
+                _ => count = Some(end - start),
+            }
+            iters.push(IterValue::Items(items, start));
+        }
+        let count = count.ok_or_else(|| runtime_err!("No vector to iterate in for loop"))?;
+
+        let mut b = self.eval(&pfor.builder)?;
+        for i in 0..count {
+            let mut item: Vec<Value> = iters
+                .iter()
+                .map(|it| match it {
+                    IterValue::Items(items, start) => items[start + i].clone(),
+                    IterValue::Repeat(v) => v.clone(),
+                })
+                .collect();
+            let item = if item.len() == 1 {
+                item.pop().unwrap()
+            } else {
+                Value::Tuple(item)
+            };
+            let mut args = vec![b, Value::U64(i as u64), item];
+            args.extend(env.iter().cloned());
+            b = self.call(params, body, args)?;
+        }
+        Ok(b)
+    }
+
+    fn eval_index(&mut self, expr: &Expr) -> Result<usize> {
+        match self.eval(expr)? {
+            Value::U64(v) => Ok(v as usize),
+            other => Err(runtime_err!("Index {} is not an u64", other)),
+        }
+    }
+
+    /// Evaluate the body with parameters bound to arguments, restoring
+    /// shadowed symbols afterwards.
+    fn call(&mut self, params: &[Symbol], body: &Expr, args: Vec<Value>) -> Result<Value> {
+        let shadowed: Vec<_> = params
+            .iter()
+            .zip(args)
+            .map(|(p, arg)| (p.clone(), self.env.insert(p.clone(), arg)))
+            .collect();
+        let r = self.eval(body);
+        for (p, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(v) => self.env.insert(p, v),
+                None => self.env.remove(&p),
+            };
+        }
+        r
+    }
+}
+
+/// Evaluate the builder, or tuple of builders, into its result.
+fn eval_result(value: Value) -> Result<Value> {
+    match value {
+        Value::Builder(b) => Ok(b.eval()),
+        Value::Tuple(items) => Ok(Value::Tuple(
+            items.into_iter().map(eval_result).collect::<Result<_>>()?,
+        )),
+        other => Err(runtime_err!("Value {} is not a builder", other)),
+    }
+}
+
+enum IterValue {
+    // items of vector and start offset
+    Items(Vec<Value>, usize),
+    Repeat(Value),
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_interpret_builders() -> Result<()> {
+        let v = Var::new_vector(vec![1, 2, 3, 2]);
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| b.merge(e * 2))
+            .eval();
+        assert_eq!(Value::I32(16), interpret(&sum.expr, &HashMap::new())?);
+
+        let app = Var::appender(I32)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| b.merge(e + 1))
+            .eval();
+        let expected: Vec<_> = vec![2, 3, 4, 3].into_iter().map(Value::I32).collect();
+        assert_eq!(
+            Value::Vector(expected),
+            interpret(&app.expr, &HashMap::new())?
+        );
+
+        let dm = Var::dictmerger(I32, I32, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| b.merge((e.clone(), e)))
+            .eval();
+        let lookup = dm.lookup(2);
+        assert_eq!(Value::I32(4), interpret(&lookup.expr, &HashMap::new())?);
+
+        let vm = Var::vecmerger(I32, BinOpType::Add, 4u64)
+            .pfor(v, |b, _i, e: Var<I32>| {
+                b.merge((e.clone().cast::<U64>(), e))
+            })
+            .eval();
+        let expected: Vec<_> = vec![0, 1, 4, 3].into_iter().map(Value::I32).collect();
+        assert_eq!(
+            Value::Vector(expected),
+            interpret(&vm.expr, &HashMap::new())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_builder_moved() -> Result<()> {
+        // merges are in place, so a long loop takes linear time
+        let n = 100_000;
+        let v = Var::new_vector(vec![1i64; n]);
+        let app = Var::appender(I64)
+            .pfor(v, |b, i, e: Var<I64>| b.merge(e + i.cast::<I64>()))
+            .eval();
+        match interpret(&app.expr, &HashMap::new())? {
+            Value::Vector(items) => {
+                assert_eq!(n, items.len());
+                assert_eq!(Value::I64(n as i64), items[n - 1]);
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        // a builder is consumed by its first use
+        let ty = Type::Appender(AppenderType {
+            item_ty: Box::new(I32.into()),
+        });
+        let b = Symbol::named("b", ty.clone());
+        let eval_merge = |item: i32| {
+            Expr::Eval(Eval(Box::new(Expr::Merge(Merge {
+                builder: Box::new(Expr::Symbol(b.clone())),
+                value: Box::new(item.into()),
+            }))))
+        };
+        let twice = Expr::Tuple(Tuple(vec![eval_merge(1), eval_merge(2)]));
+        let mut env = HashMap::new();
+        env.insert(b.clone(), Value::Builder(Box::new(BuilderValue::new(&ty)?)));
+        assert!(interpret(&eval_merge(1), &env).is_ok());
+        assert!(interpret(&twice, &env).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_vecmerger_out_of_bounds() {
+        for idx in [4, 1 << 40, u64::MAX] {
+            let v = Var::new_vector(vec![1u64, idx]);
+            let vm = Var::vecmerger(I32, BinOpType::Add, 4u64)
+                .pfor(v, |b, _i, e| b.merge((e, 1)))
+                .eval();
+            assert!(interpret(&vm.expr, &HashMap::new()).is_err());
+        }
+        let vm = Var::vecmerger(I32, BinOpType::Add, u64::MAX).eval();
+        assert!(interpret(&vm.expr, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_interpret_lambda_with_env() -> Result<()> {
+        let x = Symbol::named("x", I32);
+        let xv = Expr::Symbol(x.clone());
+        let body = Expr::IfThenElse(IfThenElse {
+            i: Box::new(Expr::BinOp(BinOp {
+                op_ty: BinOpType::GreaterThan,
+                left: Box::new(xv.clone()),
+                right: Box::new(0.into()),
+            })),
+            t: Box::new(Expr::Tuple(Tuple(vec![xv.clone(), 1.into()]))),
+            e: Box::new(Expr::Tuple(Tuple(vec![xv, (-1).into()]))),
+        });
+        let expr = Expr::Lambda(Lambda {
+            params: vec![x.clone()],
+            body: Box::new(body),
+        });
+        let mut env = HashMap::new();
+        env.insert(x, Value::I32(-5));
+        assert_eq!(
+            Value::Tuple(vec![Value::I32(-5), Value::I32(-1)]),
+            interpret(&expr, &env)?
+        );
+        assert!(interpret(&expr, &HashMap::new()).is_err());
+        let program = Program::from(expr);
+        assert_eq!(
+            Value::Tuple(vec![Value::I32(3), Value::I32(1)]),
+            interpret_program(&program, &[Value::I32(3)])?
+        );
+        assert!(interpret_program(&program, &[Value::I64(3)]).is_err());
+        // unbound symbol in a branch not taken
+        let y = Var::<I32>::new_symbol("y", I32);
+        let body = Expr::IfThenElse(IfThenElse {
+            i: Box::new(Expr::from(true)),
+            t: Box::new(1.into()),
+            e: Box::new(y.expr),
+        });
+        assert!(interpret(&body, &HashMap::new()).is_ok());
+        assert!(interpret_program(&Program::from(body), &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_float_semantics() -> Result<()> {
+        let x = Symbol::named("x", F64);
+        let xv = Expr::Symbol(x.clone());
+        let cmp = |op_ty, right: &Expr| {
+            Expr::BinOp(BinOp {
+                op_ty,
+                left: Box::new(xv.clone()),
+                right: Box::new(right.clone()),
+            })
+        };
+        let cast = |ty: Type| {
+            Expr::Cast(Cast {
+                ty,
+                value: Box::new(xv.clone()),
+            })
+        };
+        let one = Expr::Literal(1f64.into());
+        let body = Expr::Tuple(Tuple(vec![
+            cmp(BinOpType::LessThan, &one),
+            cmp(BinOpType::GreaterThanOrEqual, &one),
+            cmp(BinOpType::Equal, &xv),
+            cmp(BinOpType::NotEqual, &xv),
+            cast(I32.into()),
+            cast(U8.into()),
+        ]));
+        let run = |v: f64| {
+            let mut env = HashMap::new();
+            env.insert(x.clone(), Value::F64(v));
+            interpret(&body, &env)
+        };
+        let expected = |cmp: [bool; 4], i: i32, u: u8| {
+            let mut items: Vec<_> = cmp.iter().copied().map(Value::Bool).collect();
+            items.extend(vec![Value::I32(i), Value::U8(u)]);
+            Value::Tuple(items)
+        };
+        // comparisons on NaN are false, except not equal
+        assert_eq!(expected([false, false, false, true], 0, 0), run(f64::NAN)?);
+        // casts saturate at bounds of the integer type
+        assert_eq!(
+            expected([false, true, true, false], i32::MAX, u8::MAX),
+            run(1e10)?
+        );
+        assert_eq!(
+            expected([true, false, true, false], i32::MIN, 0),
+            run(-1e10)?
+        );
+        assert_eq!(expected([true, false, true, false], 0, 0), run(-0.5)?);
+        assert_eq!(expected([false, true, true, false], 3, 3), run(3.7)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_integer_arith() -> Result<()> {
+        let x = Symbol::named("x", I32);
+        let y = Symbol::named("y", I32);
+        let (xv, yv) = (Expr::Symbol(x.clone()), Expr::Symbol(y.clone()));
+        let run = |expr: &Expr, args: [i32; 2]| {
+            let env = vec![
+                (x.clone(), Value::I32(args[0])),
+                (y.clone(), Value::I32(args[1])),
+            ];
+            interpret(expr, &env.into_iter().collect())
+        };
+        let div = Expr::BinOp(BinOp::div(xv.clone(), yv.clone()));
+        let rem = Expr::BinOp(BinOp::rem(xv.clone(), yv));
+        assert!(run(&div, [1, 0]).is_err());
+        assert!(run(&rem, [1, 0]).is_err());
+        // minimum divided by -1 wraps
+        assert_eq!(Value::I32(i32::MIN), run(&div, [i32::MIN, -1])?);
+        assert_eq!(Value::I32(0), run(&rem, [i32::MIN, -1])?);
+        // shift amount out of bit width fails, same as the generated code
+        let shl = Expr::BinOp(BinOp {
+            op_ty: BinOpType::ShiftLeft,
+            left: Box::new(xv.clone()),
+            right: Box::new(Expr::Symbol(y.clone())),
+        });
+        assert!(run(&shl, [1, 32]).is_err());
+        assert!(run(&shl, [1, -1]).is_err());
+
+        let add = Var::<I32>::new_symbol("x", I32) + Var::<I32>::new_symbol("y", I32);
+        assert_eq!(Value::I32(i32::MIN), run(&add.expr, [i32::MAX, 1])?);
+        let neg = Expr::UnaryOp(UnaryOp {
+            op_ty: UnaryOpType::Neg,
+            value: Box::new(xv),
+        });
+        assert_eq!(Value::I32(i32::MIN), run(&neg, [i32::MIN, 0])?);
+        Ok(())
+    }
+}
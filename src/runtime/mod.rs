@@ -0,0 +1,10 @@
+//! Runtime module defines values exchanged with programs at runtime,
+//! and a reference interpreter of expressions.
+mod builder;
+mod convert;
+mod interp;
+mod value;
+
+pub use builder::BuilderValue;
+pub use interp::{interpret, interpret_program};
+pub use value::Value;
@@ -0,0 +1,191 @@
+use super::BuilderValue;
+use crate::ast::*;
+use crate::Result;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Value is the runtime representation of program inputs and outputs.
+///
+/// Float values are compared and hashed by their bits, same as Literal,
+/// so any value can be used as a dictionary key.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Bool(bool),
+    U8(u8),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Vector(Vec<Value>),
+    Dict(HashMap<Value, Value>),
+    Tuple(Vec<Value>),
+    /// Intermediate state of a builder, only exists during interpretation.
+    Builder(Box<BuilderValue>),
+}
+
+impl Value {
+    /// Returns the literal of a scalar or string value.
+    pub fn to_literal(&self) -> Option<Literal> {
+        let lit = match self {
+            Value::Bool(v) => Literal::Bool(*v),
+            Value::U8(v) => Literal::U8(*v),
+            Value::U32(v) => Literal::U32(*v),
+            Value::I32(v) => Literal::I32(*v),
+            Value::U64(v) => Literal::U64(*v),
+            Value::I64(v) => Literal::I64(*v),
+            Value::F32(v) => (*v).into(),
+            Value::F64(v) => (*v).into(),
+            Value::Str(v) => Literal::Str(v.clone()),
+            _ => return None,
+        };
+        Some(lit)
+    }
+
+    /// Returns true if the value conforms to given type.
+    ///
+    /// Items of vector and dict are all checked, so an empty vector
+    /// conforms to any vector type.
+    pub fn is_type_of(&self, ty: &Type) -> bool {
+        match (self, ty) {
+            (Value::Bool(_), Type::Bool(_))
+            | (Value::U8(_), Type::U8(_))
+            | (Value::U32(_), Type::U32(_))
+            | (Value::I32(_), Type::I32(_))
+            | (Value::U64(_), Type::U64(_))
+            | (Value::I64(_), Type::I64(_))
+            | (Value::F32(_), Type::F32(_))
+            | (Value::F64(_), Type::F64(_))
+            | (Value::Str(_), Type::Str(_)) => true,
+            (Value::Vector(items), Type::Vector(VectorType { item_ty })) => {
+                items.iter().all(|item| item.is_type_of(item_ty))
+            }
+            (Value::Dict(m), Type::Dict(DictType { key_ty, value_ty })) => m
+                .iter()
+                .all(|(k, v)| k.is_type_of(key_ty) && v.is_type_of(value_ty)),
+            (Value::Tuple(items), Type::Tuple(TupleType(tys))) => {
+                items.len() == tys.len() && items.iter().zip(tys).all(|(v, t)| v.is_type_of(t))
+            }
+            (Value::Builder(b), ty) => b.is_type_of(ty),
+            _ => false,
+        }
+    }
+
+    /// Check the value conforms to given type.
+    pub fn check_type(&self, ty: &Type) -> Result<()> {
+        if self.is_type_of(ty) {
+            Ok(())
+        } else {
+            Err(runtime_err!("Value {} does not match type[{}]", self, ty))
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(src: Literal) -> Self {
+        match src {
+            Literal::Bool(v) => Value::Bool(v),
+            Literal::U8(v) => Value::U8(v),
+            Literal::I32(v) => Value::I32(v),
+            Literal::I64(v) => Value::I64(v),
+            Literal::U32(v) => Value::U32(v),
+            Literal::U64(v) => Value::U64(v),
+            Literal::F32(v) => Value::F32(f32::from_bits(v)),
+            Literal::F64(v) => Value::F64(f64::from_bits(v)),
+            Literal::Str(v) => Value::Str(v),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bool(v0), Value::Bool(v1)) => v0 == v1,
+            (Value::U8(v0), Value::U8(v1)) => v0 == v1,
+            (Value::U32(v0), Value::U32(v1)) => v0 == v1,
+            (Value::I32(v0), Value::I32(v1)) => v0 == v1,
+            (Value::U64(v0), Value::U64(v1)) => v0 == v1,
+            (Value::I64(v0), Value::I64(v1)) => v0 == v1,
+            (Value::F32(v0), Value::F32(v1)) => v0.to_bits() == v1.to_bits(),
+            (Value::F64(v0), Value::F64(v1)) => v0.to_bits() == v1.to_bits(),
+            (Value::Str(v0), Value::Str(v1)) => v0 == v1,
+            (Value::Vector(v0), Value::Vector(v1)) | (Value::Tuple(v0), Value::Tuple(v1)) => {
+                v0 == v1
+            }
+            (Value::Dict(v0), Value::Dict(v1)) => v0 == v1,
+            (Value::Builder(v0), Value::Builder(v1)) => v0 == v1,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Bool(v) => v.hash(state),
+            Value::U8(v) => v.hash(state),
+            Value::U32(v) => v.hash(state),
+            Value::I32(v) => v.hash(state),
+            Value::U64(v) => v.hash(state),
+            Value::I64(v) => v.hash(state),
+            Value::F32(v) => v.to_bits().hash(state),
+            Value::F64(v) => v.to_bits().hash(state),
+            Value::Str(v) => v.hash(state),
+            Value::Vector(vs) | Value::Tuple(vs) => vs.hash(state),
+            // equal dicts may iterate in different order
+            Value::Dict(m) => m.len().hash(state),
+            Value::Builder(_) => (),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(v) => v.fmt(f),
+            Value::U8(v) => v.fmt(f),
+            Value::U32(v) => v.fmt(f),
+            Value::I32(v) => v.fmt(f),
+            Value::U64(v) => v.fmt(f),
+            Value::I64(v) => v.fmt(f),
+            Value::F32(v) => v.fmt(f),
+            Value::F64(v) => v.fmt(f),
+            Value::Str(v) => write!(f, "{:?}", v),
+            Value::Vector(items) => write_list(f, '[', items, ']'),
+            Value::Dict(m) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in m.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                f.write_str("}")
+            }
+            Value::Tuple(items) => write_list(f, '(', items, ')'),
+            Value::Builder(b) => b.fmt(f),
+        }
+    }
+}
+
+fn write_list(
+    f: &mut std::fmt::Formatter<'_>,
+    open: char,
+    items: &[Value],
+    close: char,
+) -> std::fmt::Result {
+    use std::fmt::Write;
+    f.write_char(open)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    f.write_char(close)
+}
@@ -1,8 +1,22 @@
 use crate::sym::Symbol;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct StmtIter {
-    pub(super) data: Symbol,
-    pub(super) start: Option<Symbol>,
-    pub(super) end: Option<Symbol>,
+    pub(crate) data: Symbol,
+    pub(crate) start: Option<Symbol>,
+    pub(crate) end: Option<Symbol>,
+}
+
+impl std::fmt::Display for StmtIter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use std::fmt::Write;
+        f.write_str("Iter(")?;
+        self.data.fmt(f)?;
+        match (self.start.as_ref(), self.end.as_ref()) {
+            (None, None) => f.write_char(')'),
+            (Some(s), None) => write!(f, ", {}, _)", s),
+            (None, Some(e)) => write!(f, ", _, {})", e),
+            (Some(s), Some(e)) => write!(f, ", {}, {})", s, e),
+        }
+    }
 }
@@ -0,0 +1,371 @@
+use super::*;
+use crate::ast::*;
+use crate::stage::Program;
+use crate::sym::{extract, extract_program, Symbol};
+use crate::Result;
+use std::collections::HashSet;
+
+/// Name of temporary symbols generated by lowering.
+const TMP_NAME: &str = "t";
+
+/// Lower an expression into SIR program.
+///
+/// The expression should be uniquified and type checked.
+/// If the expression is a lambda, its parameters become parameters
+/// of the entry function.
+/// Each lambda of For becomes a separate function, and IfThenElse
+/// becomes a conditional branch on basic blocks.
+pub fn lower(expr: &Expr) -> Result<SirProgram> {
+    let mut lw = Lowerer::new(&extract(expr));
+    match expr {
+        Expr::Lambda(Lambda { params, body }) => lw.lower_function(params.clone(), body)?,
+        other => lw.lower_function(vec![], other)?,
+    };
+    lw.finish()
+}
+
+/// Lower a program into SIR program.
+///
+/// Parameters of the program become parameters of the entry function.
+pub fn lower_program(program: &Program) -> Result<SirProgram> {
+    let mut lw = Lowerer::new(&extract_program(program));
+    lw.lower_function(program.params.clone(), &program.body)?;
+    lw.finish()
+}
+
+struct Lowerer {
+    // functions indexed by id, filled after lowering finished
+    funcs: Vec<Option<SirFunction>>,
+    // stack of functions in lowering, the last is current
+    ctxs: Vec<FunctionContext>,
+    next_tmp_id: u32,
+}
+
+struct FunctionContext {
+    defined: HashSet<Symbol>,
+    captures: Vec<Symbol>,
+    blocks: Vec<(Vec<Stmt>, Option<Terminator>)>,
+    current: usize,
+}
+
+impl Lowerer {
+    fn new(syms: &HashSet<Symbol>) -> Self {
+        // temporary symbols should never conflict with existing ones
+        let next_tmp_id = syms
+            .iter()
+            .filter(|sym| sym.name == TMP_NAME)
+            .map(|sym| sym.id + 1)
+            .max()
+            .unwrap_or(0);
+        Lowerer {
+            funcs: vec![],
+            ctxs: vec![],
+            next_tmp_id,
+        }
+    }
+
+    fn finish(self) -> Result<SirProgram> {
+        let funcs = self
+            .funcs
+            .into_iter()
+            .map(|f| f.ok_or_else(|| compile_err!("Incomplete function in lowering")))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SirProgram { funcs })
+    }
+
+    fn lower_function(&mut self, params: Vec<Symbol>, body: &Expr) -> Result<FunctionId> {
+        let id = FunctionId(self.funcs.len());
+        self.funcs.push(None);
+        self.ctxs.push(FunctionContext {
+            defined: params.iter().cloned().collect(),
+            captures: vec![],
+            blocks: vec![(vec![], None)],
+            current: 0,
+        });
+        let r = self.lower_expr(body);
+        if let Ok(ret) = r.as_ref() {
+            self.terminate(Terminator::Return(ret.clone()));
+        }
+        let ctx = self.ctxs.pop().unwrap();
+        let ret = r?;
+        let blocks = ctx
+            .blocks
+            .into_iter()
+            .enumerate()
+            .map(|(i, (stmts, terminator))| {
+                terminator
+                    .map(|terminator| BasicBlock {
+                        id: BasicBlockId(i),
+                        stmts,
+                        terminator,
+                    })
+                    .ok_or_else(|| compile_err!("Unterminated block B{} in function {}", i, id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.funcs[id.0] = Some(SirFunction {
+            id,
+            params,
+            captures: ctx.captures,
+            ret_ty: ret.ty,
+            blocks,
+        });
+        Ok(id)
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Result<Symbol> {
+        let se = match expr {
+            Expr::Symbol(sym) => return self.resolve(sym),
+            Expr::IfThenElse(ite) => return self.lower_if_then_else(ite, expr.ty()),
+            Expr::Let(lt) => return self.lower_let(lt),
+            Expr::Literal(lit) => StmtExpr::Literal(lit.clone()),
+            Expr::Broadcast(Broadcast { value }) => StmtExpr::Broadcast(self.lower_expr(value)?),
+            Expr::BinOp(BinOp { op_ty, left, right }) => StmtExpr::BinOp {
+                op_ty: *op_ty,
+                left: self.lower_expr(left)?,
+                right: self.lower_expr(right)?,
+            },
+            Expr::UnaryOp(UnaryOp { op_ty, value }) => StmtExpr::UnaryOp {
+                op_ty: *op_ty,
+                value: self.lower_expr(value)?,
+            },
+            Expr::Cast(Cast { ty, value }) => StmtExpr::Cast {
+                ty: ty.clone(),
+                value: self.lower_expr(value)?,
+            },
+            Expr::GetField(GetField { tuple, index }) => StmtExpr::GetField {
+                value: self.lower_expr(tuple)?,
+                index: *index,
+            },
+            Expr::Length(Length(value)) => StmtExpr::Length(self.lower_expr(value)?),
+            Expr::Lookup(Lookup { dict, index }) => StmtExpr::Lookup {
+                value: self.lower_expr(dict)?,
+                index: self.lower_expr(index)?,
+            },
+            Expr::For(fr) => self.lower_for(fr)?,
+            Expr::Merge(Merge { builder, value }) => StmtExpr::Merge {
+                builder: self.lower_expr(builder)?,
+                value: self.lower_expr(value)?,
+            },
+            Expr::Lambda(_) => {
+                return Err(compile_err!(
+                    "Lambda outside For is not supported: {}",
+                    expr
+                ))
+            }
+            Expr::Vector(Vector { items, .. }) => StmtExpr::NewVector(self.lower_exprs(items)?),
+            Expr::Dict(NewDict { key_ty, value_ty }) => StmtExpr::NewDict {
+                key_ty: key_ty.clone(),
+                value_ty: value_ty.clone(),
+            },
+            Expr::Tuple(Tuple(items)) => StmtExpr::NewTuple(self.lower_exprs(items)?),
+            Expr::NewAppender(NewAppender { item_ty }) => StmtExpr::NewAppender {
+                item_ty: item_ty.clone(),
+            },
+            Expr::NewMerger(NewMerger { item_ty, op_ty }) => StmtExpr::NewMerger {
+                item_ty: item_ty.clone(),
+                op_ty: *op_ty,
+            },
+            Expr::NewDictMerger(NewDictMerger {
+                key_ty,
+                value_ty,
+                op_ty,
+            }) => StmtExpr::NewDictMerger {
+                key_ty: key_ty.clone(),
+                value_ty: value_ty.clone(),
+                op_ty: *op_ty,
+            },
+            Expr::NewGroupMerger(NewGroupMerger { key_ty, value_ty }) => StmtExpr::NewGroupMerger {
+                key_ty: key_ty.clone(),
+                value_ty: value_ty.clone(),
+            },
+            Expr::NewVecMerger(NewVecMerger {
+                item_ty,
+                op_ty,
+                len,
+            }) => StmtExpr::NewVecMerger {
+                item_ty: item_ty.clone(),
+                op_ty: *op_ty,
+                len: self.lower_expr(len)?,
+            },
+            Expr::Eval(Eval(value)) => StmtExpr::Eval(self.lower_expr(value)?),
+        };
+        let sym = self.new_tmp(expr.ty());
+        self.push_stmt(Stmt {
+            sym: sym.clone(),
+            expr: se,
+        });
+        Ok(sym)
+    }
+
+    fn lower_exprs(&mut self, exprs: &[Expr]) -> Result<Vec<Symbol>> {
+        exprs.iter().map(|e| self.lower_expr(e)).collect()
+    }
+
+    fn lower_if_then_else(&mut self, ite: &IfThenElse, ty: Type) -> Result<Symbol> {
+        let cond = self.lower_expr(&ite.i)?;
+        let result = self.new_tmp(ty);
+        let on_true = self.new_block();
+        let on_false = self.new_block();
+        let join = self.new_block();
+        self.terminate(Terminator::Branch {
+            cond,
+            on_true,
+            on_false,
+        });
+        for (block, branch) in [(on_true, &ite.t), (on_false, &ite.e)] {
+            self.switch_to(block);
+            let value = self.lower_expr(branch)?;
+            self.push_stmt(Stmt {
+                sym: result.clone(),
+                expr: StmtExpr::Assign(value),
+            });
+            self.terminate(Terminator::JumpBlock(join));
+        }
+        self.switch_to(join);
+        Ok(result)
+    }
+
+    fn lower_let(&mut self, lt: &Let) -> Result<Symbol> {
+        let value = self.lower_expr(&lt.value)?;
+        self.ctx().defined.insert(lt.sym.clone());
+        self.push_stmt(Stmt {
+            sym: lt.sym.clone(),
+            expr: StmtExpr::Assign(value),
+        });
+        self.lower_expr(&lt.body)
+    }
+
+    fn lower_for(&mut self, fr: &For) -> Result<StmtExpr> {
+        let mut iters = Vec::with_capacity(fr.iters.len());
+        for it in &fr.iters {
+            let data = self.lower_expr(&it.data)?;
+            let start = it.start.as_ref().map(|s| self.lower_expr(s)).transpose()?;
+            let end = it.end.as_ref().map(|e| self.lower_expr(e)).transpose()?;
+            iters.push(StmtIter { data, start, end });
+        }
+        let builder = self.lower_expr(&fr.builder)?;
+        let env = fr.env.as_ref().map(|e| self.lower_expr(e)).transpose()?;
+        let func = match fr.func.as_ref() {
+            Expr::Lambda(Lambda { params, body }) => self.lower_function(params.clone(), body)?,
+            other => return Err(compile_err!("Non-lambda function {} in For", other)),
+        };
+        Ok(StmtExpr::For {
+            iters,
+            builder,
+            func,
+            env,
+        })
+    }
+
+    /// Resolve the symbol in current or outer functions.
+    /// If the symbol is defined in outer function, it is captured by all
+    /// functions in between.
+    fn resolve(&mut self, sym: &Symbol) -> Result<Symbol> {
+        let pos = self
+            .ctxs
+            .iter()
+            .rposition(|ctx| ctx.defined.contains(sym))
+            .ok_or_else(|| compile_err!("Undefined symbol {}", sym))?;
+        for ctx in &mut self.ctxs[pos + 1..] {
+            ctx.defined.insert(sym.clone());
+            ctx.captures.push(sym.clone());
+        }
+        Ok(sym.clone())
+    }
+
+    fn new_tmp(&mut self, ty: Type) -> Symbol {
+        let sym = Symbol::new(TMP_NAME, ty, self.next_tmp_id);
+        self.next_tmp_id += 1;
+        self.ctx().defined.insert(sym.clone());
+        sym
+    }
+
+    fn new_block(&mut self) -> BasicBlockId {
+        let ctx = self.ctx();
+        ctx.blocks.push((vec![], None));
+        BasicBlockId(ctx.blocks.len() - 1)
+    }
+
+    fn switch_to(&mut self, block: BasicBlockId) {
+        self.ctx().current = block.0;
+    }
+
+    fn push_stmt(&mut self, stmt: Stmt) {
+        let ctx = self.ctx();
+        ctx.blocks[ctx.current].0.push(stmt);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let ctx = self.ctx();
+        let block = &mut ctx.blocks[ctx.current];
+        debug_assert!(block.1.is_none(), "block already terminated");
+        block.1 = Some(terminator);
+    }
+
+    #[inline]
+    fn ctx(&mut self) -> &mut FunctionContext {
+        self.ctxs.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sym::uniquify;
+
+    #[test]
+    fn test_lower_pfor() {
+        let v1 = Var::new_vector(vec![1, 2, 3]);
+        let m1 = Var::new_merger(I32, BinOpType::Add);
+        let m2 = m1.pfor(v1, |b, _i, e: Var<I32>| b.merge(e)).eval();
+        let mut expr = m2.expr;
+        uniquify(&mut expr).unwrap();
+        let prog = lower(&expr).unwrap();
+        println!("{}", prog);
+        assert_eq!(2, prog.funcs.len());
+        assert_eq!(Type::I32(I32), prog.entry().ret_ty);
+        let body = prog.func(FunctionId(1));
+        assert_eq!(3, body.params.len());
+        assert!(body.captures.is_empty());
+    }
+
+    #[test]
+    fn test_lower_if_then_else() {
+        let x = Symbol::named("x", I32);
+        let body = Expr::IfThenElse(IfThenElse {
+            i: Box::new(Expr::BinOp(BinOp {
+                op_ty: BinOpType::GreaterThan,
+                left: Box::new(Expr::Symbol(x.clone())),
+                right: Box::new(0.into()),
+            })),
+            t: Box::new(Expr::Symbol(x.clone())),
+            e: Box::new(Expr::UnaryOp(UnaryOp::neg(Expr::Symbol(x.clone())))),
+        });
+        let expr = Expr::Lambda(Lambda {
+            params: vec![x],
+            body: Box::new(body),
+        });
+        let prog = lower(&expr).unwrap();
+        println!("{}", prog);
+        let entry = prog.entry();
+        assert_eq!(1, entry.params.len());
+        assert_eq!(4, entry.blocks.len());
+    }
+
+    #[test]
+    fn test_lower_captures() {
+        let x = Symbol::named("x", I32);
+        let v1 = Var::new_vector(vec![1, 2, 3]);
+        let m1 = Var::new_merger(I32, BinOpType::Add);
+        let xv = Var::<I32>::clone_symbol(x.clone());
+        let m2 = m1.pfor(v1, move |b, _i, e: Var<I32>| b.merge(e + xv));
+        let expr = Expr::Lambda(Lambda {
+            params: vec![x.clone()],
+            body: Box::new(m2.eval().expr),
+        });
+        let prog = lower(&expr).unwrap();
+        println!("{}", prog);
+        assert_eq!(vec![x], prog.func(FunctionId(1)).captures);
+    }
+}
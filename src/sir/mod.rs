@@ -1,12 +1,36 @@
+//! SIR(Sequential Intermediate Representation) module defines the
+//! flattened form of a program used by code generation.
+//!
+//! Each function consists of basic blocks, and each basic block is
+//! a sequence of statements ended by a terminator.
+//! Every statement assigns the result of a simple expression over
+//! symbols to a new symbol.
 mod iter;
+mod lower;
+mod program;
 mod stmt;
 
-use std::collections::HashMap;
+pub use iter::StmtIter;
+pub use lower::{lower, lower_program};
+pub use program::{BasicBlock, SirFunction, SirProgram, Terminator};
+pub use stmt::{Stmt, StmtExpr};
 
-pub(self) struct BasicBlockId(usize);
+/// Identifier of a basic block within its function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BasicBlockId(pub(crate) usize);
 
-pub(self) struct FunctionId(usize);
+impl std::fmt::Display for BasicBlockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "B{}", self.0)
+    }
+}
 
-pub(self) struct ProgramSite(FunctionId, BasicBlockId);
+/// Identifier of a function within its program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FunctionId(pub(crate) usize);
 
-// pub(self) type SiteSymbolMap = HashMap<StmtExpr, Symbol>;
+impl std::fmt::Display for FunctionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "F{}", self.0)
+    }
+}
@@ -0,0 +1,115 @@
+use super::{BasicBlockId, FunctionId, Stmt};
+use crate::ast::Type;
+use crate::sym::Symbol;
+
+/// Terminator ends a basic block and transfers the control.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Terminator {
+    /// Jump to one of the two blocks based on the bool condition.
+    Branch {
+        cond: Symbol,
+        on_true: BasicBlockId,
+        on_false: BasicBlockId,
+    },
+    /// Jump to given block unconditionally.
+    JumpBlock(BasicBlockId),
+    /// Return the symbol from current function.
+    Return(Symbol),
+}
+
+impl std::fmt::Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terminator::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => write!(f, "branch {} {} {}", cond, on_true, on_false),
+            Terminator::JumpBlock(id) => write!(f, "jump {}", id),
+            Terminator::Return(sym) => write!(f, "return {}", sym),
+        }
+    }
+}
+
+/// Basic block is a sequence of statements without any branch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BasicBlock {
+    pub(crate) id: BasicBlockId,
+    pub(crate) stmts: Vec<Stmt>,
+    pub(crate) terminator: Terminator,
+}
+
+impl std::fmt::Display for BasicBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}:", self.id)?;
+        for stmt in &self.stmts {
+            writeln!(f, "  {}", stmt)?;
+        }
+        writeln!(f, "  {}", self.terminator)
+    }
+}
+
+/// Function of SIR program.
+///
+/// The first basic block is the entry of the function.
+/// Captures are symbols defined in outer function and referred in
+/// this function, they are passed in after the parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SirFunction {
+    pub(crate) id: FunctionId,
+    pub(crate) params: Vec<Symbol>,
+    pub(crate) captures: Vec<Symbol>,
+    pub(crate) ret_ty: Type,
+    pub(crate) blocks: Vec<BasicBlock>,
+}
+
+impl SirFunction {
+    /// Returns all symbols passed into this function in order.
+    pub fn args(&self) -> impl Iterator<Item = &Symbol> {
+        self.params.iter().chain(self.captures.iter())
+    }
+}
+
+impl std::fmt::Display for SirFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}(", self.id)?;
+        for (i, arg) in self.args().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", arg, arg.ty)?;
+        }
+        writeln!(f, ") -> {}", self.ret_ty)?;
+        for block in &self.blocks {
+            block.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// SIR program is a list of functions, the first one is the entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SirProgram {
+    pub(crate) funcs: Vec<SirFunction>,
+}
+
+impl SirProgram {
+    /// Returns the entry function.
+    pub fn entry(&self) -> &SirFunction {
+        &self.funcs[0]
+    }
+
+    /// Returns the function by given id.
+    pub fn func(&self, id: FunctionId) -> &SirFunction {
+        &self.funcs[id.0]
+    }
+}
+
+impl std::fmt::Display for SirProgram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for func in &self.funcs {
+            func.fmt(f)?;
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,26 @@
+use super::{FunctionId, StmtIter};
 use crate::ast::*;
-use crate::sir::iter::StmtIter;
 use crate::sym::Symbol;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+/// Statement assigns result of a simple expression to a symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Stmt {
-    sym: Option<Symbol>,
-    expr: StmtExpr,
+    pub(crate) sym: Symbol,
+    pub(crate) expr: StmtExpr,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.sym, self.expr)
+    }
+}
+
+/// StmtExpr is the flattened form of Expr, whose operands are all symbols.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum StmtExpr {
+    /// A literal value.
+    Literal(Literal),
+    /// Assign another symbol.
     Assign(Symbol),
     /// Broadcasts a scalar into a vector.
     Broadcast(Symbol),
@@ -20,59 +31,36 @@ pub enum StmtExpr {
         right: Symbol,
     },
     /// Applies a unary operator to child expressions.
-    UnaryOp {
-        op_ty: UnaryOpType,
-        value: Symbol,
-    },
+    UnaryOp { op_ty: UnaryOpType, value: Symbol },
     /// Cast a scalar expression to another type.
-    Cast {
-        ty: Type,
-        value: Symbol,
-    },
+    Cast { ty: Type, value: Symbol },
     /// Access a tuple field at given index.
-    GetField {
-        value: Symbol,
-        index: u32,
-    },
+    GetField { value: Symbol, index: u32 },
     /// Get the length of a vector as an u64.
     Length(Symbol),
     /// Lookup a value in Dict.
-    Lookup {
-        value: Symbol,
-        index: Symbol,
-    },
-    /// Evaluate different branch based on condition.
-    IfThenElse {
-        i: Symbol,
-        t: Symbol,
-        e: Symbol,
-    },
-    /// Update a builder in parallel by itearating over data.
+    Lookup { value: Symbol, index: Symbol },
+    /// Update a builder by iterating over data, calling the function
+    /// with the builder, index and item of each iteration, followed by
+    /// the environment if any.
     For {
-        data: Vec<StmtIter>,
-        start: Option<Symbol>,
-        end: Option<Symbol>,
-    },
-    /// Update a builder value, returning a new builder.
-    Merge {
+        iters: Vec<StmtIter>,
         builder: Symbol,
-        value: Symbol,
+        func: FunctionId,
+        env: Option<Symbol>,
     },
-    /// An expression representing a function.
-    // Lambda(Lambda),
+    /// Update a builder value, returning a new builder.
+    Merge { builder: Symbol, value: Symbol },
     /// Construct a new vector.
     NewVector(Vec<Symbol>),
     /// Construct a new dictionary.
-    // NewDict(NewDict),
+    NewDict { key_ty: Type, value_ty: Type },
+    /// Construct a new tuple.
+    NewTuple(Vec<Symbol>),
     /// Construct a new appender.
-    NewAppender {
-        item_ty: Type,
-    },
+    NewAppender { item_ty: Type },
     /// Construct a new merger.
-    NewMerger {
-        item_ty: Type,
-        op_ty: BinOpType,
-    },
+    NewMerger { item_ty: Type, op_ty: BinOpType },
     /// Construct a new dictmerger.
     NewDictMerger {
         key_ty: Type,
@@ -80,15 +68,88 @@ pub enum StmtExpr {
         op_ty: BinOpType,
     },
     /// Construct a new groupmerger.
-    NewGroupMerger {
-        key_ty: Type,
-        value_ty: Type,
-    },
-    /// Construct a new vecmerger.
+    NewGroupMerger { key_ty: Type, value_ty: Type },
+    /// Construct a new vecmerger of given length.
     NewVecMerger {
         item_ty: Type,
         op_ty: BinOpType,
+        len: Symbol,
     },
     /// Consume a builder and return its result
     Eval(Symbol),
 }
+
+impl std::fmt::Display for StmtExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StmtExpr::Literal(lit) => lit.fmt(f),
+            StmtExpr::Assign(sym) => sym.fmt(f),
+            StmtExpr::Broadcast(sym) => write!(f, "Broadcast({})", sym),
+            StmtExpr::BinOp { op_ty, left, right } => write!(f, "{}({}, {})", op_ty, left, right),
+            StmtExpr::UnaryOp { op_ty, value } => write!(f, "{}({})", op_ty, value),
+            StmtExpr::Cast { ty, value } => write!(f, "Cast<{}>({})", ty, value),
+            StmtExpr::GetField { value, index } => write!(f, "GetField({}, {})", value, index),
+            StmtExpr::Length(sym) => write!(f, "Length({})", sym),
+            StmtExpr::Lookup { value, index } => write!(f, "Lookup({}, {})", value, index),
+            StmtExpr::For {
+                iters,
+                builder,
+                func,
+                env,
+            } => {
+                f.write_str("For([")?;
+                write_list(f, iters)?;
+                write!(f, "], {}, {}", builder, func)?;
+                if let Some(env) = env {
+                    write!(f, ", {}", env)?;
+                }
+                f.write_str(")")
+            }
+            StmtExpr::Merge { builder, value } => write!(f, "Merge({}, {})", builder, value),
+            StmtExpr::NewVector(items) => {
+                f.write_str("NewVector(")?;
+                write_list(f, items)?;
+                f.write_str(")")
+            }
+            StmtExpr::NewDict { key_ty, value_ty } => {
+                write!(f, "NewDict<{}, {}>", key_ty, value_ty)
+            }
+            StmtExpr::NewTuple(items) => {
+                f.write_str("(")?;
+                write_list(f, items)?;
+                f.write_str(")")
+            }
+            StmtExpr::NewAppender { item_ty } => write!(f, "NewAppender<{}>", item_ty),
+            StmtExpr::NewMerger { item_ty, op_ty } => {
+                write!(f, "NewMerger<{}, {}>", item_ty, op_ty)
+            }
+            StmtExpr::NewDictMerger {
+                key_ty,
+                value_ty,
+                op_ty,
+            } => write!(f, "NewDictMerger<{}, {}, {}>", key_ty, value_ty, op_ty),
+            StmtExpr::NewGroupMerger { key_ty, value_ty } => {
+                write!(f, "NewGroupMerger<{}, {}>", key_ty, value_ty)
+            }
+            StmtExpr::NewVecMerger {
+                item_ty,
+                op_ty,
+                len,
+            } => write!(f, "NewVecMerger<{}, {}>({})", item_ty, op_ty, len),
+            StmtExpr::Eval(sym) => write!(f, "Eval({})", sym),
+        }
+    }
+}
+
+fn write_list<T: std::fmt::Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    items: &[T],
+) -> std::fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        item.fmt(f)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// ids start from 1, so they never conflict with named symbols of id 0
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Returns an id never returned before in this process.
+///
+/// Symbols created in staging take ids from this supply, so that
+/// every expression is alpha-unique since construction.
+pub fn fresh_id() -> u32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_fresh_id() {
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..100).map(|_| fresh_id()).collect::<Vec<_>>()))
+            .collect();
+        let ids: HashSet<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        assert_eq!(400, ids.len());
+        assert!(!ids.contains(&0));
+    }
+}
@@ -1 +1,6 @@
+//! Stage module defines the top-level program to be staged.
+mod fresh;
+mod program;
 
+pub use fresh::fresh_id;
+pub use program::Program;
@@ -0,0 +1,79 @@
+use crate::ast::*;
+use crate::sym::Symbol;
+
+/// Program is a body expression with typed parameters.
+///
+/// Parameters are inputs of the program provided at run time,
+/// so they are bound symbols in the body.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Program {
+    pub(crate) params: Vec<Symbol>,
+    pub(crate) body: Expr,
+}
+
+impl Program {
+    /// Create a new program with given parameters and body.
+    pub fn new<E: Into<Expr>>(params: Vec<Symbol>, body: E) -> Self {
+        Program {
+            params,
+            body: body.into(),
+        }
+    }
+
+    pub fn params(&self) -> &[Symbol] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Expr {
+        &self.body
+    }
+}
+
+impl TypeInference for Program {
+    fn ty(&self) -> Type {
+        Type::Lambda(LambdaType {
+            args_ty: self.params.iter().map(|sym| sym.ty.clone()).collect(),
+            ret_ty: Box::new(self.body.ty()),
+        })
+    }
+}
+
+/// A lambda becomes a program with its parameters, and any other
+/// expression becomes a program without parameter.
+impl From<Expr> for Program {
+    fn from(src: Expr) -> Self {
+        match src {
+            Expr::Lambda(Lambda { params, body }) => Program {
+                params,
+                body: *body,
+            },
+            other => Program {
+                params: vec![],
+                body: other,
+            },
+        }
+    }
+}
+
+impl From<Program> for Expr {
+    fn from(src: Program) -> Self {
+        Expr::Lambda(Lambda {
+            params: src.params,
+            body: Box::new(src.body),
+        })
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Program(")?;
+        for (i, p) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", p, p.ty)?;
+        }
+        f.write_str(") ")?;
+        self.body.fmt(f)
+    }
+}
@@ -1,19 +1,56 @@
 use super::Symbol;
-use crate::ast::{Expr, ExprVisitor, Lambda};
+use crate::ast::{Expr, ExprVisitor, Lambda, Let};
+use crate::stage::Program;
 use crate::Result;
 use std::collections::HashSet;
 
+/// Extract all symbols in the expression, both free and bound.
 pub fn extract(expr: &Expr) -> HashSet<Symbol> {
     let mut ex = Extract {
         syms: HashSet::new(),
+        bound_only: false,
     };
     // this transformation won't fail
     ex.visit_expr(expr).unwrap();
     ex.syms
 }
 
+/// Returns symbols bound by lambda or let in the expression.
+pub fn bound_symbols(expr: &Expr) -> HashSet<Symbol> {
+    let mut ex = Extract {
+        syms: HashSet::new(),
+        bound_only: true,
+    };
+    // this transformation won't fail
+    ex.visit_expr(expr).unwrap();
+    ex.syms
+}
+
+/// Returns symbols the expression depends on from its environment.
+///
+/// Symbols bound by lambda or let are excluded in their scope, so a
+/// shadowed symbol is only free if it is used outside of the binding.
+pub fn free_symbols(expr: &Expr) -> HashSet<Symbol> {
+    let mut fs = FreeSymbols {
+        bound: vec![],
+        free: HashSet::new(),
+    };
+    // this transformation won't fail
+    fs.visit_expr(expr).unwrap();
+    fs.free
+}
+
+/// Extract all symbols in the program, including its parameters.
+pub fn extract_program(program: &Program) -> HashSet<Symbol> {
+    let mut syms = extract(&program.body);
+    syms.extend(program.params.iter().cloned());
+    syms
+}
+
 struct Extract {
     syms: HashSet<Symbol>,
+    // skip symbols not at binding position
+    bound_only: bool,
 }
 
 impl ExprVisitor for Extract {
@@ -23,7 +60,13 @@ impl ExprVisitor for Extract {
                 self.visit_lambda(lambda)?;
             }
             Expr::Symbol(sym) => {
+                if !self.bound_only {
+                    self.syms.insert(sym.clone());
+                }
+            }
+            Expr::Let(Let { sym, .. }) => {
                 self.syms.insert(sym.clone());
+                expr.traverse_children(self)?;
             }
             _ => expr.traverse_children(self)?,
         };
@@ -34,7 +77,42 @@ impl ExprVisitor for Extract {
         for p in &lambda.params {
             self.syms.insert(p.clone());
         }
-        lambda.body.traverse_children(self)?;
+        self.visit_expr(&lambda.body)
+    }
+}
+
+struct FreeSymbols {
+    // symbols bound in current scope
+    bound: Vec<Symbol>,
+    free: HashSet<Symbol>,
+}
+
+impl ExprVisitor for FreeSymbols {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Symbol(sym) => {
+                if !self.bound.contains(sym) {
+                    self.free.insert(sym.clone());
+                }
+                Ok(())
+            }
+            Expr::Let(Let { sym, value, body }) => {
+                // value is out of scope of the symbol
+                self.visit_expr(value)?;
+                self.bound.push(sym.clone());
+                self.visit_expr(body)?;
+                self.bound.pop();
+                Ok(())
+            }
+            other => other.traverse_children(self),
+        }
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        let n = self.bound.len();
+        self.bound.extend(lambda.params.iter().cloned());
+        self.visit_expr(&lambda.body)?;
+        self.bound.truncate(n);
         Ok(())
     }
 }
@@ -52,4 +130,45 @@ mod tests {
         let v3 = v2 + Var::new_symbol("b", I32);
         assert_eq!(2, extract(&v3.expr).len());
     }
+
+    #[test]
+    fn test_free_and_bound_symbols() {
+        let x = Symbol::named("x", I32);
+        let y = Symbol::named("y", I32);
+        let xv = Var::<I32>::clone_symbol(x.clone());
+        let yv = Var::<I32>::clone_symbol(y.clone());
+        // lambda whose body is only its parameter
+        let id = Expr::Lambda(Lambda {
+            params: vec![x.clone()],
+            body: Box::new(xv.expr.clone()),
+        });
+        assert!(extract(&id).contains(&x));
+        assert!(free_symbols(&id).is_empty());
+        assert_eq!(1, bound_symbols(&id).len());
+
+        // x is free in value, but bound in body of the let
+        let e = Expr::Let(Let {
+            sym: x.clone(),
+            value: Box::new((xv.clone() + yv).expr),
+            body: Box::new((xv * 2).expr),
+        });
+        let free = free_symbols(&e);
+        assert_eq!(2, free.len());
+        assert!(free.contains(&x) && free.contains(&y));
+        let bound = bound_symbols(&e);
+        assert_eq!(1, bound.len());
+        assert!(bound.contains(&x));
+
+        // parameters of loop function are bound
+        let v = Var::new_vector(vec![1, 2, 3]);
+        let z = Var::<I32>::new_symbol("z", I32);
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v, move |b, _i, e: Var<I32>| b.merge(e + z))
+            .eval();
+        let free = free_symbols(&sum.expr);
+        assert_eq!(1, free.len());
+        assert!(free.contains(&Symbol::named("z", I32)));
+        assert_eq!(3, bound_symbols(&sum.expr).len());
+        assert_eq!(4, extract(&sum.expr).len());
+    }
 }
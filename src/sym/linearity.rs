@@ -0,0 +1,239 @@
+use super::Symbol;
+use crate::ast::*;
+use crate::stage::Program;
+use crate::Result;
+
+/// Check each builder bound by lambda or let is consumed exactly once.
+///
+/// Both branches of a condition must consume the builder equally, and
+/// a builder can't be captured by a function, since the function may
+/// be called many times in a loop.
+/// Each field of a tuple of builders is checked separately.
+pub fn check_linearity(expr: &Expr) -> Result<()> {
+    Linearity.visit_expr(expr)
+}
+
+/// Check linearity of the program, including its parameters.
+pub fn check_linearity_program(program: &Program) -> Result<()> {
+    for param in &program.params {
+        check_consumed(param, &program.body)?;
+    }
+    check_linearity(&program.body)
+}
+
+struct Linearity;
+
+impl ExprVisitor for Linearity {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        if let Expr::Let(Let { sym, body, .. }) = expr {
+            check_consumed(sym, body)?;
+        }
+        expr.traverse_children(self)
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        for param in &lambda.params {
+            check_consumed(param, &lambda.body)?;
+        }
+        self.visit_expr(&lambda.body)
+    }
+}
+
+/// Check the symbol is consumed once in its scope if it is a builder.
+fn check_consumed(sym: &Symbol, scope: &Expr) -> Result<()> {
+    if !sym.ty.is_builder() {
+        return Ok(());
+    }
+    let n = match &sym.ty {
+        Type::Tuple(TupleType(tys)) => tys.len(),
+        _ => 1,
+    };
+    let mut uc = UseCounter::new(sym, n);
+    uc.visit_expr(scope)?;
+    for (index, count) in uc.counts.into_iter().enumerate() {
+        if count == 1 {
+            continue;
+        }
+        return Err(if n == 1 {
+            compile_err!(
+                "builder {} is consumed {} times in {}, expected once",
+                sym,
+                count,
+                scope
+            )
+        } else {
+            compile_err!(
+                "field {} of builder {} is consumed {} times in {}, expected once",
+                index,
+                sym,
+                count,
+                scope
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Counts uses of each field of the builder along any path.
+struct UseCounter<'a> {
+    sym: &'a Symbol,
+    counts: Vec<usize>,
+}
+
+impl<'a> UseCounter<'a> {
+    fn new(sym: &'a Symbol, n: usize) -> Self {
+        UseCounter {
+            sym,
+            counts: vec![0; n],
+        }
+    }
+
+    fn count(&self, expr: &Expr) -> Result<Vec<usize>> {
+        let mut uc = UseCounter::new(self.sym, self.counts.len());
+        uc.visit_expr(expr)?;
+        Ok(uc.counts)
+    }
+
+    fn add(&mut self, counts: &[usize]) {
+        for (c, n) in self.counts.iter_mut().zip(counts) {
+            *c += n;
+        }
+    }
+}
+
+impl ExprVisitor for UseCounter<'_> {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Symbol(s) if s == self.sym => {
+                for c in &mut self.counts {
+                    *c += 1;
+                }
+                Ok(())
+            }
+            Expr::GetField(GetField { tuple, index }) => match tuple.as_ref() {
+                Expr::Symbol(s) if s == self.sym && self.counts.len() > 1 => {
+                    match self.counts.get_mut(*index as usize) {
+                        Some(c) => *c += 1,
+                        None => return Err(compile_err!("Invalid field {} of {}", index, s)),
+                    }
+                    Ok(())
+                }
+                _ => expr.traverse_children(self),
+            },
+            Expr::IfThenElse(IfThenElse { i, t, e }) => {
+                self.visit_expr(i)?;
+                let ct = self.count(t)?;
+                let ce = self.count(e)?;
+                if ct != ce {
+                    return Err(compile_err!(
+                        "builder {} is consumed differently in branches of {}",
+                        self.sym,
+                        expr
+                    ));
+                }
+                self.add(&ct);
+                Ok(())
+            }
+            Expr::Let(Let { sym, value, body }) => {
+                self.visit_expr(value)?;
+                // shadowed in body
+                if sym != self.sym {
+                    self.visit_expr(body)?;
+                }
+                Ok(())
+            }
+            other => other.traverse_children(self),
+        }
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        if lambda.params.contains(self.sym) {
+            return Ok(());
+        }
+        if self.count(&lambda.body)?.iter().any(|c| *c > 0) {
+            return Err(compile_err!(
+                "builder {} is captured by function {}",
+                self.sym,
+                Expr::Lambda(lambda.clone())
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::opt::horizontal_fusion;
+
+    fn gt1(e: &Var<I32>) -> Var<Bool> {
+        e.clone().gt(Var::lit_i32(1))
+    }
+
+    #[test]
+    fn test_linearity() -> Result<()> {
+        let v = Var::new_vector(vec![1, 2, 3]);
+        // a chain of merges consumes the builder once
+        let filter = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| {
+                gt1(&e).if_then_else(b.clone().merge(e.clone()).merge(e), b)
+            })
+            .eval();
+        check_linearity(&filter.expr)?;
+
+        // consumed in one branch only
+        let dropped = Var::new_merger(I32, BinOpType::Add).pfor(v, |b, _i, e: Var<I32>| {
+            gt1(&e).if_then_else(b.merge(e), Var::new_merger(I32, BinOpType::Add))
+        });
+        assert!(check_linearity(&dropped.expr).is_err());
+
+        // used after eval
+        let m = Var::new_merger(I32, BinOpType::Add);
+        let used = Var::let_in(m.clone(), |b| {
+            Var::new_tuple((b.clone().eval(), b.merge(1).eval()))
+        });
+        assert!(check_linearity(&used.expr).is_err());
+        let once = Var::let_in(m, |b| b.merge(1).eval());
+        check_linearity(&once.expr)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_linearity_captures_and_tuples() -> Result<()> {
+        let v = Var::new_vector(vec![1, 2, 3]);
+        // outer builder merged in each iteration of inner loop
+        let captured =
+            Var::new_merger(I32, BinOpType::Add).pfor(v.clone(), |b, _i, _e: Var<I32>| {
+                let outer = b.clone();
+                let inner = Var::appender(I32)
+                    .pfor(Var::new_vector(vec![4, 5]), move |b2, _i, e: Var<I32>| {
+                        b2.merge(outer.merge(e).eval())
+                    })
+                    .eval();
+                b.merge(inner.len().cast::<I32>())
+            });
+        let err = check_linearity(&captured.expr).unwrap_err();
+        assert!(err.to_string().contains("captured"));
+
+        // each field of a tuple of builders is consumed once
+        let sum = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v.clone(), |b, _i, e: Var<I32>| b.merge(e))
+            .eval();
+        let mut fused = Var::new_tuple((sum.clone(), sum)).expr;
+        assert!(horizontal_fusion(&mut fused)?);
+        check_linearity(&fused)?;
+        if let Expr::Eval(Eval(b)) = &mut fused {
+            if let Expr::For(pfor) = b.as_mut() {
+                if let Expr::Lambda(Lambda { body, .. }) = pfor.func.as_mut() {
+                    if let Expr::Tuple(Tuple(items)) = body.as_mut() {
+                        items[1] = items[0].clone();
+                    }
+                }
+            }
+        }
+        let err = check_linearity(&fused).unwrap_err();
+        assert!(err.to_string().contains("field 0"));
+        Ok(())
+    }
+}
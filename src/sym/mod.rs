@@ -1,8 +1,21 @@
 mod extract;
+mod linearity;
+mod purity;
 mod simplify;
+mod substitute;
+mod typecheck;
 mod uniquify;
 
+pub use extract::{bound_symbols, extract, extract_program, free_symbols};
+pub use linearity::{check_linearity, check_linearity_program};
+pub use purity::is_pure;
+pub use simplify::{simplify, simplify_with_options, SimplifyOptions};
+pub use substitute::substitute;
+pub use typecheck::{typecheck, typecheck_program};
+pub use uniquify::{uniquify, uniquify_program};
+
 use crate::ast::{Builder, Expr, Merge, Type, TypeInference};
+use crate::stage::fresh_id;
 
 /// Symbol represents a named variable.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -23,6 +36,17 @@ impl Symbol {
         Self::named("_", ty)
     }
 
+    /// Create a symbol with a fresh id, the name is only for display.
+    #[inline]
+    pub fn fresh_named<S: Into<String>, T: Into<Type>>(name: S, ty: T) -> Self {
+        Self::new(name, ty, fresh_id())
+    }
+
+    #[inline]
+    pub fn fresh<T: Into<Type>>(ty: T) -> Self {
+        Self::fresh_named("_", ty)
+    }
+
     #[inline]
     pub fn new<S: Into<String>, T: Into<Type>>(name: S, ty: T, id: u32) -> Self {
         Symbol {
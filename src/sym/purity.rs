@@ -0,0 +1,71 @@
+use crate::ast::*;
+use crate::Result;
+
+/// Returns true if evaluating the expression never fails.
+///
+/// Only pure expressions can be removed by optimizations, otherwise
+/// runtime errors such as integer division by zero would be hidden.
+pub fn is_pure(expr: &Expr) -> bool {
+    let mut fa = Fallible(false);
+    // visiting won't fail
+    fa.visit_expr(expr).unwrap();
+    !fa.0
+}
+
+struct Fallible(bool);
+
+impl ExprVisitor for Fallible {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            // vecmerger checks its length and indices
+            Expr::Lookup(_) | Expr::NewVecMerger(_) => self.0 = true,
+            Expr::BinOp(BinOp { op_ty, left, .. }) => {
+                self.0 |= is_fallible_op(op_ty, &left.ty());
+            }
+            // ranges and lengths of zipped vectors are checked by the loop
+            Expr::For(For { iters, .. }) => {
+                let mut vectors = 0;
+                for it in iters {
+                    if let Expr::Broadcast(_) = it.data.as_ref() {
+                        continue;
+                    }
+                    vectors += 1;
+                    self.0 |= it.start.is_some() || it.end.is_some();
+                }
+                self.0 |= vectors > 1;
+            }
+            Expr::NewMerger(NewMerger { item_ty, op_ty }) => {
+                self.0 |= op_ty.identity(item_ty).is_none();
+            }
+            Expr::Merge(Merge { builder, .. }) => match builder.ty() {
+                Type::VecMerger(_) => self.0 = true,
+                Type::Merger(MergerType { item_ty, op_ty })
+                | Type::DictMerger(DictMergerType {
+                    value_ty: item_ty,
+                    op_ty,
+                    ..
+                }) => self.0 |= is_fallible_op(&op_ty, &item_ty),
+                _ => (),
+            },
+            _ => (),
+        }
+        expr.traverse_children(self)
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Result<()> {
+        self.visit_expr(&lambda.body)
+    }
+}
+
+/// Returns true if the operator may fail on operands of given type.
+///
+/// Integer division fails on zero divisor, and shift fails on amount
+/// out of bit width.
+fn is_fallible_op(op_ty: &BinOpType, ty: &Type) -> bool {
+    match op_ty {
+        BinOpType::Divide | BinOpType::Modulo | BinOpType::ShiftLeft | BinOpType::ShiftRight => {
+            ty.is_integer()
+        }
+        _ => false,
+    }
+}
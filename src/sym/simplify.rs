@@ -1,14 +1,41 @@
-use super::Symbol;
+use super::{is_pure, Symbol};
 use crate::ast::*;
 use crate::Result;
 use std::collections::HashMap;
 
+/// Options of simplification.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimplifyOptions {
+    /// Apply algebraic rules on floats that ignore NaN, infinity
+    /// and signed zero, e.g. `x * 0.0` to `0.0`.
+    pub fast_math: bool,
+}
+
+/// Fold constant expressions, with literal values of given symbols,
+/// and rewrite expressions by algebraic rules.
+///
+/// Symbols bound by lambda or let are not replaced, even if they
+/// share the name and id with keys of the map.
 pub fn simplify(expr: &mut Expr, syms: &HashMap<Symbol, Literal>) -> Result<bool> {
-    Simplify::with_syms(syms).transform_expr(expr)
+    simplify_with_options(expr, syms, SimplifyOptions::default())
+}
+
+/// Simplify the expression with given options.
+pub fn simplify_with_options(
+    expr: &mut Expr,
+    syms: &HashMap<Symbol, Literal>,
+    opts: SimplifyOptions,
+) -> Result<bool> {
+    let mut s = Simplify::with_syms(syms);
+    s.opts = opts;
+    s.transform_expr(expr)
 }
 
 struct Simplify<'map> {
     syms: &'map HashMap<Symbol, Literal>,
+    // symbols bound in current scope
+    bound: Vec<Symbol>,
+    opts: SimplifyOptions,
 }
 
 impl<'map> ExprTransformer for Simplify<'map> {
@@ -26,41 +53,200 @@ impl<'map> ExprTransformer for Simplify<'map> {
                     r,
                 )? {
                     *expr = new_expr;
-                    return Ok(true);
+                    r = true;
                 }
-                Ok(r)
+                Ok(self.rewrite(expr)? || r)
             }
             Expr::UnaryOp(UnaryOp { op_ty, value }) => {
-                if self.transform_expr(value.as_mut())? {
+                let r = self.transform_expr(value.as_mut())?;
+                if r {
                     if let Some(new_expr) =
                         self.simplify_uo(op_ty, &ExprOrLit::Expr(value.as_ref()))?
                     {
                         *expr = new_expr;
                     }
-                    return Ok(true);
                 }
-                Ok(false)
+                Ok(self.rewrite(expr)? || r)
             }
             Expr::Symbol(sym) => {
-                if let Some(lit) = self.syms.get(sym) {
+                if let Some(lit) = self.lookup(sym) {
                     *expr = Expr::Literal(lit.clone());
                     return Ok(true);
                 }
                 Ok(false)
             }
             Expr::Literal(_) => Ok(false),
-            _ => todo!(),
+            Expr::Cast(Cast { ty, value }) => {
+                let r = self.transform_expr(value.as_mut())?;
+                if let Some(lit) = value.as_lit() {
+                    *expr = Expr::Literal(lit.cast(ty)?);
+                    return Ok(true);
+                }
+                Ok(r)
+            }
+            Expr::GetField(GetField { tuple, index }) => {
+                let r = self.transform_expr(tuple.as_mut())?;
+                if let Expr::Tuple(Tuple(items)) = tuple.as_mut() {
+                    if (*index as usize) < items.len() && items.iter().all(Expr::is_lit) {
+                        let item = items.swap_remove(*index as usize);
+                        *expr = item;
+                        return Ok(true);
+                    }
+                }
+                Ok(r)
+            }
+            Expr::Length(Length(value)) => {
+                let r = self.transform_expr(value.as_mut())?;
+                if let Expr::Vector(Vector { items, .. }) = value.as_ref() {
+                    if items.iter().all(Expr::is_lit) {
+                        *expr = Expr::Literal(Literal::U64(items.len() as u64));
+                        return Ok(true);
+                    }
+                }
+                Ok(r)
+            }
+            Expr::IfThenElse(IfThenElse { i, t, e }) => {
+                let r = self.transform_expr(i.as_mut())?;
+                let branch = match i.as_lit() {
+                    Some(Literal::Bool(true)) => t,
+                    Some(Literal::Bool(false)) => e,
+                    _ => {
+                        let mut r = r;
+                        r |= self.transform_expr(t.as_mut())?;
+                        r |= self.transform_expr(e.as_mut())?;
+                        return Ok(r);
+                    }
+                };
+                let mut branch =
+                    std::mem::replace(branch.as_mut(), Expr::Literal(Literal::Bool(false)));
+                self.transform_expr(&mut branch)?;
+                *expr = branch;
+                Ok(true)
+            }
+            Expr::Let(Let { sym, value, body }) => {
+                let mut r = self.transform_expr(value.as_mut())?;
+                self.bound.push(sym.clone());
+                r |= self.transform_expr(body.as_mut())?;
+                self.bound.pop();
+                Ok(r)
+            }
+            other => other.apply_children(self),
         }
     }
 
     fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
-        self.transform_expr(lambda.body.as_mut())
+        let depth = self.bound.len();
+        self.bound.extend(lambda.params.iter().cloned());
+        let r = self.transform_expr(lambda.body.as_mut());
+        self.bound.truncate(depth);
+        r
     }
 }
 
 impl<'map> Simplify<'map> {
     pub fn with_syms(syms: &'map HashMap<Symbol, Literal>) -> Self {
-        Simplify { syms }
+        Simplify {
+            syms,
+            bound: vec![],
+            opts: SimplifyOptions::default(),
+        }
+    }
+
+    /// Rewrite the expression by algebraic rules until none applies.
+    fn rewrite(&self, expr: &mut Expr) -> Result<bool> {
+        let mut r = false;
+        loop {
+            let new_expr = match expr {
+                Expr::BinOp(bo) => self.rewrite_bo(bo)?,
+                Expr::UnaryOp(uo) => self.rewrite_uo(uo),
+                _ => None,
+            };
+            match new_expr {
+                Some(new_expr) => {
+                    *expr = new_expr;
+                    r = true;
+                }
+                None => return Ok(r),
+            }
+        }
+    }
+
+    fn rewrite_bo(&self, bo: &BinOp) -> Result<Option<Expr>> {
+        let BinOp { op_ty, left, right } = bo;
+        let ty = left.ty();
+        if !ty.is_numeric() {
+            return Ok(None);
+        }
+        // rules which do not hold on NaN, infinity or signed zero
+        let exact = ty.is_integer() || self.opts.fast_math;
+        let (l, r) = (left.as_lit(), right.as_lit());
+        let res = match op_ty {
+            BinOpType::Add if exact && is_zero(r) => left.as_ref().clone(),
+            BinOpType::Add if exact && is_zero(l) => right.as_ref().clone(),
+            // -0.0 - -0.0 is +0.0, only positive zero holds on floats
+            BinOpType::Subtract if is_pos_zero(r) || (exact && is_zero(r)) => left.as_ref().clone(),
+            // operands that may fail are kept, so are their errors
+            BinOpType::Subtract if exact && left == right && is_pure(left) => {
+                Expr::Literal(zero(&ty)?)
+            }
+            BinOpType::Multiply | BinOpType::Divide if is_one(r) => left.as_ref().clone(),
+            BinOpType::Multiply if is_one(l) => right.as_ref().clone(),
+            BinOpType::Multiply
+                if exact && ((is_zero(l) && is_pure(right)) || (is_zero(r) && is_pure(left))) =>
+            {
+                Expr::Literal(zero(&ty)?)
+            }
+            BinOpType::Multiply if ty.is_integer() => {
+                // strength reduction of multiplication by power of two
+                let (value, shift) = match (log2(r), log2(l)) {
+                    (Some(shift), _) => (left, shift),
+                    (None, Some(shift)) => (right, shift),
+                    _ => return Ok(None),
+                };
+                Expr::BinOp(BinOp {
+                    op_ty: BinOpType::ShiftLeft,
+                    left: value.clone(),
+                    right: Box::new(Expr::Literal(Literal::U32(shift).cast(&ty)?)),
+                })
+            }
+            BinOpType::Max | BinOpType::Min if left == right => left.as_ref().clone(),
+            _ => return Ok(None),
+        };
+        Ok(Some(res))
+    }
+
+    fn rewrite_uo(&self, uo: &UnaryOp) -> Option<Expr> {
+        match (uo.op_ty, uo.value.as_ref()) {
+            (UnaryOpType::Not, Expr::UnaryOp(UnaryOp { op_ty, value }))
+            | (UnaryOpType::Neg, Expr::UnaryOp(UnaryOp { op_ty, value }))
+                if *op_ty == uo.op_ty =>
+            {
+                Some(value.as_ref().clone())
+            }
+            (UnaryOpType::Not, Expr::BinOp(BinOp { op_ty, left, right })) => {
+                // comparisons on NaN are always false, so never negated
+                if left.ty().is_float() && !self.opts.fast_math {
+                    return None;
+                }
+                negate_cmp(*op_ty).map(|op_ty| {
+                    Expr::BinOp(BinOp {
+                        op_ty,
+                        left: left.clone(),
+                        right: right.clone(),
+                    })
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns literal value of the symbol, if it is not bound in current scope.
+    #[inline]
+    fn lookup(&self, sym: &Symbol) -> Option<&'map Literal> {
+        if self.bound.contains(sym) {
+            return None;
+        }
+        self.syms.get(sym)
     }
 
     #[inline]
@@ -72,16 +258,18 @@ impl<'map> Simplify<'map> {
         recreate: bool,
     ) -> Result<Option<Expr>> {
         if let (Some(lit0), Some(lit1)) = (left.as_lit(), right.as_lit()) {
-            let r = lit0.apply_bin_op(lit1, op_ty)?;
-            return Ok(Some(Expr::Literal(r)));
+            // errors, e.g. division by zero, are left to run time
+            if let Ok(r) = lit0.apply_bin_op(lit1, op_ty) {
+                return Ok(Some(Expr::Literal(r)));
+            }
         }
         if let Some(sym0) = left.as_symbol() {
-            if let Some(lit0) = self.syms.get(sym0) {
+            if let Some(lit0) = self.lookup(sym0) {
                 return self.simplify_bo(op_ty, &ExprOrLit::Lit(lit0), right, true);
             }
         }
         if let Some(sym1) = right.as_symbol() {
-            if let Some(lit1) = self.syms.get(sym1) {
+            if let Some(lit1) = self.lookup(sym1) {
                 return self.simplify_bo(op_ty, left, &ExprOrLit::Lit(lit1), true);
             }
         }
@@ -98,11 +286,10 @@ impl<'map> Simplify<'map> {
     #[inline]
     fn simplify_uo(&self, op_ty: &UnaryOpType, expr: &ExprOrLit) -> Result<Option<Expr>> {
         if let Some(lit) = expr.as_lit() {
-            let r = lit.apply_unary_op(op_ty)?;
-            return Ok(Some(Expr::Literal(r)));
+            return Ok(lit.apply_unary_op(op_ty).ok().map(Expr::Literal));
         }
         if let Some(sym) = expr.as_symbol() {
-            if let Some(lit) = self.syms.get(sym) {
+            if let Some(lit) = self.lookup(sym) {
                 return self.simplify_uo(op_ty, &ExprOrLit::Lit(lit));
             }
         }
@@ -110,6 +297,72 @@ impl<'map> Simplify<'map> {
     }
 }
 
+/// Returns the comparison which is the negation of given one.
+fn negate_cmp(op_ty: BinOpType) -> Option<BinOpType> {
+    let r = match op_ty {
+        BinOpType::Equal => BinOpType::NotEqual,
+        BinOpType::NotEqual => BinOpType::Equal,
+        BinOpType::LessThan => BinOpType::GreaterThanOrEqual,
+        BinOpType::LessThanOrEqual => BinOpType::GreaterThan,
+        BinOpType::GreaterThan => BinOpType::LessThanOrEqual,
+        BinOpType::GreaterThanOrEqual => BinOpType::LessThan,
+        _ => return None,
+    };
+    Some(r)
+}
+
+fn zero(ty: &Type) -> Result<Literal> {
+    Literal::U8(0).cast(ty)
+}
+
+/// Returns true if the literal is integer zero, or float zero of either sign.
+fn is_zero(lit: Option<&Literal>) -> bool {
+    match lit {
+        Some(Literal::F32(v)) => f32::from_bits(*v) == 0.0,
+        Some(Literal::F64(v)) => f64::from_bits(*v) == 0.0,
+        Some(lit) => int_value(lit) == Some(0),
+        None => false,
+    }
+}
+
+/// Returns true if the literal is zero, excluding negative zero of floats.
+fn is_pos_zero(lit: Option<&Literal>) -> bool {
+    match lit {
+        Some(Literal::F32(v)) => *v == 0,
+        Some(Literal::F64(v)) => *v == 0,
+        lit => is_zero(lit),
+    }
+}
+
+fn is_one(lit: Option<&Literal>) -> bool {
+    match lit {
+        Some(Literal::F32(v)) => f32::from_bits(*v) == 1.0,
+        Some(Literal::F64(v)) => f64::from_bits(*v) == 1.0,
+        Some(lit) => int_value(lit) == Some(1),
+        None => false,
+    }
+}
+
+/// Returns the exponent if the literal is an integer power of two, greater than one.
+fn log2(lit: Option<&Literal>) -> Option<u32> {
+    match lit.and_then(int_value) {
+        Some(v) if v > 1 && v & (v - 1) == 0 => Some(v.trailing_zeros()),
+        _ => None,
+    }
+}
+
+fn int_value(lit: &Literal) -> Option<i128> {
+    let v = match lit {
+        Literal::U8(v) => *v as i128,
+        Literal::U32(v) => *v as i128,
+        Literal::I32(v) => *v as i128,
+        Literal::U64(v) => *v as i128,
+        Literal::I64(v) => *v as i128,
+        _ => return None,
+    };
+    Some(v)
+}
+
 /// helper struct to avoid copy
 enum ExprOrLit<'a> {
     Expr(&'a Expr),
@@ -178,4 +431,209 @@ mod tests {
         simplify(&mut v2.expr, &syms).unwrap();
         assert_eq!(-1, v2.expr.as_lit().unwrap().as_i32().unwrap());
     }
+
+    #[test]
+    fn test_simplify_scoped() -> Result<()> {
+        let a = Symbol::named("a", I32);
+        let mut syms = HashMap::new();
+        syms.insert(a.clone(), Literal::I32(2));
+
+        // lambda parameter shadows the symbol
+        let av = Var::<I32>::clone_symbol(a.clone());
+        let mut expr = Expr::Lambda(Lambda {
+            params: vec![a.clone()],
+            body: Box::new((av.clone() + 1).expr),
+        });
+        let orig = expr.clone();
+        assert!(!simplify(&mut expr, &syms)?);
+        assert_eq!(orig, expr);
+
+        // let body shadows the symbol, but not its value
+        let lt = Let {
+            sym: a.clone(),
+            value: Box::new((av.clone() * 3).expr),
+            body: Box::new((av.clone() + 1).expr),
+        };
+        let mut expr = Expr::Let(lt);
+        assert!(simplify(&mut expr, &syms)?);
+        match &expr {
+            Expr::Let(Let { value, body, .. }) => {
+                assert_eq!(&Expr::from(6), value.as_ref());
+                assert_eq!((av.clone() + 1).expr, *body.as_ref());
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        // for loop is traversed with parameters in scope
+        let v = Var::new_vector(vec![1, 2, 3]);
+        let m = Var::new_merger(I32, BinOpType::Add)
+            .pfor(v, move |b, _i, e: Var<I32>| b.merge(e + av))
+            .eval();
+        let mut expr = m.expr;
+        assert!(simplify(&mut expr, &syms)?);
+        assert!(!crate::sym::extract(&expr).contains(&a));
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify_fold_exprs() -> Result<()> {
+        let syms = HashMap::new();
+        let cast = Expr::Cast(Cast {
+            ty: F64.into(),
+            value: Box::new(3.into()),
+        });
+        let tuple = Var::new_tuple((Var::<F64>::new(cast), true));
+        let cond = tuple.get::<1>();
+        let len = Expr::Length(Length(Box::new(Var::new_vector(vec![1, 2, 3]).expr)));
+        let mut expr = Expr::IfThenElse(IfThenElse {
+            i: Box::new(cond.expr),
+            t: Box::new(Expr::Tuple(Tuple(vec![tuple.get::<0>().expr, len]))),
+            e: Box::new(Expr::Tuple(Tuple(vec![
+                Literal::from(0.0f64).into(),
+                0u64.into(),
+            ]))),
+        });
+        assert!(simplify(&mut expr, &syms)?);
+        let expected = Expr::Tuple(Tuple(vec![Literal::from(3.0f64).into(), 3u64.into()]));
+        assert_eq!(expected, expr);
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify_division_by_zero() -> Result<()> {
+        let a = Symbol::named("a", I32);
+        let mut syms = HashMap::new();
+        syms.insert(a.clone(), Literal::I32(0));
+
+        // division by zero is left unfolded to fail at run time
+        let div = Expr::BinOp(BinOp::div(7.into(), 0.into()));
+        let mut expr = div.clone();
+        assert!(!simplify(&mut expr, &HashMap::new())?);
+        assert_eq!(div, expr);
+        let mut expr = Expr::BinOp(BinOp::rem(7.into(), Expr::Symbol(a)));
+        assert!(simplify(&mut expr, &syms)?);
+        assert_eq!(Expr::BinOp(BinOp::rem(7.into(), 0.into())), expr);
+        let mut expr = div.clone();
+        crate::opt::PassManager::default().run(&mut expr)?;
+        assert_eq!(div, expr);
+        // and never fails in a branch not taken
+        let mut expr = Expr::IfThenElse(IfThenElse {
+            i: Box::new(false.into()),
+            t: Box::new(div),
+            e: Box::new(1.into()),
+        });
+        assert!(simplify(&mut expr, &HashMap::new())?);
+        assert_eq!(Expr::from(1), expr);
+
+        // minimum divided by -1 wraps
+        let mut expr = Expr::BinOp(BinOp::div(i32::MIN.into(), (-1).into()));
+        assert!(simplify(&mut expr, &HashMap::new())?);
+        assert_eq!(Expr::from(i32::MIN), expr);
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify_algebraic() -> Result<()> {
+        let syms = HashMap::new();
+        let x = Var::<I32>::new_symbol("x", I32);
+        let check = |expr: Expr, expected: Expr| -> Result<()> {
+            let mut expr = expr;
+            assert!(simplify(&mut expr, &syms)?);
+            assert_eq!(expected, expr);
+            Ok(())
+        };
+        let bo = |op_ty, left: Expr, right: Expr| {
+            Expr::BinOp(BinOp {
+                op_ty,
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+        };
+        let uo = |op_ty, value: Expr| {
+            Expr::UnaryOp(UnaryOp {
+                op_ty,
+                value: Box::new(value),
+            })
+        };
+        let xe = x.expr.clone();
+        check(bo(BinOpType::Add, xe.clone(), 0.into()), xe.clone())?;
+        check(bo(BinOpType::Multiply, 1.into(), xe.clone()), xe.clone())?;
+        check(bo(BinOpType::Multiply, xe.clone(), 0.into()), 0.into())?;
+        check(bo(BinOpType::Subtract, xe.clone(), xe.clone()), 0.into())?;
+        check(bo(BinOpType::Max, xe.clone(), xe.clone()), xe.clone())?;
+        check(
+            bo(BinOpType::Multiply, xe.clone(), 8.into()),
+            bo(BinOpType::ShiftLeft, xe.clone(), 3.into()),
+        )?;
+        let lt = bo(BinOpType::LessThan, xe.clone(), 1.into());
+        check(
+            uo(UnaryOpType::Not, uo(UnaryOpType::Not, lt.clone())),
+            lt.clone(),
+        )?;
+        check(
+            uo(UnaryOpType::Not, lt),
+            bo(BinOpType::GreaterThanOrEqual, xe.clone(), 1.into()),
+        )?;
+        check(bo(BinOpType::ShiftLeft, 1.into(), 3.into()), 8.into())?;
+        let shr = bo(BinOpType::ShiftRight, 1.into(), 32.into());
+        let mut expr = shr.clone();
+        assert!(!simplify(&mut expr, &syms)?);
+        assert_eq!(shr, expr);
+        // operands which may fail are not dropped
+        let y = Var::<I32>::new_symbol("y", I32);
+        let shl = bo(BinOpType::ShiftLeft, xe.clone(), y.expr.clone());
+        for orig in [
+            bo(BinOpType::Multiply, (x.clone() / y.clone()).expr, 0.into()),
+            bo(BinOpType::Multiply, 0.into(), (x.clone() % y).expr),
+            bo(BinOpType::Subtract, shl.clone(), shl),
+        ] {
+            let mut expr = orig.clone();
+            assert!(!simplify(&mut expr, &syms)?);
+            assert_eq!(orig, expr);
+        }
+        // nested rules apply bottom-up
+        check(
+            bo(
+                BinOpType::Add,
+                bo(BinOpType::Subtract, xe.clone(), xe.clone()),
+                xe.clone(),
+            ),
+            xe,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify_float_semantics() -> Result<()> {
+        let syms = HashMap::new();
+        let y = Var::<F64>::new_symbol("y", F64);
+        let zero: Expr = Literal::from(0.0f64).into();
+        let mul = Expr::BinOp(BinOp::mul(y.expr.clone(), zero.clone()));
+        let sub = Expr::BinOp(BinOp::sub(y.expr.clone(), y.expr.clone()));
+        let one = Expr::BinOp(BinOp::mul(y.expr.clone(), Literal::from(1.0f64).into()));
+        let sub_zero = Expr::BinOp(BinOp::sub(y.expr.clone(), zero.clone()));
+        let sub_neg_zero = Expr::BinOp(BinOp::sub(y.expr.clone(), Literal::from(-0.0f64).into()));
+
+        for orig in [mul.clone(), sub.clone(), sub_neg_zero.clone()] {
+            let mut expr = orig.clone();
+            assert!(!simplify(&mut expr, &syms)?);
+            assert_eq!(orig, expr);
+        }
+        for orig in [one, sub_zero] {
+            let mut expr = orig;
+            assert!(simplify(&mut expr, &syms)?);
+            assert_eq!(y.expr, expr);
+        }
+
+        let opts = SimplifyOptions { fast_math: true };
+        for orig in [mul, sub] {
+            let mut expr = orig;
+            assert!(simplify_with_options(&mut expr, &syms, opts)?);
+            assert_eq!(zero, expr);
+        }
+        let mut expr = sub_neg_zero;
+        assert!(simplify_with_options(&mut expr, &syms, opts)?);
+        assert_eq!(y.expr, expr);
+        Ok(())
+    }
 }
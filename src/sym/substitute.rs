@@ -0,0 +1,77 @@
+use super::Symbol;
+use crate::ast::{Expr, ExprTransformer, Lambda, Let};
+use crate::Result;
+use std::collections::HashMap;
+
+/// Substitute free symbols in the expression with given expressions.
+///
+/// Symbols bound by inner lambdas are kept as is.
+pub fn substitute(expr: &mut Expr, map: &HashMap<Symbol, Expr>) -> Result<bool> {
+    let mut sub = Substitute { map, bound: vec![] };
+    sub.transform_expr(expr)
+}
+
+struct Substitute<'a> {
+    map: &'a HashMap<Symbol, Expr>,
+    bound: Vec<Symbol>,
+}
+
+impl ExprTransformer for Substitute<'_> {
+    fn transform_expr(&mut self, expr: &mut Expr) -> Result<bool> {
+        if let Expr::Symbol(sym) = expr {
+            if self.bound.contains(sym) {
+                return Ok(false);
+            }
+            return match self.map.get(sym) {
+                Some(e) => {
+                    *expr = e.clone();
+                    Ok(true)
+                }
+                None => Ok(false),
+            };
+        }
+        if let Expr::Let(Let { sym, value, body }) = expr {
+            let mut r = self.transform_expr(value.as_mut())?;
+            self.bound.push(sym.clone());
+            r |= self.transform_expr(body.as_mut())?;
+            self.bound.pop();
+            return Ok(r);
+        }
+        expr.apply_children(self)
+    }
+
+    fn transform_lambda(&mut self, lambda: &mut Lambda) -> Result<bool> {
+        let depth = self.bound.len();
+        self.bound.extend(lambda.params.iter().cloned());
+        let r = self.transform_expr(lambda.body.as_mut());
+        self.bound.truncate(depth);
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+
+    #[test]
+    fn test_substitute() {
+        let a = Symbol::named("a", I32);
+        let b = Var::new_symbol("b", I32);
+        let mut expr = (Var::<I32>::clone_symbol(a.clone()) + b.clone()).expr;
+        let mut map = HashMap::new();
+        map.insert(a.clone(), Expr::from(3));
+        assert!(substitute(&mut expr, &map).unwrap());
+        assert_eq!(expr, (Var::lit_i32(3) + b).expr);
+        assert!(!substitute(&mut expr, &map).unwrap());
+
+        // symbol bound in lambda is not substituted
+        let lambda = Lambda {
+            params: vec![a.clone()],
+            body: Box::new(Expr::Symbol(a)),
+        };
+        let mut expr = Expr::Lambda(lambda.clone());
+        assert!(!substitute(&mut expr, &map).unwrap());
+        assert_eq!(expr, Expr::Lambda(lambda));
+    }
+}
@@ -0,0 +1,468 @@
+use super::Symbol;
+use crate::ast::*;
+use crate::stage::Program;
+use crate::Result;
+
+/// Check types of the whole expression tree and returns the type of root.
+///
+/// Different from TypeInference, which only infers the type on best effort,
+/// the check validates every subexpression and resolves each symbol against
+/// the lambda scopes enclosing it.
+/// Any free symbol is treated as undefined.
+pub fn typecheck(expr: &Expr) -> Result<Type> {
+    TypeChecker::new().check(expr)
+}
+
+/// Type check the program and returns type of its body.
+///
+/// Parameters are defined in scope of the body.
+pub fn typecheck_program(program: &Program) -> Result<Type> {
+    let mut tc = TypeChecker {
+        scope: program.params.clone(),
+    };
+    tc.check(&program.body)
+}
+
+struct TypeChecker {
+    scope: Vec<Symbol>,
+}
+
+impl TypeChecker {
+    fn new() -> Self {
+        TypeChecker { scope: vec![] }
+    }
+
+    fn check(&mut self, expr: &Expr) -> Result<Type> {
+        let ty = match expr {
+            Expr::Literal(lit) => lit.ty(),
+            Expr::Symbol(sym) => self.resolve(sym)?,
+            Expr::Broadcast(Broadcast { value }) => {
+                let value_ty = self.check(value)?;
+                if !value_ty.is_scalar() {
+                    return Err(compile_err!("non-scalar type[{}] in {}", value_ty, expr));
+                }
+                Type::Vector(VectorType {
+                    item_ty: Box::new(value_ty),
+                })
+            }
+            Expr::BinOp(BinOp { op_ty, left, right }) => {
+                let left_ty = self.check(left)?;
+                let right_ty = self.check(right)?;
+                bin_op_type(op_ty, &left_ty, &right_ty).ok_or_else(|| {
+                    compile_err!(
+                        "incompatible types [{} and {}] in {}",
+                        left_ty,
+                        right_ty,
+                        expr
+                    )
+                })?
+            }
+            Expr::UnaryOp(UnaryOp { op_ty, value }) => {
+                let value_ty = self.check(value)?;
+                unary_op_type(op_ty, &value_ty).ok_or_else(|| {
+                    if op_ty.is_math() {
+                        compile_err!(
+                            "{} is only defined on floats, found type[{}] in {}",
+                            op_ty,
+                            value_ty,
+                            expr
+                        )
+                    } else {
+                        compile_err!("incompatible type[{}] in {}", value_ty, expr)
+                    }
+                })?
+            }
+            Expr::Cast(Cast { ty, value }) => {
+                let value_ty = self.check(value)?;
+                if !is_castable(&value_ty) || !is_castable(ty) {
+                    return Err(compile_err!(
+                        "invalid cast from type[{}] to type[{}] in {}",
+                        value_ty,
+                        ty,
+                        expr
+                    ));
+                }
+                ty.clone()
+            }
+            Expr::GetField(GetField { tuple, index }) => match self.check(tuple)? {
+                Type::Tuple(TupleType(mut tys)) if (*index as usize) < tys.len() => {
+                    tys.swap_remove(*index as usize)
+                }
+                other => {
+                    return Err(compile_err!(
+                        "invalid field access on type[{}] in {}",
+                        other,
+                        expr
+                    ))
+                }
+            },
+            Expr::Length(Length(value)) => {
+                let value_ty = self.check(value)?;
+                if !value_ty.is_vector() {
+                    return Err(compile_err!("non-vector type[{}] in {}", value_ty, expr));
+                }
+                Type::U64(U64)
+            }
+            Expr::Lookup(Lookup { dict, index }) => {
+                let dict_ty = self.check(dict)?;
+                let index_ty = self.check(index)?;
+                match dict_ty {
+                    Type::Dict(DictType { key_ty, value_ty }) if *key_ty == index_ty => *value_ty,
+                    other => {
+                        return Err(compile_err!(
+                            "incompatible types [{} and {}] in {}",
+                            other,
+                            index_ty,
+                            expr
+                        ))
+                    }
+                }
+            }
+            Expr::IfThenElse(IfThenElse { i, t, e }) => {
+                let cond_ty = self.check(i)?;
+                if !cond_ty.is_bool() {
+                    return Err(compile_err!(
+                        "non-bool condition type[{}] in {}",
+                        cond_ty,
+                        expr
+                    ));
+                }
+                let then_ty = self.check(t)?;
+                let else_ty = self.check(e)?;
+                if then_ty != else_ty {
+                    return Err(compile_err!(
+                        "incompatible branch types [{} and {}] in {}",
+                        then_ty,
+                        else_ty,
+                        expr
+                    ));
+                }
+                then_ty
+            }
+            Expr::Let(Let { sym, value, body }) => {
+                let value_ty = self.check(value)?;
+                if value_ty != sym.ty {
+                    return Err(compile_err!(
+                        "incompatible types [{} and {}] of symbol {} in {}",
+                        sym.ty,
+                        value_ty,
+                        sym,
+                        expr
+                    ));
+                }
+                self.scope.push(sym.clone());
+                let r = self.check(body);
+                self.scope.pop();
+                r?
+            }
+            Expr::For(fr) => self.check_for(fr, expr)?,
+            Expr::Merge(Merge { builder, value }) => {
+                let builder_ty = self.check(builder)?;
+                let value_ty = self.check(value)?;
+                match builder_ty.try_merge() {
+                    Some(merge_ty) if merge_ty == value_ty => builder_ty,
+                    _ => {
+                        return Err(compile_err!(
+                            "incompatible types [{} and {}] in {}",
+                            builder_ty,
+                            value_ty,
+                            expr
+                        ))
+                    }
+                }
+            }
+            Expr::Lambda(lambda) => {
+                let ret_ty = self.check_lambda(lambda)?;
+                Type::Lambda(LambdaType {
+                    args_ty: lambda.params.iter().map(|p| p.ty.clone()).collect(),
+                    ret_ty: Box::new(ret_ty),
+                })
+            }
+            Expr::Vector(Vector { item_ty, items }) => {
+                for item in items {
+                    let ty = self.check(item)?;
+                    if &ty != item_ty {
+                        return Err(compile_err!(
+                            "incompatible types [{} and {}] in {}",
+                            item_ty,
+                            ty,
+                            expr
+                        ));
+                    }
+                }
+                Type::Vector(VectorType {
+                    item_ty: Box::new(item_ty.clone()),
+                })
+            }
+            Expr::Tuple(Tuple(items)) => {
+                let mut tys = Vec::with_capacity(items.len());
+                for item in items {
+                    tys.push(self.check(item)?);
+                }
+                Type::Tuple(TupleType(tys))
+            }
+            Expr::NewVecMerger(NewVecMerger {
+                item_ty,
+                op_ty,
+                len,
+            }) => {
+                let len_ty = self.check(len)?;
+                if len_ty != Type::U64(U64) {
+                    return Err(compile_err!("non-u64 length type[{}] in {}", len_ty, expr));
+                }
+                if bin_op_type(op_ty, item_ty, item_ty).as_ref() != Some(item_ty) {
+                    return Err(compile_err!(
+                        "invalid operator {} on type[{}] in {}",
+                        op_ty,
+                        item_ty,
+                        expr
+                    ));
+                }
+                expr.ty()
+            }
+            Expr::NewMerger(NewMerger { item_ty, op_ty })
+            | Expr::NewDictMerger(NewDictMerger {
+                value_ty: item_ty,
+                op_ty,
+                ..
+            }) => {
+                if bin_op_type(op_ty, item_ty, item_ty).as_ref() != Some(item_ty) {
+                    return Err(compile_err!(
+                        "invalid operator {} on type[{}] in {}",
+                        op_ty,
+                        item_ty,
+                        expr
+                    ));
+                }
+                expr.ty()
+            }
+            Expr::Dict(_) | Expr::NewAppender(_) | Expr::NewGroupMerger(_) => expr.ty(),
+            Expr::Eval(Eval(value)) => {
+                let builder_ty = self.check(value)?;
+                builder_ty
+                    .try_eval()
+                    .ok_or_else(|| compile_err!("non-builder type[{}] in {}", builder_ty, expr))?
+            }
+        };
+        Ok(ty)
+    }
+
+    fn check_for(&mut self, fr: &For, expr: &Expr) -> Result<Type> {
+        if fr.iters.is_empty() {
+            return Err(compile_err!("no iterator in {}", expr));
+        }
+        let mut elem_tys = Vec::with_capacity(fr.iters.len());
+        for it in &fr.iters {
+            match self.check(&it.data)? {
+                Type::Vector(VectorType { item_ty }) => elem_tys.push(*item_ty),
+                other => {
+                    return Err(compile_err!(
+                        "non-vector type[{}] of iterator {} in {}",
+                        other,
+                        it,
+                        expr
+                    ))
+                }
+            }
+            for bound in it.start.iter().chain(it.end.iter()) {
+                let bound_ty = self.check(bound)?;
+                if !bound_ty.is_u64() {
+                    return Err(compile_err!(
+                        "non-u64 type[{}] of iterator bound {} in {}",
+                        bound_ty,
+                        bound,
+                        expr
+                    ));
+                }
+            }
+        }
+        let elem_ty = if elem_tys.len() == 1 {
+            elem_tys.pop().unwrap()
+        } else {
+            Type::Tuple(TupleType(elem_tys))
+        };
+        let builder_ty = self.check(&fr.builder)?;
+        if !builder_ty.is_builder() {
+            return Err(compile_err!("non-builder type[{}] in {}", builder_ty, expr));
+        }
+        let lambda = match fr.func.as_ref() {
+            Expr::Lambda(lambda) => lambda,
+            other => return Err(compile_err!("non-lambda function {} in {}", other, expr)),
+        };
+        let mut params_ty = vec![builder_ty.clone(), Type::U64(U64), elem_ty];
+        if let Some(env) = &fr.env {
+            params_ty.push(self.check(env)?);
+        }
+        if lambda.params.len() != params_ty.len()
+            || lambda
+                .params
+                .iter()
+                .zip(params_ty.iter())
+                .any(|(p, ty)| &p.ty != ty)
+        {
+            let expected: Vec<_> = params_ty.iter().map(|ty| ty.to_string()).collect();
+            return Err(compile_err!(
+                "incompatible parameters of function {}, expected ({}) in {}",
+                fr.func,
+                expected.join(", "),
+                expr
+            ));
+        }
+        let ret_ty = if fr.env.is_some() {
+            // function with environment is closed
+            let outer = std::mem::take(&mut self.scope);
+            let r = self.check_lambda(lambda);
+            self.scope = outer;
+            r?
+        } else {
+            self.check_lambda(lambda)?
+        };
+        if ret_ty != builder_ty {
+            return Err(compile_err!(
+                "incompatible types [{} and {}] of builder and function {} in {}",
+                builder_ty,
+                ret_ty,
+                fr.func,
+                expr
+            ));
+        }
+        Ok(builder_ty)
+    }
+
+    /// Check the lambda body with its parameters in scope, returns type of body.
+    fn check_lambda(&mut self, lambda: &Lambda) -> Result<Type> {
+        let depth = self.scope.len();
+        self.scope.extend(lambda.params.iter().cloned());
+        let r = self.check(&lambda.body);
+        self.scope.truncate(depth);
+        r
+    }
+
+    /// Resolve the symbol in the innermost scope that defines it.
+    fn resolve(&self, sym: &Symbol) -> Result<Type> {
+        let decl = self
+            .scope
+            .iter()
+            .rev()
+            .find(|s| s.name == sym.name && s.id == sym.id)
+            .ok_or_else(|| compile_err!("Undefined symbol {}", sym))?;
+        if decl.ty != sym.ty {
+            return Err(compile_err!(
+                "Symbol {} defined as type[{}] but used as type[{}]",
+                sym,
+                decl.ty,
+                sym.ty
+            ));
+        }
+        Ok(decl.ty.clone())
+    }
+}
+
+/// Returns result type of binary operation, or None if the operand types are invalid.
+fn bin_op_type(op_ty: &BinOpType, left: &Type, right: &Type) -> Option<Type> {
+    if left != right {
+        return None;
+    }
+    let valid = match op_ty {
+        BinOpType::Add
+        | BinOpType::Subtract
+        | BinOpType::Multiply
+        | BinOpType::Divide
+        | BinOpType::Modulo
+        | BinOpType::Max
+        | BinOpType::Min => left.is_numeric(),
+        BinOpType::Equal
+        | BinOpType::NotEqual
+        | BinOpType::LessThan
+        | BinOpType::LessThanOrEqual
+        | BinOpType::GreaterThan
+        | BinOpType::GreaterThanOrEqual => left.is_scalar(),
+        BinOpType::LogicalAnd | BinOpType::LogicalOr => left.is_bool(),
+        BinOpType::BitwiseAnd
+        | BinOpType::BitwiseOr
+        | BinOpType::Xor
+        | BinOpType::ShiftLeft
+        | BinOpType::ShiftRight => left.is_integer(),
+        BinOpType::Pow | BinOpType::Atan2 => left.is_float(),
+    };
+    if !valid {
+        return None;
+    }
+    let ty = match op_ty {
+        BinOpType::Equal
+        | BinOpType::NotEqual
+        | BinOpType::LessThan
+        | BinOpType::LessThanOrEqual
+        | BinOpType::GreaterThan
+        | BinOpType::GreaterThanOrEqual => Type::Bool(Bool),
+        _ => left.clone(),
+    };
+    Some(ty)
+}
+
+/// Returns result type of unary operation, or None if the operand type is invalid.
+fn unary_op_type(op_ty: &UnaryOpType, value: &Type) -> Option<Type> {
+    let valid = match op_ty {
+        UnaryOpType::Not => value.is_bool(),
+        UnaryOpType::Neg => value.is_signed(),
+        UnaryOpType::Abs => value.is_numeric(),
+        _ => value.is_float(),
+    };
+    if valid {
+        Some(value.clone())
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn is_castable(ty: &Type) -> bool {
+    ty.is_numeric() || ty.is_bool()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_typecheck_pfor() {
+        let v1 = Var::new_vector(vec![1, 2, 3]);
+        let m1 = Var::new_merger(I32, BinOpType::Add);
+        let m2 = m1.pfor(v1, |b, _i, e: Var<I32>| b.merge(e)).eval();
+        assert_eq!(Type::I32(I32), typecheck(&m2.expr).unwrap());
+    }
+
+    #[test]
+    fn test_typecheck_scoped_symbols() {
+        let t = Symbol::named("t", TupleType(vec![I32.into(), Bool.into()]));
+        let lambda = Lambda {
+            params: vec![t.clone()],
+            body: Box::new(Expr::GetField(GetField {
+                tuple: Box::new(Expr::Symbol(t.clone())),
+                index: 1,
+            })),
+        };
+        let ty = typecheck(&Expr::Lambda(lambda)).unwrap();
+        assert_eq!(Type::Bool(Bool), *ty.lambda().ret_ty);
+        // free symbol is not defined in any scope
+        assert!(typecheck(&Expr::Symbol(t)).is_err());
+    }
+
+    #[test]
+    fn test_typecheck_errors() {
+        let bo = Expr::BinOp(BinOp::add(1.into(), true.into()));
+        assert!(typecheck(&bo).is_err());
+        let uo = Expr::UnaryOp(UnaryOp {
+            op_ty: UnaryOpType::Sqrt,
+            value: Box::new(1.into()),
+        });
+        let err = typecheck(&uo).unwrap_err();
+        assert!(err.to_string().contains("only defined on floats"));
+        let gf = Expr::GetField(GetField {
+            tuple: Box::new(1.into()),
+            index: 0,
+        });
+        assert!(typecheck(&gf).is_err());
+    }
+}
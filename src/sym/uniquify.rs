@@ -1,13 +1,30 @@
 use super::Symbol;
-use crate::ast::{Expr, ExprTransformer, Lambda};
+use crate::ast::{Expr, ExprTransformer, Lambda, Let};
+use crate::stage::Program;
 use crate::Result;
 use std::collections::HashMap;
 
+/// Rename bound symbols so that each binding has a unique symbol.
+///
+/// Returns true if any symbol is renamed, an uniquified expression
+/// is kept as is.
 pub fn uniquify(expr: &mut Expr) -> Result<bool> {
     let mut su = Uniquifier::new();
     su.transform_expr(expr)
 }
 
+/// Uniquify the program, parameters are bound in the body.
+pub fn uniquify_program(program: &mut Program) -> Result<bool> {
+    let mut su = Uniquifier::new();
+    let mut changed = false;
+    for param in &mut program.params {
+        su.push(param);
+        changed |= su.rename(param)?;
+    }
+    changed |= su.transform_expr(&mut program.body)?;
+    Ok(changed)
+}
+
 struct Uniquifier {
     stack: HashMap<Symbol, Vec<u32>>,
     next_unique_id: HashMap<String, u32>,
@@ -18,8 +35,17 @@ impl ExprTransformer for Uniquifier {
         match expr {
             Expr::Symbol(sym) => {
                 // match and rename the symbol
-                *sym = self.get(sym)?;
-                Ok(true)
+                self.rename(sym)
+            }
+            Expr::Let(Let { sym, value, body }) => {
+                // value is out of scope of the symbol
+                let mut changed = self.transform_expr(value.as_mut())?;
+                let orig_sym = sym.clone();
+                self.push(&orig_sym);
+                changed |= self.rename(sym)?;
+                changed |= self.transform_expr(body.as_mut())?;
+                self.pop(&orig_sym)?;
+                Ok(changed)
             }
             other => other.apply_children(self),
         }
@@ -31,16 +57,17 @@ impl ExprTransformer for Uniquifier {
             ref mut body,
         } = lambda;
         let orig_params = params.clone();
+        let mut changed = false;
         for param in params {
             self.push(param);
-            *param = self.get(param)?;
+            changed |= self.rename(param)?;
         }
-        self.transform_expr(body.as_mut())?;
+        changed |= self.transform_expr(body.as_mut())?;
 
         for param in orig_params.iter().rev() {
             self.pop(param)?;
         }
-        Ok(true)
+        Ok(changed)
     }
 }
 
@@ -94,25 +121,86 @@ impl Uniquifier {
             .ok_or_else(|| compile_err!("Stack of symbol {} is empty", sym))?;
         Ok(Symbol::new(&sym.name, sym.ty.clone(), id))
     }
+
+    /// Rename the symbol to the one in current scope, returns true if
+    /// it is changed.
+    fn rename(&mut self, sym: &mut Symbol) -> Result<bool> {
+        let renamed = self.get(sym)?;
+        if renamed == *sym {
+            return Ok(false);
+        }
+        *sym = renamed;
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use crate::ast::*;
+    use crate::stage::Program;
+    use crate::sym::Symbol;
 
     #[test]
     fn test_uniquify() {
         let v2 = Var::new_merger(I32, BinOpType::Max);
         let v3 = Var::new_vector(vec![1, 2, 3]);
-        let v4 = v2
-            .pfor(v3.clone(), |b, _, e: Var<i32>| b.merge(e))
-            .eval(I32);
+        let v4 = v2.pfor(v3.clone(), |b, _, e: Var<I32>| b.merge(e)).eval();
         let v5 = Var::new_merger(I32, BinOpType::Min);
-        let v6 = v5.pfor(v3, |b, _, e: Var<i32>| b.merge(e)).eval(I32);
+        let v6 = v5.pfor(v3, |b, _, e: Var<I32>| b.merge(e)).eval();
         let mut v7: Expr = (v4 + v6).into();
         println!("{}", v7);
         super::uniquify(&mut v7).unwrap();
         println!("{}", v7);
     }
+
+    #[test]
+    fn test_uniquify_program() {
+        let x = Symbol::named("x", I32);
+        let xv = Var::<I32>::clone_symbol(x.clone());
+        let v1 = Var::new_merger(I32, BinOpType::Add);
+        let v2 = Var::new_vector(vec![1, 2, 3]);
+        let v3 = v1.pfor(v2, move |b, _, e: Var<I32>| b.merge(e + xv)).eval();
+        let mut expr = v3.expr.clone();
+        assert!(super::uniquify(&mut expr).is_err());
+        let mut program = Program::new(vec![x], v3);
+        assert!(super::uniquify_program(&mut program).unwrap());
+        println!("{}", program);
+        // already unique
+        assert!(!super::uniquify_program(&mut program).unwrap());
+    }
+
+    #[test]
+    fn test_uniquify_let() {
+        // value of inner let refers to the outer symbol it shadows
+        let sym = Symbol::named("v", I32);
+        let v = Var::<I32>::clone_symbol(sym.clone());
+        let inner = Expr::Let(Let {
+            sym: sym.clone(),
+            value: Box::new((v.clone() + 1).expr),
+            body: Box::new((v * 2).expr),
+        });
+        let mut expr = Expr::Let(Let {
+            sym,
+            value: Box::new(1.into()),
+            body: Box::new(inner),
+        });
+        assert!(super::uniquify(&mut expr).unwrap());
+        let (outer_sym, inner) = match &expr {
+            Expr::Let(Let { sym, body, .. }) => (sym, body.as_ref()),
+            other => panic!("unexpected {}", other),
+        };
+        match inner {
+            Expr::Let(Let { sym, value, .. }) => {
+                assert_ne!(outer_sym, sym);
+                assert!(crate::sym::extract(value).contains(outer_sym));
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        // symbols of staged lets are fresh already
+        let staged = Var::let_in(Var::lit_i32(1), |v| Var::let_in(v + 1, |w| w * 2));
+        let mut expr = staged.expr;
+        assert!(!super::uniquify(&mut expr).unwrap());
+    }
 }